@@ -2,11 +2,14 @@
 // TODO: implement module for writing and reading configuration files, perhaps use serde?
 use std::path::Path;
 use std::collections::HashMap;
-use crate::cmd::Command;
-use crate::editor::key::KeyCode;
+use crate::cmd::{Command, MoveDir, MoveKind, SeekFrom};
+use crate::editor::key::{KeyCode, EscapeKeyCode, Modifiers};
+use crate::data::word::WordChars;
+use crate::data::search::SearchOptions;
 use crate::editor::color::SetColor;
 use crate::editor::color::Color;
-use crate::cmd::command_engine::Operation;
+use crate::cmd::command_engine::{Operation, Macro, DEFAULT_GROUP_TIMEOUT_MS};
+use crate::clipboard::system::ClipboardProvider;
 use std::path::PathBuf;
 use crate::{Serialize, Deserialize};
 
@@ -23,39 +26,88 @@ use std::collections::hash_set;
 pub struct Config {
     file_name: PathBuf,
     key_bindings: HashMap<KeyCode, Command>,
+    /// Bindings for keys pressed with modifiers, like Ctrl+Right, and function keys. These can't be keys of a JSON
+    /// object, like the other bindings are, so they're kept as a list of pairs.
+    #[serde(default = "default_modified_key_bindings")]
+    modified_key_bindings: Vec<(KeyCode, Command)>,
     command_combo_key_bindings: HashMap<KeyCode, HashMap<KeyCode, Command>>,
     history_size: CfgSizeOptions,
     bg_color: SetColor,
     fg_color: SetColor,
-    stat_line_color: (SetColor, SetColor)
+    stat_line_color: (SetColor, SetColor),
+    #[serde(default = "default_undo_group_timeout")]
+    undo_group_timeout: u64,
+    #[serde(default)]
+    macros: HashMap<String, Macro>,
+    #[serde(default)]
+    system_clipboard: ClipboardProvider,
+    #[serde(default = "default_tab_width")]
+    tab_width: usize,
+    #[serde(default = "default_expand_tab")]
+    expand_tab: bool,
+    #[serde(default = "default_indent_unit")]
+    indent_unit: String,
+    #[serde(default)]
+    word_chars: WordChars,
+    #[serde(default = "default_brackets_skip_literals")]
+    brackets_skip_literals: bool,
+    #[serde(default)]
+    search: SearchOptions
 }
 
-use std::fs::read_to_string;
+fn default_undo_group_timeout() -> u64 {
+    DEFAULT_GROUP_TIMEOUT_MS
+}
 
+fn default_tab_width() -> usize {
+    DEFAULT_TAB_WIDTH
+}
 
-impl Default for Config {
-    fn default() -> Self {
+fn default_expand_tab() -> bool {
+    true
+}
 
-        let default_cfg = Path::new("config.rc");
-        if default_cfg.exists() {
-            match read_to_string(default_cfg) {
-                Ok(contents) => {
-                    let cfg = serde_json::from_str(&contents);
-                    match cfg {
-                        Ok(config) => {
-                            return config;
-                        },
-                        Err(e) => {
-
-                        }
-                    }
-                },
-                Err(e) => {
+fn default_indent_unit() -> String {
+    " ".repeat(DEFAULT_TAB_WIDTH)
+}
+
+fn default_brackets_skip_literals() -> bool {
+    true
+}
+
+/// Ctrl+Left/Right move by words, Alt+Left/Right by WORDs, Ctrl+Alt+Left/Right to word ends, Shift+Alt+Left/Right
+/// to WORD ends, Alt+] jumps to the matching bracket and F3/Shift+F3 repeat the last search forwards/backwards.
+/// Home/End go to the start/end of the line, Ctrl+Home/End of the buffer, and PageUp/PageDown a screenful up/down.
+fn default_modified_key_bindings() -> Vec<(KeyCode, Command)> {
+    let shift = Modifiers { shift: true, ..Modifiers::default() };
+    let ctrl = Modifiers { ctrl: true, ..Modifiers::default() };
+    let alt = Modifiers { alt: true, ..Modifiers::default() };
+    let ctrl_alt = Modifiers { ctrl: true, alt: true, ..Modifiers::default() };
+    let shift_alt = Modifiers { shift: true, alt: true, ..Modifiers::default() };
+    vec![
+        (KeyCode::Modified(EscapeKeyCode::Left, ctrl), Command::Move(MoveKind::Word(MoveDir::Previous))),
+        (KeyCode::Modified(EscapeKeyCode::Right, ctrl), Command::Move(MoveKind::Word(MoveDir::Next))),
+        (KeyCode::Modified(EscapeKeyCode::Left, alt), Command::Move(MoveKind::BigWord(MoveDir::Previous))),
+        (KeyCode::Modified(EscapeKeyCode::Right, alt), Command::Move(MoveKind::BigWord(MoveDir::Next))),
+        (KeyCode::Modified(EscapeKeyCode::Left, ctrl_alt), Command::Move(MoveKind::WordEnd(MoveDir::Previous))),
+        (KeyCode::Modified(EscapeKeyCode::Right, ctrl_alt), Command::Move(MoveKind::WordEnd(MoveDir::Next))),
+        (KeyCode::Modified(EscapeKeyCode::Left, shift_alt), Command::Move(MoveKind::BigWordEnd(MoveDir::Previous))),
+        (KeyCode::Modified(EscapeKeyCode::Right, shift_alt), Command::Move(MoveKind::BigWordEnd(MoveDir::Next))),
+        (KeyCode::Alt(']'), Command::Move(MoveKind::MatchingBracket)),
+        (KeyCode::Escaped(EscapeKeyCode::F(3)), Command::FindNext(SeekFrom::Start)),
+        (KeyCode::Modified(EscapeKeyCode::F(3), shift), Command::FindNext(SeekFrom::End)),
+        (KeyCode::Escaped(EscapeKeyCode::Home), Command::Move(MoveKind::LineStart)),
+        (KeyCode::Escaped(EscapeKeyCode::End), Command::Move(MoveKind::LineEnd)),
+        (KeyCode::Modified(EscapeKeyCode::Home, ctrl), Command::Move(MoveKind::BufferStart)),
+        (KeyCode::Modified(EscapeKeyCode::End, ctrl), Command::Move(MoveKind::BufferEnd)),
+        (KeyCode::Escaped(EscapeKeyCode::PageUp), Command::Move(MoveKind::Page(MoveDir::Previous))),
+        (KeyCode::Escaped(EscapeKeyCode::PageDown), Command::Move(MoveKind::Page(MoveDir::Next))),
+    ]
+}
 
-                }
-            }
-        }
 
+impl Default for Config {
+    fn default() -> Self {
         let file_name = PathBuf::from("config.rc");
         let key_bindings =
             [   (KeyCode::CtrlO, Command::Open),
@@ -63,7 +115,19 @@ impl Default for Config {
                 (KeyCode::CtrlQ, Command::Quit),
                 (KeyCode::CtrlC, Command::CommandInput),
                 (KeyCode::CtrlG, Command::Jump),
+                (KeyCode::CtrlF, Command::Find),
+                (KeyCode::CtrlL, Command::Replace),
+                (KeyCode::CtrlN, Command::Grep),
+                (KeyCode::CtrlD, Command::ReplaceInFiles),
                 (KeyCode::CtrlZ, Command::Action(Operation::Undo)),
+                (KeyCode::CtrlY, Command::Action(Operation::Redo)),
+                (KeyCode::CtrlU, Command::Action(Operation::UndoOlder)),
+                (KeyCode::CtrlT, Command::Action(Operation::UndoNewer)),
+                (KeyCode::CtrlB, Command::Action(Operation::UndoBranch(MoveDir::Next))),
+                (KeyCode::CtrlW, Command::Action(Operation::Copy(ObjectKind::Word))),
+                (KeyCode::CtrlK, Command::Action(Operation::Cut(ObjectKind::Line))),
+                (KeyCode::CtrlV, Command::Action(Operation::Paste)),
+                (KeyCode::CtrlP, Command::Action(Operation::YankPop)),
             ].iter().cloned().collect();
 
         let command_combo_key_bindings =
@@ -78,16 +142,26 @@ impl Default for Config {
         Config {
             file_name,
             key_bindings,
+            modified_key_bindings: default_modified_key_bindings(),
             command_combo_key_bindings,
             history_size,
             bg_color,
             fg_color,
-            stat_line_color
+            stat_line_color,
+            undo_group_timeout: default_undo_group_timeout(),
+            macros: HashMap::new(),
+            system_clipboard: ClipboardProvider::default(),
+            tab_width: default_tab_width(),
+            expand_tab: default_expand_tab(),
+            indent_unit: default_indent_unit(),
+            word_chars: WordChars::default(),
+            brackets_skip_literals: default_brackets_skip_literals(),
+            search: SearchOptions::default()
         }
     }
 }
 use std::fs;
-use crate::data::text_buffer::ObjectKind;
+use crate::data::text_buffer::{ObjectKind, DEFAULT_TAB_WIDTH};
 use std::hash::Hash;
 
 impl Config {
@@ -111,23 +185,78 @@ impl Config {
         }
     }
 
-    pub fn save_config(&self, file_path: &Path) {
-        let config_contents = serde_json::to_string_pretty(self);
-        if file_path.exists() {
-            match config_contents {
-                Ok(data) => {
+    pub fn save_config(&self, file_path: &Path) -> std::io::Result<()> {
+        let config_contents = serde_json::to_string_pretty(self)?;
+        fs::write(file_path, config_contents)
+    }
 
-                },
-                Err(e) => {
+    #[inline]
+    pub fn macros(&self) -> &HashMap<String, Macro> {
+        &self.macros
+    }
 
-                }
-            }
-        }
+    pub fn set_macro(&mut self, name: &str, m: Macro) {
+        self.macros.insert(name.to_string(), m);
     }
 
     #[inline]
     pub fn get_binding(&self, kc: KeyCode) -> Option<&Command> {
         self.key_bindings.get(&kc)
+            .or_else(|| self.modified_key_bindings.iter().find(|(key, _)| *key == kc).map(|(_, cmd)| cmd))
+    }
+
+    /// What `kc` is bound to among the default bindings of keys with modifiers and function keys, for configs
+    /// that were saved before the key had a binding
+    pub fn default_modified_binding(kc: &KeyCode) -> Option<Command> {
+        default_modified_key_bindings().into_iter().find(|(key, _)| key == kc).map(|(_, cmd)| cmd)
+    }
+
+    /// Milliseconds of typing pause, after which a new undo group is started
+    #[inline]
+    pub fn undo_group_timeout(&self) -> u64 {
+        self.undo_group_timeout
+    }
+
+    /// Where copied and cut text is sent, besides the editor's own kill ring
+    #[inline]
+    pub fn system_clipboard(&self) -> &ClipboardProvider {
+        &self.system_clipboard
+    }
+
+    /// How many columns apart tab stops are
+    #[inline]
+    pub fn tab_width(&self) -> usize {
+        self.tab_width
+    }
+
+    /// Whether the tab key inserts spaces up to the next tab stop, instead of a tab character
+    #[inline]
+    pub fn expand_tab(&self) -> bool {
+        self.expand_tab
+    }
+
+    /// What one level of indentation is, added after an opening bracket and removed before a closing one
+    #[inline]
+    pub fn indent_unit(&self) -> &str {
+        &self.indent_unit
+    }
+
+    /// Which characters word motions treat as parts of words
+    #[inline]
+    pub fn word_chars(&self) -> &WordChars {
+        &self.word_chars
+    }
+
+    /// Whether brackets in string literals and comments are left out, when matching brackets
+    #[inline]
+    pub fn brackets_skip_literals(&self) -> bool {
+        self.brackets_skip_literals
+    }
+
+    /// Whether searches are for regular expressions, how they match case and whether they only match whole words
+    #[inline]
+    pub fn search_options(&self) -> &SearchOptions {
+        &self.search
     }
 
     #[inline]
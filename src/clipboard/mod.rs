@@ -0,0 +1,234 @@
+use std::collections::{HashMap, VecDeque};
+
+pub mod system;
+
+pub const DEFAULT_KILL_RING_SIZE: usize = 60;
+
+/**
+    Emacs style kill ring. Every copy or cut pushes a new entry to the front, and yanking
+    pastes the entry the ring is currently rotated to (the latest one, unless a yank-pop has rotated it).
+*/
+pub struct KillRing {
+    entries: VecDeque<String>,
+    capacity: usize,
+    yank_index: usize
+}
+
+impl KillRing {
+    pub fn new(capacity: usize) -> KillRing {
+        KillRing {
+            entries: VecDeque::new(),
+            capacity,
+            yank_index: 0
+        }
+    }
+
+    pub fn push(&mut self, text: String) {
+        self.entries.push_front(text);
+        if self.entries.len() > self.capacity {
+            self.entries.pop_back();
+        }
+        self.yank_index = 0;
+    }
+
+    /// Appends to the latest entry instead of creating a new one, which is what consecutive cuts do.
+    pub fn append(&mut self, text: &str) {
+        match self.entries.front_mut() {
+            Some(latest) => latest.push_str(text),
+            None => self.push(text.to_string())
+        }
+        self.yank_index = 0;
+    }
+
+    pub fn current(&self) -> Option<&String> {
+        self.entries.get(self.yank_index)
+    }
+
+    /// Rotates the ring one step towards older entries, wrapping around at the end, and returns the new current entry.
+    pub fn rotate(&mut self) -> Option<&String> {
+        if self.entries.is_empty() {
+            return None;
+        }
+        self.yank_index = (self.yank_index + 1) % self.entries.len();
+        self.current()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+/**
+    The clipboard: one kill ring, plus named registers a-z (like vim's "a). Uppercase register names append to
+    the register with the lowercase name. A selected register is only used for the next copy, cut or paste,
+    after that the kill ring is used again.
+*/
+pub struct Clipboard {
+    ring: KillRing,
+    registers: HashMap<char, String>,
+    selected: Option<char>,
+    last: Option<String>
+}
+
+impl Default for Clipboard {
+    fn default() -> Self {
+        Clipboard::new()
+    }
+}
+
+impl Clipboard {
+    pub fn new() -> Clipboard {
+        Clipboard {
+            ring: KillRing::new(DEFAULT_KILL_RING_SIZE),
+            registers: HashMap::new(),
+            selected: None,
+            last: None
+        }
+    }
+
+    pub fn select_register(&mut self, register: char) -> Result<(), String> {
+        if register.is_ascii_alphabetic() {
+            self.selected = Some(register);
+            Ok(())
+        } else {
+            Err(format!("Invalid register name '{}'. Registers are named a-z (A-Z appends)", register))
+        }
+    }
+
+    pub fn selected_register(&self) -> Option<char> {
+        self.selected
+    }
+
+    /// Stores copied or cut text. `append` is set for consecutive cuts, which then end up in the same entry.
+    pub fn store(&mut self, text: String, append: bool) {
+        self.last = match self.selected.take() {
+            Some(reg) => {
+                let entry = self.registers.entry(reg.to_ascii_lowercase()).or_default();
+                if !(append || reg.is_ascii_uppercase()) {
+                    entry.clear();
+                }
+                entry.push_str(&text);
+                Some(entry.clone())
+            },
+            None => {
+                if append {
+                    self.ring.append(&text);
+                } else {
+                    self.ring.push(text);
+                }
+                self.ring.current().cloned()
+            }
+        };
+    }
+
+    /// The full contents of whatever entry the last copy or cut ended up in.
+    pub fn last_stored(&self) -> Option<&String> {
+        self.last.as_ref()
+    }
+
+    /// The text a paste should insert: the selected register's contents, or the current kill ring entry.
+    pub fn paste_text(&mut self) -> Option<String> {
+        match self.selected.take() {
+            Some(reg) => self.registers.get(&reg.to_ascii_lowercase()).cloned(),
+            None => self.ring.current().cloned()
+        }
+    }
+
+    pub fn rotate(&mut self) -> Option<String> {
+        self.ring.rotate().cloned()
+    }
+
+    pub fn register(&self, register: char) -> Option<&String> {
+        self.registers.get(&register.to_ascii_lowercase())
+    }
+
+    pub fn kill_ring(&self) -> &KillRing {
+        &self.ring
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Clipboard, KillRing};
+    use super::system::{base64_encode, osc52_sequence, copy_to_system, ClipboardProvider};
+
+    #[test]
+    fn test_kill_ring_rotation() {
+        let mut ring = KillRing::new(2);
+        assert!(ring.is_empty());
+        assert_eq!(None, ring.rotate());
+        ring.push("a".into());
+        ring.push("b".into());
+        ring.push("c".into());
+        assert_eq!(2, ring.len());
+        assert_eq!(Some(&"c".to_string()), ring.current());
+        assert_eq!(Some(&"b".to_string()), ring.rotate());
+        assert_eq!(Some(&"c".to_string()), ring.rotate());
+    }
+
+    #[test]
+    fn test_consecutive_cuts_append() {
+        let mut cb = Clipboard::new();
+        cb.store("hello ".into(), false);
+        cb.store("world".into(), true);
+        assert_eq!(Some("hello world".to_string()), cb.paste_text());
+        assert_eq!(1, cb.kill_ring().len());
+    }
+
+    #[test]
+    fn test_named_registers() {
+        let mut cb = Clipboard::new();
+        cb.store("ring".into(), false);
+        cb.select_register('a').unwrap();
+        cb.store("foo".into(), false);
+        cb.select_register('A').unwrap();
+        cb.store("bar".into(), false);
+        assert_eq!(Some(&"foobar".to_string()), cb.register('a'));
+        // the register selection only lasts for one operation
+        assert_eq!(Some("ring".to_string()), cb.paste_text());
+        cb.select_register('a').unwrap();
+        assert_eq!(Some("foobar".to_string()), cb.paste_text());
+        assert!(cb.select_register('1').is_err());
+    }
+
+    #[test]
+    fn test_osc52_sequence() {
+        assert_eq!("", base64_encode(b""));
+        assert_eq!("Zg==", base64_encode(b"f"));
+        assert_eq!("Zm8=", base64_encode(b"fo"));
+        assert_eq!("Zm9vYmFy", base64_encode(b"foobar"));
+        assert_eq!("\x1b]52;c;aMOlbGxv\x07", osc52_sequence("h\u{e5}llo", false));
+        assert_eq!("\x1bPtmux;\x1b\x1b]52;c;aGk=\x07\x1b\\", osc52_sequence("hi", true));
+        let mut out: Vec<u8> = vec![];
+        copy_to_system(&ClipboardProvider::None, "hi", &mut out).unwrap();
+        assert!(out.is_empty());
+    }
+
+    #[test]
+    fn test_clipboard_command() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = std::env::temp_dir().join(format!("cxedit-clipboard-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let script = dir.join("fake-xclip");
+        let target = dir.join("clipboard");
+        std::fs::write(&script, "#!/bin/sh\ncat > \"$1\"\n").unwrap();
+        std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let provider = ClipboardProvider::Command {
+            program: script.to_string_lossy().to_string(),
+            args: vec![target.to_string_lossy().to_string()]
+        };
+        let mut out: Vec<u8> = vec![];
+        copy_to_system(&provider, "copied\ntext", &mut out).unwrap();
+        assert!(out.is_empty());
+        assert_eq!("copied\ntext", std::fs::read_to_string(&target).unwrap());
+
+        let failing = ClipboardProvider::Command { program: "false".into(), args: vec![] };
+        assert!(copy_to_system(&failing, "text", &mut out).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
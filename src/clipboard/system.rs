@@ -0,0 +1,75 @@
+use std::io::{self, Write};
+use std::process::{Command, Stdio};
+use crate::{Serialize, Deserialize};
+
+/**
+    How copied text gets to the clipboard of the host. Osc52 asks the terminal to set it, which also
+    works over SSH and inside tmux. Command pipes the text to a program, like xclip or wl-copy.
+*/
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Default)]
+pub enum ClipboardProvider {
+    None,
+    #[default]
+    Osc52,
+    Command { program: String, args: Vec<String> }
+}
+
+const BASE64_ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+pub fn base64_encode(data: &[u8]) -> String {
+    let mut encoded = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let b = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(BASE64_ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+/// The OSC 52 sequence that sets the clipboard to `text`. Inside tmux the sequence has to be wrapped
+/// in a DCS passthrough (with every ESC doubled), or tmux swallows it instead of passing it on.
+pub fn osc52_sequence(text: &str, tmux: bool) -> String {
+    let seq = format!("\x1b]52;c;{}\x07", base64_encode(text.as_bytes()));
+    if tmux {
+        format!("\x1bPtmux;{}\x1b\\", seq.replace('\x1b', "\x1b\x1b"))
+    } else {
+        seq
+    }
+}
+
+fn run_command(program: &str, args: &[String], text: &str) -> io::Result<()> {
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(text.as_bytes())?;
+    }
+    let status = child.wait()?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(io::Error::other(format!("{} exited with {}", program, status)))
+    }
+}
+
+/// Sends `text` to the system clipboard. OSC 52 sequences are written to `out`, which is the terminal.
+pub fn copy_to_system(provider: &ClipboardProvider, text: &str, out: &mut dyn Write) -> io::Result<()> {
+    match provider {
+        ClipboardProvider::None => Ok(()),
+        ClipboardProvider::Osc52 => {
+            let tmux = std::env::var_os("TMUX").is_some();
+            out.write_all(osc52_sequence(text, tmux).as_bytes())?;
+            out.flush()
+        },
+        ClipboardProvider::Command { program, args } => run_command(program, args, text)
+    }
+}
@@ -1,11 +1,14 @@
 use std::sync::{Arc, Mutex};
-use crate::data::text_buffer::Textbuffer;
-use std::thread::sleep;
-use std::time::Duration;
+use crate::data::text_buffer::{Textbuffer, TextPosition};
+use std::time::{Duration, Instant};
 use std::collections::HashMap;
 use crate::{Serialize as S, Deserialize as D};
-use crate::data::text_buffer::ObjectKind;
+use crate::data::text_buffer::{ObjectKind, Cursor};
+use crate::clipboard::Clipboard;
 use crate::editor::key::KeyCode;
+use crate::cmd::MoveDir;
+use crate::cmd::undo_tree::{UndoTree, Edit};
+use std::ops::Range;
 
 
 pub enum Position {
@@ -23,32 +26,55 @@ pub enum Operation {
     InsertData(AbsolutePos, String),
     Delete(AbsolutePos, char),
     Remove(AbsolutePos, char),
+    /// Replaces each range with its text, all of them as one undo step. The ranges are in order and don't overlap.
+    Replace(Vec<(Range<AbsolutePos>, String)>),
     Copy(ObjectKind),
-    MacroRecord,
+    Cut(ObjectKind),
+    Paste,
+    YankPop,
+    SelectRegister(char),
+    MacroRecord(MacroName),
     MacroStop,
     MacroPlay(MacroName),
     Undo,
     Redo,
+    UndoOlder,
+    UndoNewer,
+    UndoBranch(MoveDir),
 }
 
+impl Operation {
+    /// Whether the operation changes the text of the buffer, which isn't allowed in a read-only buffer
+    pub fn modifies_buffer(&self) -> bool {
+        !matches!(self, Operation::Copy(_) | Operation::SelectRegister(_) | Operation::MacroRecord(_) | Operation::MacroStop | Operation::MacroPlay(_))
+    }
+}
+
+/// A recorded sequence of keys. Playing it back means feeding the keys to the editor again,
+/// just as if they were typed.
+#[derive(Clone, S, D, Debug)]
 pub struct Macro {
-    data: String,
-    lines: usize,
-    len: usize
+    keys: Vec<KeyCode>
 }
 
 impl Macro {
-    pub fn play(&self) -> &str {
-        &self.data
+    pub fn play(&self) -> &[KeyCode] {
+        &self.keys
+    }
+
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
     }
 }
 
 impl Default for Macro {
     fn default() -> Self {
         Macro {
-            data: String::new(),
-            lines: 0,
-            len: 0
+            keys: vec![]
         }
     }
 }
@@ -58,23 +84,103 @@ pub enum OperationResult {
     ERR(String)
 }
 
+impl OperationResult {
+    pub fn is_err(&self) -> bool {
+        matches!(self, OperationResult::ERR(_))
+    }
+}
+
+/// Decides whether an edit continues the current undo group, or starts a new one. A group is broken
+/// when the cursor jumps somewhere else, when there's been a pause in typing, or explicitly by the editor
+/// (e.g. on a mode change). Edits made between begin_group and end_group always end up in the same group.
+struct EditGroup {
+    open: bool,
+    expected_pos: AbsolutePos,
+    last_edit: Option<Instant>,
+    timeout: Duration,
+    transaction_depth: usize,
+}
+
+impl EditGroup {
+    fn new() -> EditGroup {
+        EditGroup {
+            open: false,
+            expected_pos: 0,
+            last_edit: None,
+            timeout: Duration::from_millis(DEFAULT_GROUP_TIMEOUT_MS),
+            transaction_depth: 0
+        }
+    }
+
+    fn record(&mut self, history: &mut UndoTree, edit: Edit, at: AbsolutePos, before: TextPosition, after: TextPosition) {
+        let now = Instant::now();
+        let continues = if self.transaction_depth > 0 {
+            self.open
+        } else {
+            self.open && self.expected_pos == at && self.last_edit.map(|t| now.duration_since(t) < self.timeout).unwrap_or(false)
+        };
+        self.expected_pos = after.absolute;
+        if continues {
+            history.extend_current(edit, before, after);
+        } else {
+            history.push(vec![edit], before, after);
+        }
+        self.open = true;
+        self.last_edit = Some(now);
+    }
+
+    fn close(&mut self) {
+        self.open = false;
+    }
+
+    /// Ends the current group, unless we are inside a transaction.
+    fn boundary(&mut self) {
+        if self.transaction_depth == 0 {
+            self.open = false;
+        }
+    }
+
+    fn begin(&mut self) {
+        self.boundary();
+        self.transaction_depth += 1;
+    }
+
+    fn end(&mut self) {
+        if self.transaction_depth > 0 {
+            self.transaction_depth -= 1;
+            self.boundary();
+        }
+    }
+}
+
+pub const DEFAULT_GROUP_TIMEOUT_MS: u64 = 1000;
+
 pub struct CommandEngine {
-    history: Vec<Operation>,
-    forward_history: Vec<Operation>,
+    history: UndoTree,
+    group: EditGroup,
     buffer_ref: Arc<Mutex<Textbuffer>>,
     macros: HashMap<String, Macro>,
     pub combo_trigger: Option<KeyCode>,
-    _macro_recording: bool,
+    macro_recording: Option<(MacroName, Vec<KeyCode>)>,
+    /// the macros being played back, the innermost last
+    macros_playing: Vec<MacroName>,
+    clipboard: Clipboard,
+    last_was_cut: bool,
+    last_paste: Option<(AbsolutePos, usize)>,
 }
 
 impl CommandEngine {
     pub fn new(buffer: Arc<Mutex<Textbuffer>>) -> CommandEngine {
         CommandEngine {
-            history: vec![],
-            forward_history: vec![],
+            history: UndoTree::new(),
+            group: EditGroup::new(),
             buffer_ref: buffer.clone(),
             macros: HashMap::new(),
-            _macro_recording: false,
+            macro_recording: None,
+            macros_playing: vec![],
+            clipboard: Clipboard::new(),
+            last_was_cut: false,
+            last_paste: None,
             combo_trigger: None
         }
     }
@@ -87,158 +193,315 @@ impl CommandEngine {
         self.buffer_ref = buf_ref.clone();
     }
 
+    pub fn is_recording(&self) -> Option<&str> {
+        self.macro_recording.as_ref().map(|(name, _)| name.as_str())
+    }
+
+    /// Records a key into the macro currently being recorded, if any.
+    pub fn record_key(&mut self, kc: &KeyCode) {
+        if let Some((_, keys)) = self.macro_recording.as_mut() {
+            keys.push(kc.clone());
+        }
+    }
+
+    pub fn get_macro(&self, name: &str) -> Option<&Macro> {
+        self.macros.get(name)
+    }
+
+    pub fn add_macro(&mut self, name: &str, m: Macro) {
+        self.macros.insert(name.to_string(), m);
+    }
+
+    /// The editor is done playing back the macro that the last successful MacroPlay was for.
+    pub fn end_macro_play(&mut self) {
+        self.macros_playing.pop();
+    }
+
+    /// How long a pause in typing has to be, before the next edit starts a new undo group.
+    pub fn set_group_timeout(&mut self, timeout: Duration) {
+        self.group.timeout = timeout;
+    }
+
+    /// Ends the current undo group, so that the next edit will be undone separately.
+    pub fn break_group(&mut self) {
+        self.group.boundary();
+    }
+
+    /// Starts a transaction. Every edit up until the matching end_group is undone as one step. Transactions may be nested.
+    pub fn begin_group(&mut self) {
+        self.group.begin();
+    }
+
+    pub fn end_group(&mut self) {
+        self.group.end();
+    }
+
+    /// Forgets every edit made so far, for when the text of the buffer has been replaced by something else altogether,
+    /// like the contents of a file that was opened in it.
+    pub fn clear_history(&mut self) {
+        self.history = UndoTree::new();
+        self.group.boundary();
+        self.last_paste = None;
+    }
+
+    pub fn clipboard(&self) -> &Clipboard {
+        &self.clipboard
+    }
+
     pub fn execute(&mut self, action: Operation) -> OperationResult {
+        if action.modifies_buffer() && self.buffer_ref.lock().unwrap().is_read_only() {
+            return OperationResult::ERR("The buffer is read-only".into());
+        }
+        // consecutive cuts end up in the same kill ring entry, and yank-pop only works right after a paste
+        let continues_cut = std::mem::replace(&mut self.last_was_cut, false);
+        let last_paste = self.last_paste.take();
 
         match &action {
             Operation::Insert(pos, ch) => {
                 let mut guard = self.buffer_ref.lock().unwrap();
-                let bufpos = guard.get_textpos().absolute;
-                if *pos == bufpos {
-                    guard.insert_ch(*ch);
-                } else {
+                let before = guard.get_textpos();
+                if *pos != before.absolute {
                     guard.set_textpos(*pos);
-                    guard.insert_ch(*ch);
                 }
-                self.history.push(action);
-                self.forward_history.clear();
+                guard.insert_ch(*ch);
+                let after = guard.get_textpos();
+                self.group.record(&mut self.history, Edit::Insert(*pos, ch.to_string()), *pos, before, after);
                 OperationResult::OK
             },
             Operation::InsertData(pos, data) => {
                 let mut guard = self.buffer_ref.lock().unwrap();
-                let bufpos = guard.get_textpos().absolute;
-                if *pos == bufpos {
-                    guard.insert_data(data);
-                } else {
+                let before = guard.get_textpos();
+                if *pos != before.absolute {
                     guard.set_textpos(*pos);
-                    guard.insert_data(data);
                 }
-                self.history.push(action);
+                guard.insert_data(data);
+                let after = guard.get_textpos();
+                self.group.record(&mut self.history, Edit::Insert(*pos, data.clone()), *pos, before, after);
                 OperationResult::OK
             },
-            Operation::Delete(pos, _) => {
+            Operation::Delete(pos, ch) => {
                 let mut guard = self.buffer_ref.lock().unwrap();
-                let bufpos = guard.get_textpos().absolute;
-                if *pos == bufpos {
-                    guard.delete();
-                } else {
+                let before = guard.get_textpos();
+                if *pos != before.absolute {
                     guard.set_textpos(*pos);
-                    guard.delete();
                 }
-                self.history.push(action);
-                OperationResult::OK
+                if let Some(c) = guard.delete() {
+                    let after = guard.get_textpos();
+                    self.group.record(&mut self.history, Edit::Delete(*pos, c.to_string()), *pos, before, after);
+                    OperationResult::OK
+                } else {
+                    OperationResult::ERR(format!("Couldn't delete {} at {}", ch, pos))
+                }
             },
             Operation::Remove(pos, ch) => {
-                // sleep(Duration::from_millis(1500));
                 let mut guard = self.buffer_ref.lock().unwrap();
-                guard.set_textpos(*pos);
-                let bufpos = guard.get_textpos().absolute;
-                if *pos == bufpos && *pos > 0 {
-                    guard.remove();
-                    self.history.push(action);
-                    OperationResult::OK
-                } else if *pos > 0 {
-                    // guard.set_textpos(*pos);
-                    if let Some(c) = guard.remove() {
-                        if c != *ch {
-                            println!("ERROR C != CH");
-                        }
-                    };
-                    self.history.push(Operation::Remove(bufpos-1, *ch));
-                    OperationResult::OK
-                } else {
-                    OperationResult::ERR(format!("Couldn't remove {} at {}", ch, pos))
+                let before = guard.get_textpos();
+                if *pos > 0 {
+                    guard.set_textpos(*pos);
+                    if let Some(removed) = guard.remove() {
+                        let after = guard.get_textpos();
+                        let start = *pos - removed.chars().count();
+                        self.group.record(&mut self.history, Edit::Delete(start, removed), *pos, before, after);
+                        return OperationResult::OK;
+                    }
                 }
+                OperationResult::ERR(format!("Couldn't remove {} at {}", ch, pos))
             },
-            Operation::Undo => {
-                if let Some(act) = self.history.last() {
-                    match act {
-                        Operation::Delete(pos, ch) => {
-                            let mut guard= self.buffer_ref.lock().unwrap();
-                            let bufpos = guard.get_textpos().absolute;
-                            if *pos == bufpos {
-                                guard.insert_ch(*ch);
-                            } else {
-                                guard.set_textpos(*pos);
-                                guard.insert_ch(*ch);
-                            }
-                            self.forward_history.push(Operation::Insert(*pos, *ch));
-                            self.history.pop();
-                            OperationResult::OK
-                        },
-                        Operation::Insert(pos, ch) => {
-                            let mut guard = self.buffer_ref.lock().unwrap();
-                            let bufpos = guard.get_textpos().absolute;
-                            if *pos == bufpos {
-                                guard.delete();
-                            } else {
-                                guard.set_textpos(*pos);
-                                guard.delete();
-                            }
-                            self.forward_history.push(Operation::Remove(*pos, *ch));
-                            self.history.pop();
-                            OperationResult::OK
-                        },
-                        Operation::Remove(pos, ch) => {
-                            let mut guard = self.buffer_ref.lock().unwrap();
-                            guard.set_textpos(*pos-1);
-                            guard.insert_ch(*ch);
-                            self.forward_history.push(Operation::Insert(*pos, *ch));
-                            self.history.pop();
-                            OperationResult::OK
-                        },
-                        Operation::InsertData(pos, data) => {
-                            let mut guard = self.buffer_ref.lock().unwrap();
-                            let bufpos = guard.get_textpos().absolute;
-                            if *pos == bufpos {
-                                for i in 0..data.len() {
-                                    if let Some(ch) = guard.delete() {
-                                        self.forward_history.push(Operation::Remove(*pos+i, ch));
-                                    }
-                                }
-                            } else {
-                                guard.set_textpos(*pos);
-                                for i in 0..data.len() {
-                                    if let Some(ch) = guard.delete() {
-                                        self.forward_history.push(Operation::Remove(*pos+i, ch));
-                                    }
-                                }
-                            }
-                            self.history.pop();
-                            OperationResult::OK
-                        },
-                        Operation::MacroPlay(macroname) => {
-                            let m = &self.macros[macroname];
-                            unimplemented!("Undoing played macros not yet implemented!")
-                        },
-                        _ => {
-                            unimplemented!("This is not implemented yet!!!");
-                            sleep(Duration::from_millis(2500));
-                            OperationResult::ERR("Not yet implemented!".into())
-                        }
+            Operation::Replace(replacements) => {
+                if replacements.is_empty() {
+                    return OperationResult::ERR("Nothing to replace.".into());
+                }
+                // the cursor ends up at the start of the last replacement, wherever the ones before it move it to
+                let (last, _) = replacements.last().unwrap();
+                let shift: isize = replacements[..replacements.len() - 1].iter()
+                    .map(|(range, text)| text.chars().count() as isize - range.len() as isize)
+                    .sum();
+                let cursor = (last.start as isize + shift) as usize;
+                let mut guard = self.buffer_ref.lock().unwrap();
+                self.group.begin();
+                // back to front, so that the ranges still to be replaced aren't moved by the ones replaced already
+                for (i, (range, text)) in replacements.iter().enumerate().rev() {
+                    let before = guard.get_textpos();
+                    let old = guard.get_data(range.clone());
+                    guard.set_textpos(range.start);
+                    for _ in range.clone() {
+                        guard.delete();
                     }
-                } else {
-                    OperationResult::ERR("History queue empty.".into())
+                    let removed = guard.get_textpos();
+                    guard.insert_data(text);
+                    if i == 0 {
+                        guard.set_textpos(cursor);
+                    }
+                    let after = guard.get_textpos();
+                    self.group.record(&mut self.history, Edit::Delete(range.start, old), range.start, before, removed.clone());
+                    self.group.record(&mut self.history, Edit::Insert(range.start, text.clone()), range.start, removed, after);
+                }
+                self.group.end();
+                OperationResult::OK
+            },
+            Operation::Undo => {
+                let mut guard = self.buffer_ref.lock().unwrap();
+                self.group.close();
+                match self.history.undo(&mut guard) {
+                    Some(_) => OperationResult::OK,
+                    None => OperationResult::ERR("History queue empty.".into())
                 }
             },
             Operation::Redo => {
-                unimplemented!("Redoing last undo command not yet implemented");
-                OperationResult::ERR(format!("Redo command not implemented yet"))
+                let mut guard = self.buffer_ref.lock().unwrap();
+                self.group.close();
+                match self.history.redo(&mut guard) {
+                    Some(_) => OperationResult::OK,
+                    None => OperationResult::ERR("Nothing to redo.".into())
+                }
+            },
+            Operation::UndoOlder => {
+                let mut guard = self.buffer_ref.lock().unwrap();
+                self.group.close();
+                match self.history.older(&mut guard) {
+                    Some(_) => OperationResult::OK,
+                    None => OperationResult::ERR("Already at oldest change.".into())
+                }
+            },
+            Operation::UndoNewer => {
+                let mut guard = self.buffer_ref.lock().unwrap();
+                self.group.close();
+                match self.history.newer(&mut guard) {
+                    Some(_) => OperationResult::OK,
+                    None => OperationResult::ERR("Already at newest change.".into())
+                }
+            },
+            Operation::UndoBranch(dir) => {
+                let mut guard = self.buffer_ref.lock().unwrap();
+                self.group.close();
+                match self.history.switch_branch(dir.clone(), &mut guard) {
+                    Some(_) => OperationResult::OK,
+                    None => OperationResult::ERR("No other branch to switch to.".into())
+                }
             },
             Operation::MacroPlay(name) => {
-                unimplemented!("Playing macros not yet implemented");
-                OperationResult::ERR(format!("Macro command not implemented yet"))
-            }
-            Operation::MacroRecord => {
-                unimplemented!("Recording macros not yet implemented");
-                OperationResult::ERR(format!("Macro command not implemented yet"))
-            }
+                // The engine can't play the keys back by itself, the editor does that. We only check that there's anything to play,
+                // and that it won't end up playing itself, which would never end.
+                if !self.macros.contains_key(name) {
+                    OperationResult::ERR(format!("No macro named {}", name))
+                } else if self.is_recording() == Some(name.as_str()) {
+                    OperationResult::ERR(format!("Can't play macro {} while recording it", name))
+                } else if self.macros_playing.contains(name) {
+                    OperationResult::ERR(format!("Macro {} would play itself", name))
+                } else {
+                    self.macros_playing.push(name.clone());
+                    OperationResult::OK
+                }
+            },
+            Operation::MacroRecord(name) => {
+                if let Some((recording, _)) = &self.macro_recording {
+                    OperationResult::ERR(format!("Already recording macro {}", recording))
+                } else {
+                    self.macro_recording = Some((name.clone(), vec![]));
+                    OperationResult::OK
+                }
+            },
             Operation::MacroStop => {
-                unimplemented!("Recording macros not yet implemented");
-                OperationResult::ERR(format!("Macro command not implemented yet"))
+                if let Some((name, mut keys)) = self.macro_recording.take() {
+                    // the key that stopped the recording was recorded as well, and is not part of the macro
+                    keys.pop();
+                    self.macros.insert(name, Macro { keys });
+                    OperationResult::OK
+                } else {
+                    OperationResult::ERR("Not recording any macro".into())
+                }
             },
             Operation::Copy(obj) => {
-                OperationResult::ERR(format!("Macro command not implemented yet"))
+                let mut guard = self.buffer_ref.lock().unwrap();
+                match guard.find_range_of(Cursor::Buffer, obj.clone()) {
+                    Some(range) => {
+                        let text = guard.get_data(range);
+                        if let ObjectKind::Selection = obj {
+                            guard.clear_mark();
+                        }
+                        self.clipboard.store(text, false);
+                        OperationResult::OK
+                    },
+                    None => OperationResult::ERR("Nothing to copy.".into())
+                }
+            },
+            Operation::Cut(obj) => {
+                let mut guard = self.buffer_ref.lock().unwrap();
+                let before = guard.get_textpos();
+                match guard.find_range_of(Cursor::Buffer, obj.clone()) {
+                    Some(range) => {
+                        let text = guard.get_data(range.clone());
+                        guard.set_textpos(range.start);
+                        for _ in range.clone() {
+                            guard.delete();
+                        }
+                        guard.clear_mark();
+                        let after = guard.get_textpos();
+                        self.group.boundary();
+                        self.group.record(&mut self.history, Edit::Delete(range.start, text.clone()), range.start, before, after);
+                        self.group.boundary();
+                        self.clipboard.store(text, continues_cut);
+                        self.last_was_cut = true;
+                        OperationResult::OK
+                    },
+                    None => OperationResult::ERR("Nothing to cut.".into())
+                }
+            },
+            Operation::Paste => {
+                let text = match self.clipboard.paste_text() {
+                    Some(text) => text,
+                    None => return OperationResult::ERR("Nothing to paste.".into())
+                };
+                let mut guard = self.buffer_ref.lock().unwrap();
+                let before = guard.get_textpos();
+                let pos = before.absolute;
+                guard.insert_data(&text);
+                let after = guard.get_textpos();
+                self.group.boundary();
+                self.group.record(&mut self.history, Edit::Insert(pos, text.clone()), pos, before, after);
+                self.group.boundary();
+                self.last_paste = Some((pos, text.chars().count()));
+                OperationResult::OK
+            },
+            Operation::YankPop => {
+                let (pos, len) = match last_paste {
+                    Some(paste) => paste,
+                    None => return OperationResult::ERR("Previous command was not a paste.".into())
+                };
+                let text = match self.clipboard.rotate() {
+                    Some(text) => text,
+                    None => return OperationResult::ERR("Kill ring is empty.".into())
+                };
+                let mut guard = self.buffer_ref.lock().unwrap();
+                let before = guard.get_textpos();
+                let old = guard.get_data(pos..pos+len);
+                guard.set_textpos(pos);
+                for _ in 0..len {
+                    guard.delete();
+                }
+                let removed = guard.get_textpos();
+                guard.insert_data(&text);
+                let after = guard.get_textpos();
+                self.group.begin();
+                self.group.record(&mut self.history, Edit::Delete(pos, old), pos, before, removed);
+                self.group.record(&mut self.history, Edit::Insert(pos, text.clone()), pos, after.clone(), after);
+                self.group.end();
+                self.last_paste = Some((pos, text.chars().count()));
+                OperationResult::OK
+            },
+            Operation::SelectRegister(reg) => {
+                match self.clipboard.select_register(*reg) {
+                    Ok(_) => OperationResult::OK,
+                    Err(msg) => OperationResult::ERR(msg)
+                }
             }
         }
     }
-}
\ No newline at end of file
+
+    /// Describes where in the undo tree we are, e.g. "change 4 of 7, branch 1/2"
+    pub fn history_info(&self) -> String {
+        let (state, total) = self.history.state();
+        let (branch, branches) = self.history.branch();
+        format!("change {} of {}, branch {}/{}", state, total, branch, branches)
+    }
+}
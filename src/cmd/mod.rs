@@ -1,4 +1,5 @@
 pub mod command_engine;
+pub mod undo_tree;
 use crate::{Deserialize as Des, Serialize as Ser};
 
 pub trait ToOption where Self: Clone {
@@ -14,9 +15,24 @@ pub enum MoveDir {
 }
 #[derive(Clone, Ser, Des, Debug)]
 pub enum MoveKind {
+    /// to the start of a word
     Word(MoveDir),
+    /// to the start of a WORD, which is anything between whitespace
+    BigWord(MoveDir),
+    /// to the last character of a word
+    WordEnd(MoveDir),
+    /// to the last character of a WORD
+    BigWordEnd(MoveDir),
     Line(MoveDir),
-    Char(MoveDir)
+    /// a screenful of lines up or down, which it takes the view showing the buffer to know the height of
+    Page(MoveDir),
+    LineStart,
+    LineEnd,
+    BufferStart,
+    BufferEnd,
+    Char(MoveDir),
+    /// to the bracket that pairs up with the one under the cursor, or right before it
+    MatchingBracket
 }
 // let words be something, and see where clion tabs
 #[derive(Clone, Ser, Des, Debug)]
@@ -25,22 +41,39 @@ pub enum Command {
     CommandInput,
     Jump,
     Find,
+    /// repeats the last search, in the given direction
+    FindNext(SeekFrom),
+    Replace,
+    /// searches the files of a directory tree, and lists the matches in a buffer of their own
+    Grep,
+    /// replaces in the files of a directory tree, after showing what would change
+    ReplaceInFiles,
     Save,
     Open,
     Quit,
+    Record,
+    Play,
+    Register,
     Action(Operation)
 }
 
-use self::Command::{Jump, Find, Save, Open};
+use self::Command::{Jump, Find, Replace, Grep, ReplaceInFiles, Save, Open, Record, Play, Register};
 use crate::cmd::command_engine::Operation;
+use crate::data::search::{SearchOptions, CaseMatching};
 
 impl From<&Command> for &str {
     fn from(cmd: &Command) -> Self {
         match cmd {
             Jump => "[goto]: ",
             Find => "[find]: ",
+            Replace => "[replace]: ",
+            Grep => "[grep]: ",
+            ReplaceInFiles => "[replace in files]: ",
             Save => "[save]: ",
             Open => "[open]: ",
+            Record => "[record]: ",
+            Play => "[macro]: ",
+            Register => "[register]: ",
             _ => ""
         }
     }
@@ -51,8 +84,14 @@ impl From<&Command> for String {
         match cmd {
             Jump => "[goto]: ".into(),
             Find => "[find]: ".into(),
+            Replace => "[replace]: ".into(),
+            Grep => "[grep]: ".into(),
+            ReplaceInFiles => "[replace in files]: ".into(),
             Save => "[save]: ".into(),
             Open => "[open]: ".into(),
+            Record => "[record]: ".into(),
+            Play => "[macro]: ".into(),
+            Register => "[register]: ".into(),
             _ => "".into()
         }
     }
@@ -98,17 +137,569 @@ pub enum StatlineCommandFlag {
     SameWindowClose
 }
 
-#[derive(Clone, Debug)]
+/// Which way a search goes: from the start towards the end of the buffer (forwards), or from the end towards the start
+#[derive(Clone, Ser, Des, Debug, PartialEq)]
 pub enum SeekFrom {
     Start,
     End,
 }
 
+/// The title of the find prompt, which says how the search is made where that isn't the usual way: "back" when it
+/// searches backwards, "re" for regular expressions, "Aa" or "aa" for case sensitive or insensitive and "w" for whole words
+pub fn find_title(dir: &SeekFrom, options: &SearchOptions) -> String {
+    let mut title = String::from("[find");
+    if *dir == SeekFrom::End {
+        title.push_str(" back");
+    }
+    if options.regex {
+        title.push_str(" re");
+    }
+    match options.case {
+        CaseMatching::Sensitive => title.push_str(" Aa"),
+        CaseMatching::Insensitive => title.push_str(" aa"),
+        CaseMatching::Smart => {}
+    }
+    if options.whole_word {
+        title.push_str(" w");
+    }
+    title.push_str("]: ");
+    title
+}
+
 #[derive(Clone, Debug)]
 pub enum StatlineCommand {
     OpenFile(Option<String>, Option<Vec<StatlineCommandFlag>>),
     SaveFile(Option<String>, Option<Vec<StatlineCommandFlag>>),
     Goto(Option<usize>),
-    Find(Option<String>, SeekFrom),
+    /// pattern, which way to search, and what the pattern is matched with
+    Find(Option<String>, SeekFrom, SearchOptions),
+    /// pattern, replacement, where to replace, and whether to ask before each replacement
+    Replace(String, String, ReplaceScope, bool),
+    /// pattern, and the directory to search the files of
+    Grep(String, String),
+    /// pattern, replacement, and the directory to replace in the files of
+    ReplaceInFiles(String, String, String),
+    RecordMacro(String),
+    PlayMacro(String, MacroRepeat),
+    SelectRegister(char),
     Error(String)
 }
+
+/// How many times a macro should be played. Lines are 1-indexed and inclusive, just like the goto command.
+#[derive(Clone, Debug, PartialEq)]
+pub enum MacroRepeat {
+    Times(usize),
+    Lines(usize, usize),
+    AllLines
+}
+
+/// Which part of the buffer a replace command replaces in. Lines are 1-indexed and inclusive, just like the goto command.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ReplaceScope {
+    Buffer,
+    Lines(usize, usize),
+    /// the selection if there is one, otherwise the whole buffer
+    Selection
+}
+
+/// Splits `input` at the first unescaped `delimiter`. A backslash in front of the delimiter makes it a part of the text,
+/// any other backslash is kept, so that regular expressions like \d come through as they are.
+fn split_delimited(input: &str, delimiter: char) -> (String, Option<&str>) {
+    let mut text = String::new();
+    let mut chars = input.char_indices();
+    while let Some((idx, ch)) = chars.next() {
+        if ch == delimiter {
+            return (text, Some(&input[idx + ch.len_utf8()..]));
+        }
+        if ch == '\\' {
+            match chars.next() {
+                Some((_, next)) if next == delimiter => text.push(next),
+                Some((_, next)) => {
+                    text.push(ch);
+                    text.push(next);
+                },
+                None => text.push(ch)
+            }
+        } else {
+            text.push(ch);
+        }
+    }
+    (text, None)
+}
+
+/**
+    Parses the input of the replace prompt, which looks like "[range]/pattern/replacement/[flags]". The range is either
+    "%" (the whole buffer) or "from,to" (lines), and without it the selection is replaced in, or the whole buffer if
+    nothing is selected. Any character that isn't a part of the range can be used instead of '/'. The only flag is 'c',
+    which asks for confirmation of every replacement.
+*/
+pub fn parse_replace_args(input: &str) -> StatlineCommand {
+    let input = input.trim_start();
+    let range_len = input.find(|c: char| !(c.is_ascii_digit() || c == ',' || c == '%' || c.is_whitespace())).unwrap_or(input.len());
+    let (range, rest) = input.split_at(range_len);
+    let scope = match range.trim() {
+        "" => ReplaceScope::Selection,
+        "%" => ReplaceScope::Buffer,
+        range => {
+            let mut lines = range.splitn(2, ',').map(|v| v.trim().parse::<usize>());
+            match (lines.next(), lines.next()) {
+                (Some(Ok(from)), Some(Ok(to))) if from > 0 && from <= to => ReplaceScope::Lines(from, to),
+                (Some(Ok(line)), None) if line > 0 => ReplaceScope::Lines(line, line),
+                _ => return StatlineCommand::Error(format!("Couldn't parse line range from {}", range))
+            }
+        }
+    };
+    let delimiter = match rest.chars().next() {
+        Some(ch) => ch,
+        None => return StatlineCommand::Error("No pattern given, replace takes /pattern/replacement/".into())
+    };
+    let (pattern, rest) = split_delimited(&rest[delimiter.len_utf8()..], delimiter);
+    let (replacement, flags) = match rest {
+        Some(rest) => split_delimited(rest, delimiter),
+        None => return StatlineCommand::Error(format!("No replacement given for {}", pattern))
+    };
+    if pattern.is_empty() {
+        return StatlineCommand::Error("No pattern given, replace takes /pattern/replacement/".into());
+    }
+    let confirm = match flags.map(str::trim) {
+        None | Some("") => false,
+        Some("c") => true,
+        Some(flags) => return StatlineCommand::Error(format!("Unknown replace flags {}", flags))
+    };
+    StatlineCommand::Replace(pattern, replacement, scope, confirm)
+}
+
+/// Parses the input of the grep prompt, which is "pattern [directory]". A pattern with whitespace in it is put in double
+/// quotes, inside of which \" is a quote. Without a directory, the one the editor was started in is searched.
+pub fn parse_grep_args(input: &str) -> StatlineCommand {
+    let input = input.trim_start();
+    let (pattern, rest) = if let Some(quoted) = input.strip_prefix('"') {
+        match split_delimited(quoted, '"') {
+            (pattern, Some(rest)) => (pattern, rest),
+            (_, None) => return StatlineCommand::Error(format!("Missing closing quote in {}", input))
+        }
+    } else {
+        match input.find(char::is_whitespace) {
+            Some(idx) => (input[..idx].to_string(), &input[idx..]),
+            None => (input.to_string(), "")
+        }
+    };
+    if pattern.is_empty() {
+        return StatlineCommand::Error("No pattern given, grep takes a pattern and a directory".into());
+    }
+    let dir = match rest.trim() {
+        "" => ".",
+        dir => dir
+    };
+    StatlineCommand::Grep(pattern, dir.to_string())
+}
+
+/// Parses the input of the replace in files prompt, which is "/pattern/replacement/ [directory]". Just like with replace,
+/// any character can be used instead of '/'. Without a directory, the one the editor was started in is replaced in.
+pub fn parse_replace_in_files_args(input: &str) -> StatlineCommand {
+    let input = input.trim_start();
+    let delimiter = match input.chars().next() {
+        Some(ch) => ch,
+        None => return StatlineCommand::Error("No pattern given, replace in files takes /pattern/replacement/ and a directory".into())
+    };
+    let (pattern, rest) = split_delimited(&input[delimiter.len_utf8()..], delimiter);
+    let (replacement, dir) = match rest {
+        Some(rest) => split_delimited(rest, delimiter),
+        None => return StatlineCommand::Error(format!("No replacement given for {}", pattern))
+    };
+    if pattern.is_empty() {
+        return StatlineCommand::Error("No pattern given, replace in files takes /pattern/replacement/ and a directory".into());
+    }
+    let dir = match dir.map(str::trim) {
+        None | Some("") => ".",
+        Some(dir) => dir
+    };
+    StatlineCommand::ReplaceInFiles(pattern, replacement, dir.to_string())
+}
+
+/// Parses the input of the macro playback prompt. Accepted forms are "name", "name 5" (play 5 times),
+/// "name 10,20" (play once on every line from 10 to 20) and "name %" (play once on every line)
+pub fn parse_macro_args(input: &str) -> StatlineCommand {
+    let mut parts = input.split_whitespace();
+    let name = match parts.next() {
+        Some(name) => name.to_string(),
+        None => return StatlineCommand::Error("No macro name given".into())
+    };
+    let repeat = match parts.next() {
+        None => MacroRepeat::Times(1),
+        Some("%") => MacroRepeat::AllLines,
+        Some(arg) if arg.contains(',') => {
+            let mut range = arg.splitn(2, ',').map(|v| v.trim().parse::<usize>());
+            match (range.next(), range.next()) {
+                (Some(Ok(from)), Some(Ok(to))) if from > 0 && from <= to => MacroRepeat::Lines(from, to),
+                _ => return StatlineCommand::Error(format!("Couldn't parse line range from {}", arg))
+            }
+        },
+        Some(arg) => match arg.parse::<usize>() {
+            Ok(n) => MacroRepeat::Times(n),
+            Err(_) => return StatlineCommand::Error(format!("Couldn't parse repeat count from {}", arg))
+        }
+    };
+    StatlineCommand::PlayMacro(name, repeat)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use crate::data::text_buffer::Textbuffer;
+    use crate::cmd::command_engine::{CommandEngine, Operation, Macro};
+    use crate::cmd::undo_tree::{UndoTree, Edit};
+    use crate::cmd::{MoveDir, MoveKind, MacroRepeat, ReplaceScope, SeekFrom, StatlineCommand, Command, find_title, parse_macro_args, parse_replace_args, parse_grep_args, parse_replace_in_files_args};
+    use crate::editor::key::{KeyCode, EscapeKeyCode, Modifiers};
+    use crate::cfg::Config;
+    use crate::data::text_buffer::ObjectKind;
+    use crate::data::search::{SearchOptions, CaseMatching};
+
+    fn type_str(ce: &mut CommandEngine, buf: &Arc<Mutex<Textbuffer>>, s: &str) {
+        for c in s.chars() {
+            let pos = buf.lock().unwrap().get_textpos().absolute;
+            ce.execute(Operation::Insert(pos, c));
+        }
+        ce.break_group();
+    }
+
+    fn contents(buf: &Arc<Mutex<Textbuffer>>) -> String {
+        let guard = buf.lock().unwrap();
+        guard.get_data_range(0, guard.len())
+    }
+
+    #[test]
+    fn test_undo_redo() {
+        let buf = Arc::new(Mutex::new(Textbuffer::new()));
+        let mut ce = CommandEngine::new(buf.clone());
+        type_str(&mut ce, &buf, "a");
+        type_str(&mut ce, &buf, "b");
+        ce.execute(Operation::Undo);
+        assert_eq!("a", contents(&buf));
+        assert_eq!(1, buf.lock().unwrap().get_textpos().absolute);
+        ce.execute(Operation::Redo);
+        assert_eq!("ab", contents(&buf));
+        assert_eq!(2, buf.lock().unwrap().get_textpos().absolute);
+    }
+
+    #[test]
+    fn test_undo_then_type_keeps_branch() {
+        let buf = Arc::new(Mutex::new(Textbuffer::new()));
+        let mut ce = CommandEngine::new(buf.clone());
+        type_str(&mut ce, &buf, "a");
+        type_str(&mut ce, &buf, "b");
+        ce.execute(Operation::Undo);
+        type_str(&mut ce, &buf, "c");
+        assert_eq!("ac", contents(&buf));
+        // "b" is not lost, it lives on the sibling branch
+        ce.execute(Operation::UndoBranch(MoveDir::Previous));
+        assert_eq!("ab", contents(&buf));
+        ce.execute(Operation::UndoBranch(MoveDir::Next));
+        assert_eq!("ac", contents(&buf));
+    }
+
+    #[test]
+    fn test_undo_older_newer() {
+        let buf = Arc::new(Mutex::new(Textbuffer::new()));
+        let mut ce = CommandEngine::new(buf.clone());
+        type_str(&mut ce, &buf, "a");
+        type_str(&mut ce, &buf, "b");
+        ce.execute(Operation::Undo);
+        type_str(&mut ce, &buf, "c");
+        // chronological order of states: "", "a", "ab", "ac"
+        ce.execute(Operation::UndoOlder);
+        assert_eq!("ab", contents(&buf));
+        ce.execute(Operation::UndoOlder);
+        assert_eq!("a", contents(&buf));
+        ce.execute(Operation::UndoNewer);
+        ce.execute(Operation::UndoNewer);
+        assert_eq!("ac", contents(&buf));
+    }
+
+    #[test]
+    fn test_undo_restores_cursor() {
+        let buf = Arc::new(Mutex::new(Textbuffer::new()));
+        let mut ce = CommandEngine::new(buf.clone());
+        type_str(&mut ce, &buf, "hello\nworl");
+        type_str(&mut ce, &buf, "d");
+        buf.lock().unwrap().set_textpos(2);
+        ce.execute(Operation::Remove(2, 'e'));
+        assert_eq!("hllo\nworld", contents(&buf));
+        buf.lock().unwrap().set_textpos(8);
+        ce.execute(Operation::Undo);
+        assert_eq!("hello\nworld", contents(&buf));
+        let tp = buf.lock().unwrap().get_textpos();
+        assert_eq!(2, tp.absolute);
+        ce.execute(Operation::Undo);
+        let tp = buf.lock().unwrap().get_textpos();
+        assert_eq!((10, 1), (tp.absolute, tp.line_index));
+    }
+
+    #[test]
+    fn test_extending_the_root_keeps_the_cursor_before() {
+        let mut tb = Textbuffer::new();
+        tb.insert_data("ab");
+        let before = tb.get_textpos();
+        tb.insert_data("c");
+        let mut history = UndoTree::default();
+        history.extend_current(Edit::Insert(2, "c".into()), before, tb.get_textpos());
+        assert_eq!(Some(2), history.undo(&mut tb).map(|tp| tp.absolute));
+        assert_eq!("ab", tb.dump_to_string());
+    }
+
+    #[test]
+    fn test_typing_is_grouped() {
+        let buf = Arc::new(Mutex::new(Textbuffer::new()));
+        let mut ce = CommandEngine::new(buf.clone());
+        type_str(&mut ce, &buf, "hello ");
+        type_str(&mut ce, &buf, "world");
+        ce.execute(Operation::Remove(11, 'd'));
+        ce.execute(Operation::Remove(10, 'l'));
+        assert_eq!("hello wor", contents(&buf));
+        ce.execute(Operation::Undo);
+        assert_eq!("hello world", contents(&buf));
+        ce.execute(Operation::Undo);
+        assert_eq!("hello ", contents(&buf));
+        ce.execute(Operation::Redo);
+        assert_eq!("hello world", contents(&buf));
+    }
+
+    #[test]
+    fn test_group_breaks_on_cursor_jump() {
+        let buf = Arc::new(Mutex::new(Textbuffer::new()));
+        let mut ce = CommandEngine::new(buf.clone());
+        ce.execute(Operation::InsertData(0, "abc".into()));
+        ce.execute(Operation::Insert(1, 'x'));
+        ce.execute(Operation::Insert(2, 'y'));
+        assert_eq!("axybc", contents(&buf));
+        ce.execute(Operation::Undo);
+        assert_eq!("abc", contents(&buf));
+        ce.execute(Operation::Undo);
+        assert_eq!("", contents(&buf));
+    }
+
+    #[test]
+    fn test_group_breaks_on_pause() {
+        let buf = Arc::new(Mutex::new(Textbuffer::new()));
+        let mut ce = CommandEngine::new(buf.clone());
+        ce.set_group_timeout(std::time::Duration::from_millis(0));
+        ce.execute(Operation::Insert(0, 'a'));
+        ce.execute(Operation::Insert(1, 'b'));
+        ce.execute(Operation::Undo);
+        assert_eq!("a", contents(&buf));
+    }
+
+    #[test]
+    fn test_explicit_group() {
+        let buf = Arc::new(Mutex::new(Textbuffer::new()));
+        let mut ce = CommandEngine::new(buf.clone());
+        type_str(&mut ce, &buf, "abc");
+        ce.begin_group();
+        ce.execute(Operation::Delete(0, 'a'));
+        ce.execute(Operation::Insert(2, 'd'));
+        ce.end_group();
+        assert_eq!("bcd", contents(&buf));
+        ce.execute(Operation::Undo);
+        assert_eq!("abc", contents(&buf));
+    }
+
+    #[test]
+    fn test_macro_record_and_stop() {
+        let buf = Arc::new(Mutex::new(Textbuffer::new()));
+        let mut ce = CommandEngine::new(buf.clone());
+        assert!(ce.execute(Operation::MacroPlay("a".into())).is_err());
+        ce.execute(Operation::MacroRecord("a".into()));
+        assert_eq!(Some("a"), ce.is_recording());
+        for kc in vec![KeyCode::Character('x'), KeyCode::Enter, KeyCode::CtrlR] {
+            ce.record_key(&kc);
+        }
+        ce.execute(Operation::MacroStop);
+        assert_eq!(None, ce.is_recording());
+        assert_eq!(&[KeyCode::Character('x'), KeyCode::Enter], ce.get_macro("a").unwrap().play());
+        assert!(!ce.execute(Operation::MacroPlay("a".into())).is_err());
+        ce.end_macro_play();
+    }
+
+    #[test]
+    fn test_macro_cannot_play_itself() {
+        let buf = Arc::new(Mutex::new(Textbuffer::new()));
+        let mut ce = CommandEngine::new(buf.clone());
+        ce.add_macro("a", Macro::default());
+        ce.add_macro("b", Macro::default());
+        // not while it's being recorded
+        ce.execute(Operation::MacroRecord("a".into()));
+        assert!(ce.execute(Operation::MacroPlay("a".into())).is_err());
+        ce.execute(Operation::MacroStop);
+        // nor from within its own playback, even through another macro
+        assert!(!ce.execute(Operation::MacroPlay("a".into())).is_err());
+        assert!(!ce.execute(Operation::MacroPlay("b".into())).is_err());
+        assert!(ce.execute(Operation::MacroPlay("a".into())).is_err());
+        ce.end_macro_play();
+        ce.end_macro_play();
+        assert!(!ce.execute(Operation::MacroPlay("a".into())).is_err());
+    }
+
+    #[test]
+    fn test_parse_macro_args() {
+        match parse_macro_args("foo") {
+            StatlineCommand::PlayMacro(name, repeat) => assert_eq!(("foo".to_string(), MacroRepeat::Times(1)), (name, repeat)),
+            _ => panic!("expected macro playback")
+        }
+        match parse_macro_args("foo 3") {
+            StatlineCommand::PlayMacro(_, repeat) => assert_eq!(MacroRepeat::Times(3), repeat),
+            _ => panic!("expected macro playback")
+        }
+        match parse_macro_args("foo 10,20") {
+            StatlineCommand::PlayMacro(_, repeat) => assert_eq!(MacroRepeat::Lines(10, 20), repeat),
+            _ => panic!("expected macro playback")
+        }
+        match parse_macro_args("foo %") {
+            StatlineCommand::PlayMacro(_, repeat) => assert_eq!(MacroRepeat::AllLines, repeat),
+            _ => panic!("expected macro playback")
+        }
+        assert!(matches!(parse_macro_args("foo 20,10"), StatlineCommand::Error(_)));
+        assert!(matches!(parse_macro_args(""), StatlineCommand::Error(_)));
+    }
+
+    #[test]
+    fn test_macros_survive_config_serialization() {
+        let buf = Arc::new(Mutex::new(Textbuffer::new()));
+        let mut ce = CommandEngine::new(buf.clone());
+        ce.execute(Operation::MacroRecord("m".into()));
+        ce.record_key(&KeyCode::Character('q'));
+        ce.record_key(&KeyCode::CtrlR);
+        ce.execute(Operation::MacroStop);
+        let mut cfg = Config::default();
+        cfg.set_macro("m", ce.get_macro("m").unwrap().clone());
+        let serialized = serde_json::to_string(&cfg).unwrap();
+        let restored: Config = serde_json::from_str(&serialized).unwrap();
+        assert_eq!(&[KeyCode::Character('q')], restored.macros()["m"].play());
+        // bindings of keys with modifiers survive the round trip as well
+        let ctrl_right = KeyCode::Modified(EscapeKeyCode::Right, Modifiers { ctrl: true, ..Modifiers::default() });
+        assert!(matches!(restored.get_binding(ctrl_right), Some(Command::Move(MoveKind::Word(MoveDir::Next)))));
+    }
+
+    #[test]
+    fn test_cut_and_paste_are_undoable() {
+        let buf = Arc::new(Mutex::new(Textbuffer::new()));
+        let mut ce = CommandEngine::new(buf.clone());
+        ce.execute(Operation::InsertData(0, "one\ntwo\n".into()));
+        buf.lock().unwrap().set_textpos(0);
+        ce.execute(Operation::Cut(ObjectKind::Line));
+        assert_eq!("two\n", contents(&buf));
+        buf.lock().unwrap().set_textpos(4);
+        ce.execute(Operation::Paste);
+        assert_eq!("two\none\n", contents(&buf));
+        ce.execute(Operation::Undo);
+        assert_eq!("two\n", contents(&buf));
+        ce.execute(Operation::Undo);
+        assert_eq!("one\ntwo\n", contents(&buf));
+    }
+
+    #[test]
+    fn test_consecutive_cuts_and_yank_pop() {
+        let buf = Arc::new(Mutex::new(Textbuffer::new()));
+        let mut ce = CommandEngine::new(buf.clone());
+        ce.execute(Operation::InsertData(0, "a\nb\nc\n".into()));
+        buf.lock().unwrap().set_textpos(0);
+        ce.execute(Operation::Copy(ObjectKind::Word));
+        ce.execute(Operation::Cut(ObjectKind::Line));
+        ce.execute(Operation::Cut(ObjectKind::Line));
+        assert_eq!("c\n", contents(&buf));
+        assert_eq!(Some(&"a\nb\n".to_string()), ce.clipboard().last_stored());
+        ce.execute(Operation::Paste);
+        assert_eq!("a\nb\nc\n", contents(&buf));
+        ce.execute(Operation::YankPop);
+        assert_eq!("ac\n", contents(&buf));
+        ce.execute(Operation::Undo);
+        assert_eq!("a\nb\nc\n", contents(&buf));
+        assert!(ce.execute(Operation::YankPop).is_err());
+    }
+
+    #[test]
+    fn test_copy_selection_into_register() {
+        let buf = Arc::new(Mutex::new(Textbuffer::new()));
+        let mut ce = CommandEngine::new(buf.clone());
+        ce.execute(Operation::InsertData(0, "hello world".into()));
+        buf.lock().unwrap().set_textpos(2);
+        buf.lock().unwrap().set_mark();
+        buf.lock().unwrap().set_textpos(7);
+        ce.execute(Operation::SelectRegister('q'));
+        ce.execute(Operation::Copy(ObjectKind::Selection));
+        assert_eq!(Some(&"llo w".to_string()), ce.clipboard().register('q'));
+        assert_eq!(None, buf.lock().unwrap().get_mark());
+        assert!(ce.execute(Operation::Copy(ObjectKind::Selection)).is_err());
+    }
+
+    #[test]
+    fn test_find_title() {
+        let mut options = SearchOptions::default();
+        assert_eq!("[find]: ", find_title(&SeekFrom::Start, &options));
+        options.regex = true;
+        options.case = CaseMatching::Insensitive;
+        options.whole_word = true;
+        assert_eq!("[find back re aa w]: ", find_title(&SeekFrom::End, &options));
+    }
+
+    #[test]
+    fn test_parse_replace_args() {
+        let replace = |input| match parse_replace_args(input) {
+            StatlineCommand::Replace(pattern, replacement, scope, confirm) => Some((pattern, replacement, scope, confirm)),
+            _ => None
+        };
+        assert_eq!(Some(("foo".into(), "bar".into(), ReplaceScope::Selection, false)), replace("/foo/bar/"));
+        assert_eq!(Some(("foo".into(), "".into(), ReplaceScope::Buffer, true)), replace("%/foo//c"));
+        assert_eq!(Some(("a/b".into(), "$1 c".into(), ReplaceScope::Lines(3, 10), false)), replace("3,10/a\\/b/$1 c"));
+        assert_eq!(Some(("(\\d+)".into(), "<$1>".into(), ReplaceScope::Lines(7, 7), false)), replace(" 7 #(\\d+)#<$1>#"));
+        assert_eq!(None, replace("/foo"));
+        assert_eq!(None, replace("//bar/"));
+        assert_eq!(None, replace("10,3/a/b/"));
+        assert_eq!(None, replace("/a/b/x"));
+    }
+
+    #[test]
+    fn test_parse_grep_args() {
+        let grep = |input| match parse_grep_args(input) {
+            StatlineCommand::Grep(pattern, dir) => Some((pattern, dir)),
+            _ => None
+        };
+        assert_eq!(Some(("foo".into(), ".".into())), grep("foo"));
+        assert_eq!(Some(("fn \\w+".into(), "src/cmd".into())), grep(" \"fn \\w+\"  src/cmd "));
+        assert_eq!(Some(("say \"hi\"".into(), ".".into())), grep("\"say \\\"hi\\\"\""));
+        assert_eq!(None, grep(""));
+        assert_eq!(None, grep("\"foo"));
+    }
+
+    #[test]
+    fn test_parse_replace_in_files_args() {
+        let replace = |input| match parse_replace_in_files_args(input) {
+            StatlineCommand::ReplaceInFiles(pattern, replacement, dir) => Some((pattern, replacement, dir)),
+            _ => None
+        };
+        assert_eq!(Some(("foo".into(), "bar".into(), ".".into())), replace("/foo/bar/"));
+        assert_eq!(Some(("a/b".into(), "".into(), "src".into())), replace("#a/b## src"));
+        assert_eq!(Some(("x".into(), "y".into(), ".".into())), replace("/x/y"));
+        assert_eq!(None, replace("/foo"));
+        assert_eq!(None, replace("//bar/"));
+        assert_eq!(None, replace(""));
+    }
+
+    #[test]
+    fn test_replace_is_one_undo_step() {
+        let buf = Arc::new(Mutex::new(Textbuffer::new()));
+        let mut ce = CommandEngine::new(buf.clone());
+        type_str(&mut ce, &buf, "a x b x c x");
+        buf.lock().unwrap().set_textpos(0);
+        ce.execute(Operation::Replace(vec![(2..3, "yy".into()), (6..7, "".into()), (10..11, "z".into())]));
+        assert_eq!("a yy b  c z", contents(&buf));
+        // at the start of the last replacement
+        assert_eq!(10, buf.lock().unwrap().get_textpos().absolute);
+        ce.execute(Operation::Undo);
+        assert_eq!("a x b x c x", contents(&buf));
+        assert_eq!(0, buf.lock().unwrap().get_textpos().absolute);
+        ce.execute(Operation::Redo);
+        assert_eq!("a yy b  c z", contents(&buf));
+        assert!(ce.execute(Operation::Replace(vec![])).is_err());
+    }
+}
@@ -0,0 +1,240 @@
+use crate::data::text_buffer::{Textbuffer, TextPosition};
+use crate::cmd::MoveDir;
+
+type AbsolutePos = usize;
+
+/// The primitive, invertible changes that the undo tree stores. Every Operation that modifies
+/// the buffer is normalized into one of these, so that undoing never has to care about whether
+/// something was typed, pasted, deleted or backspaced.
+#[derive(Clone, Debug)]
+pub enum Edit {
+    Insert(AbsolutePos, String),
+    Delete(AbsolutePos, String),
+}
+
+impl Edit {
+    pub fn inverse(&self) -> Edit {
+        match self {
+            Edit::Insert(pos, data) => Edit::Delete(*pos, data.clone()),
+            Edit::Delete(pos, data) => Edit::Insert(*pos, data.clone()),
+        }
+    }
+
+    pub fn apply(&self, tb: &mut Textbuffer) {
+        match self {
+            Edit::Insert(pos, data) => {
+                tb.set_textpos(*pos);
+                tb.insert_data(data);
+            },
+            Edit::Delete(pos, data) => {
+                tb.set_textpos(*pos);
+                for _ in data.chars() {
+                    tb.delete();
+                }
+            }
+        }
+    }
+}
+
+/// One state of the buffer. The edits are what takes the buffer from the parent state to this one.
+pub struct UndoNode {
+    parent: Option<usize>,
+    children: Vec<usize>,
+    active_child: usize,
+    edits: Vec<Edit>,
+    cursor_before: TextPosition,
+    cursor_after: TextPosition,
+}
+
+/// Undo history that never throws anything away. Undoing and then making a new edit starts a new
+/// branch, instead of clearing the "forward history" like a plain undo stack would.
+/// Nodes are stored in the order they were created, so a node's index doubles as its sequence
+/// number, which is what stepping to older/newer states walks along (like g- and g+ in vim).
+pub struct UndoTree {
+    nodes: Vec<UndoNode>,
+    current: usize,
+}
+
+impl Default for UndoTree {
+    fn default() -> Self {
+        UndoTree::new()
+    }
+}
+
+impl UndoTree {
+    pub fn new() -> UndoTree {
+        UndoTree {
+            nodes: vec![UndoNode {
+                parent: None,
+                children: vec![],
+                active_child: 0,
+                edits: vec![],
+                cursor_before: TextPosition::default(),
+                cursor_after: TextPosition::default()
+            }],
+            current: 0,
+        }
+    }
+
+    /// Records a new state as a child of the current one, and makes it the current state.
+    pub fn push(&mut self, edits: Vec<Edit>, cursor_before: TextPosition, cursor_after: TextPosition) {
+        let idx = self.nodes.len();
+        self.nodes.push(UndoNode {
+            parent: Some(self.current),
+            children: vec![],
+            active_child: 0,
+            edits,
+            cursor_before,
+            cursor_after
+        });
+        let parent = &mut self.nodes[self.current];
+        parent.children.push(idx);
+        parent.active_child = parent.children.len() - 1;
+        self.current = idx;
+    }
+
+    /// Adds an edit to the current state instead of creating a new one. This is what lets a whole
+    /// typed word be undone in one go. Edits that continue each other are merged into one.
+    /// At the root there is no state to add to, so a new one is made, with the cursor where it was before the edit.
+    pub fn extend_current(&mut self, edit: Edit, cursor_before: TextPosition, cursor_after: TextPosition) {
+        if self.current == 0 {
+            self.push(vec![edit], cursor_before, cursor_after);
+            return;
+        }
+        let node = &mut self.nodes[self.current];
+        node.cursor_after = cursor_after;
+        let merged = match (node.edits.last_mut(), &edit) {
+            (Some(Edit::Insert(pos, data)), Edit::Insert(new_pos, new_data)) if *pos + data.chars().count() == *new_pos => {
+                data.push_str(new_data);
+                true
+            },
+            (Some(Edit::Delete(pos, data)), Edit::Delete(new_pos, new_data)) if *pos == *new_pos => {
+                data.push_str(new_data);
+                true
+            },
+            (Some(Edit::Delete(pos, data)), Edit::Delete(new_pos, new_data)) if *new_pos + new_data.chars().count() == *pos => {
+                data.insert_str(0, new_data);
+                *pos = *new_pos;
+                true
+            },
+            _ => false
+        };
+        if !merged {
+            node.edits.push(edit);
+        }
+    }
+
+    pub fn undo(&mut self, tb: &mut Textbuffer) -> Option<TextPosition> {
+        let parent = self.nodes[self.current].parent?;
+        let node = &self.nodes[self.current];
+        for edit in node.edits.iter().rev() {
+            edit.inverse().apply(tb);
+        }
+        let cursor = node.cursor_before.clone();
+        tb.set_textpos(cursor.absolute);
+        let from = self.current;
+        let p = &mut self.nodes[parent];
+        p.active_child = p.children.iter().position(|c| *c == from).unwrap_or(0);
+        self.current = parent;
+        Some(tb.get_textpos())
+    }
+
+    pub fn redo(&mut self, tb: &mut Textbuffer) -> Option<TextPosition> {
+        let node = &self.nodes[self.current];
+        let child = *node.children.get(node.active_child)?;
+        self.enter_child(child, tb);
+        Some(tb.get_textpos())
+    }
+
+    /// Steps to the state that was created right before the current one, regardless of which branch it is on.
+    pub fn older(&mut self, tb: &mut Textbuffer) -> Option<TextPosition> {
+        if self.current == 0 {
+            None
+        } else {
+            self.goto(self.current - 1, tb)
+        }
+    }
+
+    /// Steps to the state that was created right after the current one, regardless of which branch it is on.
+    pub fn newer(&mut self, tb: &mut Textbuffer) -> Option<TextPosition> {
+        if self.current + 1 >= self.nodes.len() {
+            None
+        } else {
+            self.goto(self.current + 1, tb)
+        }
+    }
+
+    /// Moves over to the previous or next sibling branch of the current state.
+    pub fn switch_branch(&mut self, dir: MoveDir, tb: &mut Textbuffer) -> Option<TextPosition> {
+        let parent = self.nodes[self.current].parent?;
+        let siblings = &self.nodes[parent].children;
+        if siblings.len() < 2 {
+            return None;
+        }
+        let idx = siblings.iter().position(|c| *c == self.current).unwrap_or(0);
+        let target = match dir {
+            MoveDir::Next => siblings[(idx + 1) % siblings.len()],
+            MoveDir::Previous => siblings[(idx + siblings.len() - 1) % siblings.len()],
+        };
+        self.goto(target, tb)
+    }
+
+    /// Walks the tree from the current state to `target`, by undoing up to the closest common
+    /// ancestor and then redoing down the target's branch.
+    pub fn goto(&mut self, target: usize, tb: &mut Textbuffer) -> Option<TextPosition> {
+        if target >= self.nodes.len() {
+            return None;
+        }
+        let target_path = self.path_from_root(target);
+        while !target_path.contains(&self.current) {
+            self.undo(tb)?;
+        }
+        let start = target_path.iter().position(|n| *n == self.current).unwrap();
+        for child in target_path[start + 1..].iter() {
+            self.enter_child(*child, tb);
+        }
+        Some(tb.get_textpos())
+    }
+
+    fn enter_child(&mut self, child: usize, tb: &mut Textbuffer) {
+        let parent = &mut self.nodes[self.current];
+        parent.active_child = parent.children.iter().position(|c| *c == child).unwrap_or(0);
+        let node = &self.nodes[child];
+        for edit in node.edits.iter() {
+            edit.apply(tb);
+        }
+        tb.set_textpos(node.cursor_after.absolute);
+        self.current = child;
+    }
+
+    fn path_from_root(&self, node: usize) -> Vec<usize> {
+        let mut path = vec![node];
+        let mut n = node;
+        while let Some(p) = self.nodes[n].parent {
+            path.push(p);
+            n = p;
+        }
+        path.reverse();
+        path
+    }
+
+    /// The current state number, and the total amount of states recorded. The original, unedited buffer is state 0.
+    pub fn state(&self) -> (usize, usize) {
+        (self.current, self.nodes.len() - 1)
+    }
+
+    /// Which branch (1-indexed) among its siblings the current state is on, and how many siblings there are.
+    pub fn branch(&self) -> (usize, usize) {
+        match self.nodes[self.current].parent {
+            Some(p) => {
+                let siblings = &self.nodes[p].children;
+                (siblings.iter().position(|c| *c == self.current).unwrap_or(0) + 1, siblings.len())
+            },
+            None => (1, 1)
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.len() == 1
+    }
+}
@@ -0,0 +1,133 @@
+/// The bracket pairs that blocks are made of
+pub const PAIRS: [(char, char); 4] = [('(', ')'), ('[', ']'), ('{', '}'), ('<', '>')];
+
+/// The pair `ch` is a bracket of, and whether it's the opening one
+fn pair_of(ch: char) -> Option<(char, char, bool)> {
+    PAIRS.iter().find_map(|(open, close)| {
+        if ch == *open {
+            Some((*open, *close, true))
+        } else if ch == *close {
+            Some((*open, *close, false))
+        } else {
+            None
+        }
+    })
+}
+
+/// Whether `ch` is one of the brackets of `PAIRS`
+pub fn is_bracket(ch: char) -> bool {
+    pair_of(ch).is_some()
+}
+
+/// Whether the '<' or '>' at `at` could be an angle bracket, and not a part of ->, =>, <= or >=
+fn is_angle_bracket(text: &[char], at: usize) -> bool {
+    let prev = at.checked_sub(1).map(|i| text[i]);
+    let next = text.get(at + 1).cloned();
+    match text[at] {
+        '<' => next != Some('='),
+        '>' => next != Some('=') && prev != Some('-') && prev != Some('='),
+        _ => false
+    }
+}
+
+/**
+    Which characters of `text` are code, as opposed to being inside of a string or character literal, or a comment.
+    This knows the C family of languages, Rust included: "strings" with escapes, 'c' character literals (as opposed
+    to 'lifetimes), // line comments and (nested) /* block comments */.
+*/
+pub fn code_mask(text: &[char]) -> Vec<bool> {
+    let mut mask = vec![true; text.len()];
+    let mut i = 0;
+    while i < text.len() {
+        let end = match (text[i], text.get(i + 1).cloned()) {
+            ('/', Some('/')) => text[i..].iter().position(|c| *c == '\n').map_or(text.len(), |n| i + n),
+            ('/', Some('*')) => {
+                let mut depth = 0;
+                let mut j = i;
+                while j < text.len() {
+                    match (text[j], text.get(j + 1).cloned()) {
+                        ('/', Some('*')) => { depth += 1; j += 1; },
+                        ('*', Some('/')) => { depth -= 1; j += 1; },
+                        _ => {}
+                    }
+                    j += 1;
+                    if depth == 0 {
+                        break;
+                    }
+                }
+                j
+            },
+            ('"', _) => {
+                let mut j = i + 1;
+                while j < text.len() && text[j] != '"' {
+                    j += if text[j] == '\\' { 2 } else { 1 };
+                }
+                std::cmp::min(j + 1, text.len())
+            },
+            ('\'', Some('\\')) => {
+                // the escaped character may be a quote itself
+                let from = std::cmp::min(i + 3, text.len());
+                text[from..].iter().position(|c| *c == '\'').map_or(i + 1, |n| from + n + 1)
+            },
+            ('\'', Some(_)) if text.get(i + 2) == Some(&'\'') => i + 3,
+            _ => i + 1
+        };
+        if end > i + 1 {
+            mask[i..end].iter_mut().for_each(|m| *m = false);
+        }
+        i = end;
+    }
+    mask
+}
+
+/**
+    The position of the bracket that the one at `at` pairs up with, looking forwards from an opening bracket and
+    backwards from a closing one. Brackets that aren't code (see `code_mask`) are skipped. Angle brackets are only
+    counted when looking for the match of one, since most of them are comparisons and shifts.
+*/
+pub fn matching_bracket(text: &[char], code: &[bool], at: usize) -> Option<usize> {
+    let (open, close, forward) = pair_of(*text.get(at)?)?;
+    let angle = open == '<';
+    if !code[at] || (angle && !is_angle_bracket(text, at)) {
+        return None;
+    }
+    let (target, step): (char, Box<dyn Iterator<Item=usize>>) = if forward {
+        (close, Box::new(at + 1..text.len()))
+    } else {
+        (open, Box::new((0..at).rev()))
+    };
+    let mut expected = vec![];
+    for i in step {
+        let ch = text[i];
+        if !code[i] || ((ch == '<' || ch == '>') && !(angle && is_angle_bracket(text, i))) {
+            continue;
+        }
+        match pair_of(ch) {
+            Some((o, c, opening)) if opening == forward => expected.push(if forward { c } else { o }),
+            Some(_) if expected.last() == Some(&ch) => { expected.pop(); },
+            Some(_) if expected.is_empty() && ch == target => return Some(i),
+            Some(_) => return None,
+            None => {}
+        }
+    }
+    None
+}
+
+/// The innermost bracket pair that `pos` is inside of, or the pair that the bracket at `pos` is a part of
+pub fn enclosing_pair(text: &[char], code: &[bool], pos: usize) -> Option<(usize, usize)> {
+    if let Some(other) = matching_bracket(text, code, pos) {
+        return Some((std::cmp::min(pos, other), std::cmp::max(pos, other)));
+    }
+    // going backwards, pairs that close before `pos` are skipped as a whole, so that only the opening brackets
+    // that may be around `pos` are tried
+    let mut i = std::cmp::min(pos, text.len());
+    while i > 0 {
+        i -= 1;
+        match (pair_of(text[i]), matching_bracket(text, code, i)) {
+            (Some((_, _, true)), Some(close)) if close >= pos => return Some((i, close)),
+            (Some((_, _, false)), Some(open)) => i = open,
+            _ => {}
+        }
+    }
+    None
+}
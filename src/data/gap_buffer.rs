@@ -1,6 +1,6 @@
 use std::ops::Range;
 use std::ptr::copy as copyrange;
-use crate::data::BufferString;
+use crate::data::{BufferString, TextStorage};
 use std::ops::Index;
 use crate::data::text_buffer::Cursor;
 
@@ -264,6 +264,41 @@ impl<T> super::Buffer<T> for GapBuffer<T> {
     }
 }
 
+impl TextStorage for GapBuffer<char> {
+    fn len(&self) -> usize {
+        GapBuffer::len(self)
+    }
+
+    fn get(&self, pos: usize) -> Option<char> {
+        GapBuffer::get(self, pos).cloned()
+    }
+
+    fn insert(&mut self, pos: usize, text: &str) {
+        self.set_gap_position(std::cmp::min(pos, GapBuffer::len(self)));
+        self.map_to(text.chars());
+    }
+
+    fn delete(&mut self, range: Range<usize>) -> String {
+        let end = std::cmp::min(range.end, GapBuffer::len(self));
+        if range.start >= end {
+            return String::new();
+        }
+        self.set_gap_position(range.start);
+        (range.start..end).filter_map(|_| GapBuffer::delete(self)).collect()
+    }
+
+    fn chars(&self, range: Range<usize>) -> Box<dyn DoubleEndedIterator<Item=char> + '_> {
+        let end = std::cmp::min(range.end, GapBuffer::len(self));
+        Box::new((std::cmp::min(range.start, end)..end).filter_map(move |i| GapBuffer::get(self, i).cloned()))
+    }
+
+    fn snapshot(&self) -> Box<dyn TextStorage> {
+        let mut copy = GapBuffer::new();
+        copy.map_to(self.iter().cloned());
+        Box::new(copy)
+    }
+}
+
 impl<T> Drop for GapBuffer<T> {
     fn drop(&mut self) {
         unsafe {
@@ -0,0 +1,86 @@
+use std::ops::Range;
+
+/**
+    Keeps track of where every line in a buffer starts, so that going from a line number to a position in the buffer
+    (and back) is a binary search instead of a scan for newlines. It has to be told about every insertion and removal
+    made to the buffer, which it then adjusts the line starts for.
+    Positions are character indices into the buffer, and line numbers are 0-indexed.
+*/
+#[derive(Clone, Debug)]
+pub struct LineIndex {
+    /// The position where each line starts. The first line always starts at 0, every other line starts right after a newline.
+    starts: Vec<usize>
+}
+
+impl LineIndex {
+    pub fn new() -> LineIndex {
+        LineIndex {
+            starts: vec![0]
+        }
+    }
+
+    pub fn from_text(text: &str) -> LineIndex {
+        let mut index = LineIndex::new();
+        index.insert(0, text);
+        index
+    }
+
+    /// The number of lines, which is always one more than the number of newlines
+    pub fn line_count(&self) -> usize {
+        self.starts.len()
+    }
+
+    /// Where line `line` starts, or None if there aren't that many lines
+    pub fn line_start(&self, line: usize) -> Option<usize> {
+        self.starts.get(line).cloned()
+    }
+
+    /// The position of the newline that ends line `line`, or None if it's the last line (or there aren't that many lines)
+    pub fn line_end(&self, line: usize) -> Option<usize> {
+        self.starts.get(line + 1).map(|next| next - 1)
+    }
+
+    /// The line that position `pos` is on. A newline belongs to the line it ends.
+    pub fn line_of(&self, pos: usize) -> usize {
+        self.starts.partition_point(|start| *start <= pos) - 1
+    }
+
+    /// Adjusts the line starts for `text` having been inserted at `pos`
+    pub fn insert(&mut self, pos: usize, text: &str) {
+        let mut len = 0;
+        let mut new_starts = vec![];
+        for ch in text.chars() {
+            len += 1;
+            if ch == '\n' {
+                new_starts.push(pos + len);
+            }
+        }
+        if len == 0 {
+            return;
+        }
+        let at = self.starts.partition_point(|start| *start <= pos);
+        for start in self.starts[at..].iter_mut() {
+            *start += len;
+        }
+        self.starts.splice(at..at, new_starts);
+    }
+
+    /// Adjusts the line starts for the characters in `range` having been removed
+    pub fn remove(&mut self, range: Range<usize>) {
+        if range.is_empty() {
+            return;
+        }
+        let first = self.starts.partition_point(|start| *start <= range.start);
+        let last = self.starts.partition_point(|start| *start <= range.end);
+        for start in self.starts[last..].iter_mut() {
+            *start -= range.len();
+        }
+        self.starts.drain(first..last);
+    }
+}
+
+impl Default for LineIndex {
+    fn default() -> Self {
+        LineIndex::new()
+    }
+}
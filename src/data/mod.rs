@@ -1,5 +1,10 @@
+pub mod brackets;
 pub mod gap_buffer;
+pub mod line_index;
+pub mod piece_table;
+pub mod search;
 pub mod text_buffer;
+pub mod word;
 use std::fmt::{Display, Formatter as Fmt, Error as FmtError};
 use crate::data::SaveFileError::Other;
 
@@ -40,6 +45,49 @@ pub trait BufferString {
     fn read_string(&self, range: std::ops::Range<usize>) -> String;
 }
 
+/**
+    Where a Textbuffer keeps its text. Positions are character indices, and ranges are end-exclusive.
+    Reads are clamped to the end of the text, so asking for more than there is gives what there is.
+    Storages are Send, so that the buffers keeping them can be shared behind an Arc<Mutex>.
+*/
+pub trait TextStorage: BufferString + Send {
+    fn len(&self) -> usize;
+    fn get(&self, pos: usize) -> Option<char>;
+    /// Inserts `text` so that it starts at `pos`
+    fn insert(&mut self, pos: usize, text: &str);
+    /// Removes the characters in `range`, and returns them
+    fn delete(&mut self, range: std::ops::Range<usize>) -> String;
+    fn chars(&self, range: std::ops::Range<usize>) -> Box<dyn DoubleEndedIterator<Item=char> + '_>;
+    /// A copy of the text as it is right now, that edits made after this don't show up in
+    fn snapshot(&self) -> Box<dyn TextStorage>;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The length of the text, encoded as UTF-8
+    fn byte_len(&self) -> usize {
+        self.chars(0..self.len()).map(char::len_utf8).sum()
+    }
+
+    /// The UTF-8 byte offset of the character at `pos`. Anything past the end is the end of the text.
+    fn char_to_byte(&self, pos: usize) -> usize {
+        self.chars(0..pos).map(char::len_utf8).sum()
+    }
+
+    /// The character that starts at UTF-8 byte offset `byte`. None if it's in the middle of a character, or past the end.
+    fn byte_to_char(&self, byte: usize) -> Option<usize> {
+        let mut offset = 0;
+        for (pos, ch) in self.chars(0..self.len()).enumerate() {
+            if offset >= byte {
+                return if offset == byte { Some(pos) } else { None };
+            }
+            offset += ch.len_utf8();
+        }
+        if offset == byte { Some(self.len()) } else { None }
+    }
+}
+
 
 #[cfg(test)]
 mod tests {
@@ -47,92 +95,428 @@ mod tests {
     // use super::Gap::GapBuffer as GB;
     use super::BufferString;
     use crate::data::text_buffer::Textbuffer;
+    use crate::data::line_index::LineIndex;
+    use crate::data::piece_table::PieceTable;
+    use crate::data::TextStorage;
+    use crate::data::text_buffer::{Cursor, Offset};
 
-    #[test]
-    fn test_gapbuffer_insert() {
-        let mut gb = GB::new();
-        gb.map_to("hello world!".chars());
-        assert_eq!(gb.get(0), Some(&'h'));
+    /// Line starts found by scanning the text, which is what the line index has to agree with
+    fn scanned_line_starts(text: &str) -> Vec<usize> {
+        std::iter::once(0).chain(text.chars().enumerate().filter(|(_, c)| *c == '\n').map(|(i, _)| i + 1)).collect()
     }
 
     #[test]
-    fn test_gapbuffer_read_string() {
-        let mut gb = GB::new();
-        gb.map_to("hello world!".chars());
-        assert_eq!(gb.read_string(0..5), "hello");
+    fn test_line_index() {
+        let mut li = LineIndex::from_text("ab\ncd\n\nef");
+        assert_eq!(4, li.line_count());
+        assert_eq!(Some(7), li.line_start(3));
+        assert_eq!(Some(5), li.line_end(1));
+        assert_eq!(None, li.line_end(3));
+        assert_eq!(0, li.line_of(2));
+        assert_eq!(1, li.line_of(3));
+        assert_eq!(3, li.line_of(100));
+        // "ab\ncd\n\nef" -> "ab\nc\nxyd\n\nef"
+        li.insert(4, "\nxy");
+        assert_eq!(vec![0, 3, 5, 9, 10], (0..li.line_count()).filter_map(|l| li.line_start(l)).collect::<Vec<usize>>());
+        // -> "ab\nc\nxyef"
+        li.remove(8..10);
+        assert_eq!(vec![0, 3, 5], (0..li.line_count()).filter_map(|l| li.line_start(l)).collect::<Vec<usize>>());
     }
 
     #[test]
-    fn test_gapbuffer_dump_to_string() {
-        let mut gb = GB::new();
-        gb.map_to("hello world!".chars());
-        assert_eq!(gb.read_string(0..25), "hello world!");
+    fn test_indentation() {
+        let mut tb = Textbuffer::new();
+        tb.insert_data("fn f() {\n\tif x ( \n    \n  \t");
+        assert_eq!("    ", tb.newline_indentation(8, "    "));
+        assert_eq!("", tb.newline_indentation(3, "    "));
+        assert_eq!("\t  ", tb.newline_indentation(17, "  "));
+        assert_eq!("\t", tb.newline_indentation(13, "  "));
+        // blank lines dedent by a unit, a tab, or what's left of the unit in spaces
+        assert_eq!(4, tb.dedent_width(22, "    "));
+        assert_eq!(2, tb.dedent_width(20, "    "));
+        assert_eq!(1, tb.dedent_width(26, "    "));
+        assert_eq!(0, tb.dedent_width(23, "    "));
+        assert_eq!(0, tb.dedent_width(10, "    "));
     }
 
     #[test]
-    fn test_insert_move_insert() {
-        let mut gb = GB::new();
-        gb.map_to("hello world!".chars());
-        assert_eq!(gb.read_string(0..25), "hello world!");
-        gb.set_gap_position(6);
-        gb.map_to("fucking ".chars());
-        let a = gb.read_string(0..25);
-        assert_eq!(gb.read_string(0..25), "hello fucking world!");
+    fn test_word_motions() {
+        use crate::cmd::MoveDir::{Next, Previous};
+        use crate::data::word::WordChars;
+        let mut tb = Textbuffer::new();
+        tb.insert_data("let x_1 = a.b(); // größe\n  end");
+        let starts: Vec<usize> = std::iter::successors(Some(0), |p| Some(tb.word_motion(*p, &Next, false, false)))
+            .take_while(|p| *p < tb.len()).collect();
+        assert_eq!(vec![0, 4, 8, 10, 11, 12, 13, 17, 20, 28], starts);
+        assert_eq!(vec![0, 4, 8, 10, 17, 20, 28], std::iter::successors(Some(0), |p| Some(tb.word_motion(*p, &Next, true, false)))
+            .take_while(|p| *p < tb.len()).collect::<Vec<usize>>());
+        assert_eq!(13, tb.word_motion(17, &Previous, false, false));
+        assert_eq!(10, tb.word_motion(17, &Previous, true, false));
+        assert_eq!(2, tb.word_motion(0, &Next, false, true));
+        assert_eq!(6, tb.word_motion(2, &Next, false, true));
+        assert_eq!(15, tb.word_motion(13, &Next, false, true));
+        assert_eq!(15, tb.word_motion(17, &Previous, false, true));
+        assert_eq!(0, tb.word_motion(1, &Previous, false, true));
+        assert_eq!(30, tb.word_motion(28, &Next, false, true));
+        assert_eq!(31, tb.word_motion(30, &Next, false, true));
+        // underscores and non-ASCII letters are configurable
+        tb.set_word_chars(WordChars { extra: String::new(), unicode_letters: false });
+        assert_eq!(5, tb.word_motion(4, &Next, false, false));
+        assert_eq!(22, tb.word_motion(20, &Next, false, false));
     }
 
     #[test]
-    fn test_insert_lines() {
-
+    fn test_brackets() {
+        use crate::data::brackets::{code_mask, matching_bracket, enclosing_pair};
+        let text: Vec<char> = "f(a, \"(\", ')', 'b) { x[1] } // )\n".chars().collect();
+        let code = code_mask(&text);
+        assert!(code[1] && !code[6] && !code[11] && code[16] && code[17] && !code[31]);
+        assert_eq!(Some(17), matching_bracket(&text, &code, 1));
+        assert_eq!(Some(1), matching_bracket(&text, &code, 17));
+        // without skipping literals, the brackets in the string and the character literal pair up
+        assert_eq!(None, matching_bracket(&text, &code, 6));
+        assert_eq!(Some(11), matching_bracket(&text, &vec![true; text.len()], 6));
+        assert_eq!(Some((22, 24)), enclosing_pair(&text, &code, 23));
+        assert_eq!(Some((19, 26)), enclosing_pair(&text, &code, 21));
+        assert_eq!(Some((1, 17)), enclosing_pair(&text, &code, 4));
+        assert_eq!(None, enclosing_pair(&text, &code, 28));
+        // angle brackets, but not arrows and comparisons
+        let text: Vec<char> = "fn f<T: Fn() -> u8>(x: T) { x() > 1 }".chars().collect();
+        let code = code_mask(&text);
+        assert_eq!(Some(18), matching_bracket(&text, &code, 4));
+        assert_eq!(Some((4, 18)), enclosing_pair(&text, &code, 8));
+        assert_eq!(Some((26, 36)), enclosing_pair(&text, &code, 33));
+        // pairs that close before the position are passed over
+        let text: Vec<char> = "{ (a) [b<c>] x }".chars().collect();
+        assert_eq!(Some((0, 15)), enclosing_pair(&text, &vec![true; text.len()], 13));
+        // buffers only match brackets within a window of lines around the position
+        let mut tb = Textbuffer::new();
+        tb.insert_data(&format!("({})", "\n".repeat(2000)));
+        assert_eq!(None, tb.matching_bracket_near(0));
+        assert_eq!(None, tb.matching_bracket_near(2001));
+        tb.insert_data("()");
+        assert_eq!(Some(2002), tb.matching_bracket_near(2003));
+        assert_eq!(Some((2002, 2003)), tb.find_block(2003));
     }
 
     #[test]
-    fn test_remove_char() {
-        let mut gb = GB::new();
-        gb.map_to("hello world!".chars());
-        let c = gb.remove();
-        assert_eq!("hello world", gb.read_string(0..25));
+    fn test_block_objects() {
+        use crate::data::text_buffer::{ObjectKind, RangeType};
+        use crate::cmd::MoveKind;
+        let mut tb = Textbuffer::new();
+        tb.insert_data("if (a[i] == b) { c }");
+        assert_eq!(Some(6..7), tb.find_range_of(Cursor::Absolute(6), ObjectKind::Block(RangeType::FullExclusive)));
+        assert_eq!(Some(4..13), tb.find_range_of(Cursor::Absolute(9), ObjectKind::Block(RangeType::FullExclusive)));
+        assert_eq!(Some(3..14), tb.find_range_of(Cursor::Absolute(9), ObjectKind::Block(RangeType::FullInclusive)));
+        assert_eq!(Some(3..13), tb.find_range_of(Cursor::Absolute(9), ObjectKind::Block(RangeType::EndExclusive)));
+        assert_eq!(None, tb.find_range_of(Cursor::Absolute(1), ObjectKind::Block(RangeType::FullInclusive)));
+        // on a bracket, or right after one
+        tb.set_textpos(15);
+        assert_eq!(19, tb.move_cursor(MoveKind::MatchingBracket).unwrap().absolute);
+        tb.set_textpos(14);
+        assert_eq!(3, tb.move_cursor(MoveKind::MatchingBracket).unwrap().absolute);
     }
 
     #[test]
-    fn test_insert_newline() {
-        let mut gb = GB::new();
-        gb.map_to("hello wor".chars());
-        gb.set_gap_position(5);
-        // gb.delete();
-        let a = gb.get(5);
-        gb.insert('\n');
-        assert_eq!("hello\n wor", gb.read_string(0..25));
+    fn test_search() {
+        use crate::data::search::{Search, SearchOptions, CaseMatching, next_match};
+        let mut tb = Textbuffer::new();
+        tb.insert_data("Foo föo foo_bar\nfoo(x) = FOO.foo");
+        let literal = SearchOptions::default();
+        // smart case: lower case matches any case, upper case only itself
+        assert_eq!(vec![0..3, 8..11, 16..19, 25..28, 29..32], tb.find_all(&Search::new("foo", &literal).unwrap()));
+        assert_eq!(vec![0..3], tb.find_all(&Search::new("Foo", &literal).unwrap()));
+        let sensitive = SearchOptions { case: CaseMatching::Sensitive, ..literal.clone() };
+        assert_eq!(vec![8..11, 16..19, 29..32], tb.find_all(&Search::new("foo", &sensitive).unwrap()));
+        let whole_word = SearchOptions { whole_word: true, ..sensitive.clone() };
+        assert_eq!(vec![16..19, 29..32], tb.find_all(&Search::new("foo", &whole_word).unwrap()));
+        // the regex is matched line by line, characters are counted and \W isn't upper case
+        let regex = SearchOptions { regex: true, ..literal.clone() };
+        assert_eq!(vec![0..4, 4..8, 16..20, 25..29], tb.find_all(&Search::new("f.o\\W", &regex).unwrap()));
+        assert_eq!(vec![0..3, 16..19], tb.find_all(&Search::new("^foo", &regex).unwrap()));
+        assert_eq!(vec![12..15, 29..32], tb.find_all(&Search::new("[a-z]+$", &regex).unwrap()));
+        assert_eq!(vec![0..4, 12..16, 16..20], tb.find_all(&Search::new("(bar|foo)\\s|foo\\(", &regex).unwrap()));
+        assert!(Search::new("foo(", &regex).is_err());
+        // capture groups in replacements, only for regular expressions
+        assert_eq!(vec![(16..22, "x:foo".to_string())], tb.replacements(&Search::new("(foo)\\((\\w)\\)", &regex).unwrap(), "$2:$1"));
+        assert_eq!(vec![(16..20, "$1".to_string())], tb.replacements(&Search::new("foo(", &literal).unwrap(), "$1"));
+        assert_eq!(vec![16..20], tb.find_all(&Search::new("foo(", &literal).unwrap()));
+        // wrapping around in both directions
+        let matches = vec![2..4, 10..12];
+        assert_eq!(Some((0, false)), next_match(&matches, 2, true));
+        assert_eq!(Some((1, false)), next_match(&matches, 3, true));
+        assert_eq!(Some((0, true)), next_match(&matches, 11, true));
+        assert_eq!(Some((0, false)), next_match(&matches, 10, false));
+        assert_eq!(Some((1, true)), next_match(&matches, 2, false));
+        assert_eq!(None, next_match(&[], 0, true));
     }
 
     #[test]
-    fn test_remove_world_from_hello_world() {
-        let mut gb = GB::new();
-        gb.map_to("hello world".chars());
-        gb.set_gap_position(6);
-        for i in 0..5 {
-            gb.delete();
-        }
-        assert_eq!("hello ", gb.read_string(0..25));
+    fn test_dirty_and_save() {
+        use crate::editor::FileOpt;
+        let file = std::env::temp_dir().join(format!("cxedit-save-{}.txt", std::process::id()));
+        let mut tb = Textbuffer::new();
+        assert!(!tb.is_dirty());
+        tb.insert_data("hello");
+        assert!(tb.is_dirty());
+        tb.set_pristine();
+        tb.set_textpos(0);
+        tb.delete();
+        assert!(tb.is_dirty());
+        assert_eq!(4, tb.save_to_file(&file, None).unwrap());
+        // an existing file is only written over when asked to
+        tb.insert_ch('j');
+        assert!(tb.save_to_file(&file, None).is_err());
+        assert!(tb.save_to_file(&file, Some(FileOpt::NoOverwrite)).is_err());
+        assert_eq!("ello", std::fs::read_to_string(&file).unwrap());
+        tb.save_to_file(&file, Some(FileOpt::Overwrite)).unwrap();
+        assert_eq!("jello", std::fs::read_to_string(&file).unwrap());
+        std::fs::remove_file(&file).unwrap();
     }
 
     #[test]
-    fn test_replace_world_with_simon() {
-        let mut gb = GB::new();
-        gb.map_to("hello world".chars());
-        gb.set_gap_position(6);
-        for i in 0..5 {
-            gb.delete();
-        }
-        let simon: String = "Simon".into();
-        gb.map_to(simon.chars());
-        assert_eq!("hello Simon", gb.read_string(0..25));
+    fn test_mark_follows_edits() {
+        let mut tb = Textbuffer::new();
+        tb.insert_data("hello world");
+        tb.set_textpos(6);
+        tb.set_mark();
+        // edits before the mark move it along with the text, edits after it don't
+        tb.set_textpos(0);
+        tb.insert_data(">> ");
+        tb.insert_ch('!');
+        tb.delete();
+        assert_eq!(Some(9), tb.get_mark());
+        tb.set_textpos(14);
+        tb.insert_ch('s');
+        tb.remove();
+        assert_eq!(Some(9), tb.get_mark());
+        assert_eq!("world", tb.get_data(9..14));
+        // text typed at the mark goes after it, so the selection grows
+        tb.set_textpos(9);
+        tb.insert_data("new ");
+        assert_eq!(Some(9), tb.get_mark());
+        // and a mark inside of removed text ends up where that text was
+        tb.set_textpos(11);
+        tb.remove();
+        tb.remove();
+        tb.remove();
+        assert_eq!(Some(8), tb.get_mark());
+        assert_eq!(">> !ellow world", tb.dump_to_string());
     }
 
     #[test]
-    fn test_text_buffer() {
-        let mut tb = Textbuffer::new();
-        tb.insert_data("hello Simon");
-        assert_eq!("hello Simon", tb.get_data_range(0, 25));
+    fn test_piece_table_pieces_are_bounded() {
+        let text = format!("{}x", "é".repeat(3000));
+        let pt = PieceTable::from_text(text.clone());
+        assert_eq!(3, pt.piece_count());
+        assert_eq!(Some('é'), pt.get(2999));
+        assert_eq!(Some('x'), pt.get(3000));
+        assert_eq!(6000, pt.char_to_byte(3000));
+        assert_eq!(Some(3000), pt.byte_to_char(6000));
+        assert_eq!(text, pt.read_string(0..pt.len()));
+        // typing appends to the same piece until it's full
+        let mut pt = PieceTable::new();
+        (0..1500).for_each(|pos| pt.insert(pos, "ü"));
+        assert_eq!(2, pt.piece_count());
+        assert_eq!("ü".repeat(1500), pt.read_string(0..pt.len()));
     }
 
-}
\ No newline at end of file
+    /// Tests that every storage backend has to pass, through the TextStorage trait and through a Textbuffer using it
+    macro_rules! storage_tests {
+        ($name:ident, $storage:expr) => {
+            mod $name {
+                use super::*;
+
+                fn storage() -> Box<dyn TextStorage> {
+                    Box::new($storage)
+                }
+
+                fn buffer() -> Textbuffer {
+                    Textbuffer::with_storage(storage())
+                }
+
+                #[test]
+                fn test_insert_and_read() {
+                    let mut st = storage();
+                    assert!(st.is_empty());
+                    st.insert(0, "hello world!");
+                    assert!(!st.is_empty());
+                    assert_eq!(Some('h'), st.get(0));
+                    assert_eq!(None, st.get(12));
+                    assert_eq!("hello", st.read_string(0..5));
+                    assert_eq!("hello world!", st.read_string(0..25));
+                    assert_eq!("", st.read_string(20..25));
+                    assert_eq!("!dlrow", st.chars(6..12).rev().collect::<String>());
+                }
+
+                #[test]
+                fn test_insert_move_insert() {
+                    let mut st = storage();
+                    st.insert(0, "hello world!");
+                    st.insert(6, "dear ");
+                    st.insert(0, "oh, ");
+                    st.insert(st.len(), "\n");
+                    assert_eq!("oh, hello dear world!\n", st.read_string(0..st.len()));
+                    assert_eq!(22, st.len());
+                }
+
+                #[test]
+                fn test_replace_world_with_simon() {
+                    let mut st = storage();
+                    st.insert(0, "hello world");
+                    assert_eq!("world", st.delete(6..11));
+                    assert_eq!("hello ", st.read_string(0..25));
+                    st.insert(6, "Simon");
+                    assert_eq!("hello Simon", st.read_string(0..25));
+                    assert_eq!("lo S", st.delete(3..7));
+                    assert_eq!("helimon", st.read_string(0..25));
+                    assert_eq!("", st.delete(10..12));
+                }
+
+                #[test]
+                fn test_multibyte_characters() {
+                    let mut st = storage();
+                    st.insert(0, "blåbær");
+                    st.insert(3, "ø");
+                    assert_eq!(7, st.len());
+                    assert_eq!(Some('ø'), st.get(3));
+                    assert_eq!(Some('b'), st.get(4));
+                    assert_eq!("æ", st.delete(5..6));
+                    assert_eq!("blåøbr", st.read_string(0..st.len()));
+                }
+
+                #[test]
+                fn test_byte_offsets() {
+                    let mut st = storage();
+                    st.insert(0, "a€b😀\nc");
+                    assert_eq!(11, st.byte_len());
+                    assert_eq!(vec![0, 1, 4, 5, 9, 10, 11], (0..7).map(|pos| st.char_to_byte(pos)).collect::<Vec<usize>>());
+                    assert_eq!(11, st.char_to_byte(100));
+                    assert_eq!(Some(2), st.byte_to_char(4));
+                    assert_eq!(Some(4), st.byte_to_char(9));
+                    assert_eq!(Some(5), st.byte_to_char(10));
+                    assert_eq!(Some(6), st.byte_to_char(11));
+                    assert_eq!(None, st.byte_to_char(2));
+                    assert_eq!(None, st.byte_to_char(12));
+                }
+
+                #[test]
+                fn test_snapshot() {
+                    let mut st = storage();
+                    st.insert(0, "hello");
+                    let snapshot = st.snapshot();
+                    st.insert(5, " world");
+                    st.delete(0..1);
+                    assert_eq!("hello", snapshot.read_string(0..snapshot.len()));
+                    assert_eq!("ello world", st.read_string(0..st.len()));
+                }
+
+                #[test]
+                fn test_text_positions_in_every_unit() {
+                    let mut tb = buffer();
+                    tb.insert_data("hi\na€😀b\n");
+                    // 'b' is character 6, byte 11, UTF-16 code unit 4 and display column 4 (the emoji is wide) on line 1
+                    assert_eq!(11, tb.byte_offset(6));
+                    assert_eq!((1, 4), tb.utf16_column(6));
+                    assert_eq!((1, 4), tb.display_column(6));
+                    assert_eq!((4, 1), Cursor::Absolute(6).to_row_col(&tb));
+                    for offset in vec![Offset::Char(6), Offset::Byte(11), Offset::Utf16(1, 4), Offset::LineColumn(1, 4)] {
+                        let tp = tb.text_position(offset).unwrap();
+                        assert_eq!((6, 3, 1), (tp.absolute, tp.line_start_absolute, tp.line_index));
+                    }
+                    // in the middle of a character, and past the end of a line or the buffer
+                    assert!(tb.text_position(Offset::Byte(5)).is_none());
+                    assert_eq!(2, tb.text_position(Offset::LineColumn(0, 10)).unwrap().absolute);
+                    assert!(tb.text_position(Offset::LineColumn(3, 0)).is_none());
+                    assert!(tb.text_position(Offset::Char(9)).is_none());
+                }
+
+                #[test]
+                fn test_grapheme_motion_and_backspace() {
+                    use crate::cmd::{MoveKind, MoveDir};
+                    let mut tb = buffer();
+                    // an 'e' with a combining accent, and a wide CJK character followed by a wide emoji
+                    tb.insert_data("e\u{301}x\n中😀\n");
+                    tb.set_textpos(0);
+                    assert_eq!(2, tb.move_cursor(MoveKind::Char(MoveDir::Next)).unwrap().absolute);
+                    assert_eq!(0, tb.move_cursor(MoveKind::Char(MoveDir::Previous)).unwrap().absolute);
+                    tb.set_textpos(3);
+                    assert_eq!(4, tb.move_cursor(MoveKind::Char(MoveDir::Next)).unwrap().absolute);
+                    assert_eq!((1, 4), tb.display_column(6));
+                    // up and down keep the display column, landing on the start of a wide character
+                    tb.set_textpos(2);
+                    assert_eq!(4, tb.move_cursor(MoveKind::Line(MoveDir::Next)).unwrap().absolute);
+                    tb.set_textpos(5);
+                    assert_eq!(3, tb.move_cursor(MoveKind::Line(MoveDir::Previous)).unwrap().absolute);
+                    tb.set_textpos(2);
+                    assert_eq!(Some("e\u{301}".to_string()), tb.remove());
+                    assert_eq!("x\n中😀\n", tb.dump_to_string());
+                    assert_eq!(0, tb.get_textpos().absolute);
+                }
+
+                #[test]
+                fn test_tab_stops() {
+                    use crate::cmd::{MoveKind, MoveDir};
+                    let mut tb = buffer();
+                    tb.insert_data("\tx\nab\tc\nabcdefgh");
+                    assert_eq!((0, 4), tb.display_column(1));
+                    assert_eq!((1, 4), tb.display_column(6));
+                    tb.set_tab_width(8);
+                    assert_eq!((0, 8), tb.display_column(1));
+                    // a column inside of a tab is the tab itself
+                    assert_eq!(0, tb.text_position(Offset::LineColumn(0, 5)).unwrap().absolute);
+                    tb.set_textpos(15);
+                    assert_eq!(5, tb.move_cursor(MoveKind::Line(MoveDir::Previous)).unwrap().absolute);
+                }
+
+                #[test]
+                fn test_text_buffer() {
+                    let mut tb = buffer();
+                    tb.insert_data("hello Simon");
+                    assert_eq!("hello Simon", tb.get_data_range(0, 25));
+                }
+
+                #[test]
+                fn test_line_start_0_idx() {
+                    let mut tb = buffer();
+                    tb.insert_data("ab\ncd\n\nef");
+                    assert_eq!(0, tb.get_line_start_0_idx(0).unwrap().absolute);
+                    assert_eq!(3, tb.get_line_start_0_idx(1).unwrap().absolute);
+                    assert_eq!(7, tb.get_line_start_0_idx(3).unwrap().absolute);
+                    assert!(tb.get_line_start_0_idx(4).is_none());
+                }
+
+                #[test]
+                fn test_text_buffer_line_index_follows_edits() {
+                    let mut tb = buffer();
+                    tb.insert_data("fn main() {\n    let x = 1;\n}\n");
+                    tb.set_textpos(12);
+                    tb.insert_ch('\n');
+                    tb.insert_data("    // å\n");
+                    tb.set_textpos(5);
+                    tb.remove();
+                    tb.delete();
+                    tb.set_textpos(tb.len());
+                    tb.remove();
+                    tb.remove();
+                    let text = tb.dump_to_string();
+                    assert_eq!("fn mn() {\n\n    // å\n    let x = 1;\n", text);
+                    let starts = scanned_line_starts(&text);
+                    assert_eq!(starts.len(), tb.line_count());
+                    for (line, start) in starts.iter().enumerate() {
+                        assert_eq!(*start, tb.get_line_start_0_idx(line).unwrap().absolute);
+                    }
+                    for pos in 0..=tb.len() {
+                        let tp = tb.get_text_position_info(pos);
+                        let line = starts.iter().rposition(|s| *s <= pos).unwrap();
+                        assert_eq!((line, starts[line]), (tp.line_index, tp.line_start_absolute));
+                    }
+                    assert!(tb.get_line_start_0_idx(starts.len()).is_none());
+                }
+            }
+        }
+    }
+
+    storage_tests!(gap_buffer, GB::<char>::new());
+    storage_tests!(piece_table, PieceTable::new());
+}
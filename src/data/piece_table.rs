@@ -0,0 +1,281 @@
+use std::ops::Range;
+use std::sync::Arc;
+use crate::data::{BufferString, TextStorage};
+
+/// The most characters a piece holds. Finding a character in a piece that isn't all ASCII walks through it, so
+/// this is what keeps that cheap, in files loaded as a whole too.
+const MAX_PIECE_CHARS: usize = 1024;
+
+/// A run of text, that is a part of one of the strings the piece table has been given.
+#[derive(Clone)]
+struct Piece {
+    text: Arc<String>,
+    /// byte range of the piece in `text`
+    bytes: Range<usize>,
+    chars: usize
+}
+
+impl Piece {
+    fn new(text: String) -> Piece {
+        let chars = text.chars().count();
+        Piece {
+            bytes: 0..text.len(),
+            text: Arc::new(text),
+            chars
+        }
+    }
+
+    /// Pieces of `text`, of at most `MAX_PIECE_CHARS` characters each, which share the one string
+    fn chunks(text: String) -> Vec<Piece> {
+        if text.chars().count() <= MAX_PIECE_CHARS {
+            return vec![Piece::new(text)];
+        }
+        let text = Arc::new(text);
+        let mut bounds: Vec<usize> = text.char_indices().step_by(MAX_PIECE_CHARS).map(|(b, _)| b).collect();
+        bounds.push(text.len());
+        bounds.windows(2).map(|b| Piece {
+            text: text.clone(),
+            bytes: b[0]..b[1],
+            chars: text[b[0]..b[1]].chars().count()
+        }).collect()
+    }
+
+    fn as_str(&self) -> &str {
+        &self.text[self.bytes.clone()]
+    }
+
+    /// Byte offset into the piece, of the character with index `idx` in the piece
+    fn byte_offset(&self, idx: usize) -> usize {
+        if self.bytes.len() == self.chars {
+            idx
+        } else {
+            self.as_str().char_indices().nth(idx).map_or(self.bytes.len(), |(b, _)| b)
+        }
+    }
+
+    /// The part of the piece between the characters `from` and `to`
+    fn slice(&self, from: usize, to: usize) -> Piece {
+        let start = self.bytes.start + self.byte_offset(from);
+        let end = self.bytes.start + self.byte_offset(to);
+        Piece {
+            text: self.text.clone(),
+            bytes: start..end,
+            chars: to - from
+        }
+    }
+
+    /// Appends to the piece, if nothing else (another piece, or a snapshot) shares the string it is a part of,
+    /// the piece is at the end of that string and it doesn't grow past `MAX_PIECE_CHARS`. Returns whether it could.
+    fn try_append(&mut self, text: &str, chars: usize) -> bool {
+        if self.bytes.end != self.text.len() || self.chars + chars > MAX_PIECE_CHARS {
+            return false;
+        }
+        match Arc::get_mut(&mut self.text) {
+            Some(s) => {
+                s.push_str(text);
+                self.bytes.end = s.len();
+                self.chars += chars;
+                true
+            },
+            None => false
+        }
+    }
+}
+
+/**
+    Text stored as a sequence of pieces, each pointing into a string that is never changed once something else
+    refers to it. An edit only adds, removes or splits pieces, so it never moves the text around, and a snapshot
+    only has to copy the list of pieces. Text is kept as UTF-8, so it costs a byte per (ASCII) character, and
+    converting between character and byte offsets is a binary search over the pieces, and then a walk through
+    at most `MAX_PIECE_CHARS` characters. Typing in one place mostly appends to the same piece, so the list of pieces stays short.
+*/
+#[derive(Clone)]
+pub struct PieceTable {
+    pieces: Vec<Piece>,
+    /// the character position each piece starts at
+    starts: Vec<usize>,
+    /// the byte offset each piece starts at
+    byte_starts: Vec<usize>,
+    len: usize,
+    byte_len: usize
+}
+
+impl PieceTable {
+    pub fn new() -> PieceTable {
+        PieceTable {
+            pieces: vec![],
+            starts: vec![],
+            byte_starts: vec![],
+            len: 0,
+            byte_len: 0
+        }
+    }
+
+    pub fn from_text(text: String) -> PieceTable {
+        let mut pt = PieceTable::new();
+        if !text.is_empty() {
+            pt.pieces = Piece::chunks(text);
+            pt.update_starts(0);
+        }
+        pt
+    }
+
+    /// How many pieces the text is made up of
+    pub fn piece_count(&self) -> usize {
+        self.pieces.len()
+    }
+
+    /// The piece that position `pos` is in, and the position's index within that piece
+    fn locate(&self, pos: usize) -> Option<(usize, usize)> {
+        if pos >= self.len {
+            return None;
+        }
+        let idx = self.starts.partition_point(|start| *start <= pos) - 1;
+        Some((idx, pos - self.starts[idx]))
+    }
+
+    /// Recalculates where the pieces start, from piece `from` and on
+    fn update_starts(&mut self, from: usize) {
+        self.starts.truncate(from);
+        self.byte_starts.truncate(from);
+        let (mut pos, mut byte) = match from {
+            0 => (0, 0),
+            _ => (self.starts[from - 1] + self.pieces[from - 1].chars, self.byte_starts[from - 1] + self.pieces[from - 1].bytes.len())
+        };
+        for piece in self.pieces[from..].iter() {
+            self.starts.push(pos);
+            self.byte_starts.push(byte);
+            pos += piece.chars;
+            byte += piece.bytes.len();
+        }
+        self.len = pos;
+        self.byte_len = byte;
+    }
+
+    fn clamp(&self, range: Range<usize>) -> Range<usize> {
+        let end = std::cmp::min(range.end, self.len);
+        std::cmp::min(range.start, end)..end
+    }
+}
+
+impl Default for PieceTable {
+    fn default() -> Self {
+        PieceTable::new()
+    }
+}
+
+impl TextStorage for PieceTable {
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn get(&self, pos: usize) -> Option<char> {
+        let (idx, offset) = self.locate(pos)?;
+        let piece = &self.pieces[idx];
+        if piece.bytes.len() == piece.chars {
+            Some(piece.as_str().as_bytes()[offset] as char)
+        } else {
+            piece.as_str().chars().nth(offset)
+        }
+    }
+
+    fn insert(&mut self, pos: usize, text: &str) {
+        if text.is_empty() {
+            return;
+        }
+        let pos = std::cmp::min(pos, self.len);
+        let chars = text.chars().count();
+        let idx = match self.locate(pos) {
+            Some((idx, 0)) => idx,
+            Some((idx, offset)) => {
+                let piece = self.pieces[idx].clone();
+                self.pieces[idx] = piece.slice(0, offset);
+                self.pieces.insert(idx + 1, piece.slice(offset, piece.chars));
+                idx + 1
+            },
+            None => self.pieces.len()
+        };
+        if idx > 0 && self.pieces[idx - 1].try_append(text, chars) {
+            self.update_starts(idx - 1);
+        } else {
+            self.pieces.splice(idx..idx, Piece::chunks(text.to_string()));
+            self.update_starts(idx);
+        }
+    }
+
+    fn delete(&mut self, range: Range<usize>) -> String {
+        let range = self.clamp(range);
+        if range.is_empty() {
+            return String::new();
+        }
+        let removed = self.read_string(range.clone());
+        let (first, _) = self.locate(range.start).unwrap();
+        let mut kept = vec![];
+        for (piece, start) in self.pieces[first..].iter().zip(self.starts[first..].iter()) {
+            let end = start + piece.chars;
+            if *start >= range.end {
+                kept.push(piece.clone());
+                continue;
+            }
+            if *start < range.start {
+                kept.push(piece.slice(0, range.start - start));
+            }
+            if end > range.end {
+                kept.push(piece.slice(range.end - start, piece.chars));
+            }
+        }
+        self.pieces.truncate(first);
+        self.pieces.extend(kept);
+        self.update_starts(first);
+        removed
+    }
+
+    fn chars(&self, range: Range<usize>) -> Box<dyn DoubleEndedIterator<Item=char> + '_> {
+        let Range { start: from, end: to } = self.clamp(range);
+        Box::new(self.pieces.iter().zip(self.starts.iter())
+            .filter(move |(piece, start)| **start < to && **start + piece.chars > from)
+            .flat_map(move |(piece, start)| {
+                let from = from.saturating_sub(*start);
+                let to = std::cmp::min(to - start, piece.chars);
+                let s = piece.as_str();
+                s[piece.byte_offset(from)..piece.byte_offset(to)].chars()
+            }))
+    }
+
+    fn snapshot(&self) -> Box<dyn TextStorage> {
+        Box::new(self.clone())
+    }
+
+    fn byte_len(&self) -> usize {
+        self.byte_len
+    }
+
+    fn char_to_byte(&self, pos: usize) -> usize {
+        match self.locate(pos) {
+            Some((idx, offset)) => self.byte_starts[idx] + self.pieces[idx].byte_offset(offset),
+            None => self.byte_len
+        }
+    }
+
+    fn byte_to_char(&self, byte: usize) -> Option<usize> {
+        if byte >= self.byte_len {
+            return if byte == self.byte_len { Some(self.len) } else { None };
+        }
+        let idx = self.byte_starts.partition_point(|start| *start <= byte) - 1;
+        let piece = &self.pieces[idx];
+        let offset = byte - self.byte_starts[idx];
+        if !piece.as_str().is_char_boundary(offset) {
+            None
+        } else if piece.bytes.len() == piece.chars {
+            Some(self.starts[idx] + offset)
+        } else {
+            Some(self.starts[idx] + piece.as_str()[..offset].chars().count())
+        }
+    }
+}
+
+impl BufferString for PieceTable {
+    fn read_string(&self, range: Range<usize>) -> String {
+        self.chars(range).collect()
+    }
+}
@@ -0,0 +1,155 @@
+use std::ops::Range;
+use regex::{Captures, Regex, RegexBuilder};
+use crate::data::word::{WordChars, CharClass};
+use crate::{Deserialize, Serialize};
+
+/// How letter case is matched. Smart case matches case-insensitively, unless the pattern has an upper case letter in it.
+#[derive(Clone, Copy, Deserialize, Serialize, Debug, PartialEq)]
+pub enum CaseMatching {
+    Sensitive,
+    Insensitive,
+    Smart
+}
+
+/// How search patterns are matched against the text
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct SearchOptions {
+    /// whether patterns are regular expressions, or text to be found as is
+    #[serde(default)]
+    pub regex: bool,
+    #[serde(default = "default_case")]
+    pub case: CaseMatching,
+    /// whether matches have to be whole words, i.e. not have word characters right before or after them
+    #[serde(default)]
+    pub whole_word: bool
+}
+
+fn default_case() -> CaseMatching {
+    CaseMatching::Smart
+}
+
+impl Default for SearchOptions {
+    fn default() -> Self {
+        SearchOptions {
+            regex: false,
+            case: default_case(),
+            whole_word: false
+        }
+    }
+}
+
+/// A compiled search pattern
+#[derive(Debug, Clone)]
+pub struct Search {
+    pattern: String,
+    regex: Regex,
+    /// whether the pattern is a regular expression, which is what makes $1 and the like in replacements capture groups
+    expand: bool,
+    whole_word: bool
+}
+
+impl Search {
+    /// Compiles `pattern` as the options say. A pattern that isn't a valid regular expression is an error,
+    /// which says what's wrong with it.
+    pub fn new(pattern: &str, options: &SearchOptions) -> Result<Search, String> {
+        let source = if options.regex { pattern.to_string() } else { regex::escape(pattern) };
+        let case_insensitive = match options.case {
+            CaseMatching::Sensitive => false,
+            CaseMatching::Insensitive => true,
+            CaseMatching::Smart => !has_upper_case(pattern, options.regex)
+        };
+        let regex = RegexBuilder::new(&source)
+            .case_insensitive(case_insensitive)
+            .multi_line(true)
+            .build()
+            .map_err(|e| format!("Invalid pattern {}: {}", pattern, e))?;
+        Ok(Search { pattern: pattern.to_string(), regex, expand: options.regex, whole_word: options.whole_word })
+    }
+
+    pub fn pattern(&self) -> &str {
+        &self.pattern
+    }
+
+    /// The character ranges of every match in `text`, in order. Empty matches are left out, as there would be
+    /// nothing to move to or show.
+    pub fn find_all(&self, text: &str, word_chars: &WordChars) -> Vec<Range<usize>> {
+        let mut matches = vec![];
+        self.for_each_match(text, word_chars, |range, _| matches.push(range));
+        matches
+    }
+
+    /// Every match in `text`, like `find_all`, together with what `replacement` makes of it. In a regular expression
+    /// search $1, ${name} and so on are replaced by what the groups captured, otherwise the replacement is taken as is.
+    pub fn replacements(&self, text: &str, word_chars: &WordChars, replacement: &str) -> Vec<(Range<usize>, String)> {
+        let mut replacements = vec![];
+        self.for_each_match(text, word_chars, |range, caps| {
+            let mut with = String::new();
+            if self.expand {
+                caps.expand(replacement, &mut with);
+            } else {
+                with.push_str(replacement);
+            }
+            replacements.push((range, with));
+        });
+        replacements
+    }
+
+    fn for_each_match<F: FnMut(Range<usize>, &Captures)>(&self, text: &str, word_chars: &WordChars, mut f: F) {
+        let is_word = |ch: Option<char>| ch.is_some_and(|ch| word_chars.class(ch, false) == CharClass::Word);
+        // byte offsets are turned into character positions as we go, matches come in order
+        let (mut byte, mut pos) = (0, 0);
+        let mut char_pos = |to: usize| {
+            pos += text[byte..to].chars().count();
+            byte = to;
+            pos
+        };
+        for caps in self.regex.captures_iter(text) {
+            let m = caps.get(0).unwrap();
+            if m.start() == m.end() {
+                continue;
+            }
+            if self.whole_word && (is_word(text[..m.start()].chars().next_back()) || is_word(text[m.end()..].chars().next())) {
+                continue;
+            }
+            let start = char_pos(m.start());
+            let end = char_pos(m.end());
+            f(start..end, &caps);
+        }
+    }
+}
+
+/// Whether there's an upper case letter in `pattern`. In a regular expression, what comes right after a backslash
+/// is left out, since \W, \S and the like aren't letters to match.
+fn has_upper_case(pattern: &str, regex: bool) -> bool {
+    let mut chars = pattern.chars();
+    while let Some(ch) = chars.next() {
+        if regex && ch == '\\' {
+            chars.next();
+        } else if ch.is_uppercase() {
+            return true;
+        }
+    }
+    false
+}
+
+/**
+    Which of `matches` a search starting at `pos` ends up at: the first one starting at or after `pos` when searching
+    forwards, the last one starting before it when searching backwards. If there is none in that direction, the search
+    wraps around the buffer, which the second value says. None if there are no matches at all.
+*/
+pub fn next_match(matches: &[Range<usize>], pos: usize, forward: bool) -> Option<(usize, bool)> {
+    if matches.is_empty() {
+        return None;
+    }
+    if forward {
+        match matches.iter().position(|m| m.start >= pos) {
+            Some(idx) => Some((idx, false)),
+            None => Some((0, true))
+        }
+    } else {
+        match matches.iter().rposition(|m| m.start < pos) {
+            Some(idx) => Some((idx, false)),
+            None => Some((matches.len() - 1, true))
+        }
+    }
+}
@@ -1,30 +1,39 @@
-use crate::data::gap_buffer::GapBuffer;
+use crate::data::piece_table::PieceTable;
+use crate::data::line_index::LineIndex;
 use crate::editor::view::View;
 use std::cmp::Ordering;
-use crate::cmd::MoveKind;
+use crate::cmd::{MoveKind, MoveDir};
 use crate::cmd::MoveDir::{Next, Previous};
-use crate::data::BufferString;
+use crate::data::word::{WordChars, CharClass};
+use crate::data::brackets::{code_mask, is_bracket, matching_bracket, enclosing_pair};
+use crate::data::search::Search;
+use crate::data::TextStorage;
 use std::sync::Arc;
 use crate::comms::observer::EventListener;
 use crate::comms::observer::Event;
 use crate::comms::observer::EventData;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::fs::File;
 use std::io::Write;
 pub use crate::data::FileResult;
 use crate::data::SaveFileError;
 use crate::editor::FileOpt;
-use std::error::Error;
 
 use std::ops::Range;
 use crate::{Deserialize, Serialize};
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthChar;
 #[derive(Clone, Deserialize, Serialize, Debug)]
 pub enum ObjectKind {
     Word,
     Line,
-    Block
+    /// The innermost bracket pair around the cursor. The range type says which of the brackets are a part of it:
+    /// FullExclusive is what's inside of the brackets, FullInclusive is that and the brackets.
+    Block(RangeType),
+    Selection
 }
 
+#[derive(Clone, Copy, Deserialize, Serialize, Debug, PartialEq)]
 pub enum RangeType {
     FullInclusive, // "hello world" means world, has position (6, 10), since 10 is included, but it is NOT the length of "world", that 10-6 != 5
     EndExclusive, // "hello world" means world, has position (6, 11), since 11 is not included, but it is the length of "world", since 11-6 = 5
@@ -108,6 +117,47 @@ impl Default for TextPosition {
     }
 }
 
+/// A position in a buffer, in one of the units that positions get counted in
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Offset {
+    /// character index, which is what TextPosition::absolute is
+    Char(usize),
+    /// UTF-8 byte offset, like compilers report
+    Byte(usize),
+    /// 0-indexed line, and UTF-16 code unit in that line, like LSP positions
+    Utf16(usize, usize),
+    /// 0-indexed line, and display column in that line
+    LineColumn(usize, usize)
+}
+
+/**
+    How many columns a grapheme cluster takes up on screen. That's the width of its first character (the others are
+    combining marks, joiners and the like), except that an emoji presentation selector makes it a wide emoji.
+    Anything but a newline takes up at least one column, since it does get drawn.
+*/
+pub fn grapheme_width(g: &str) -> usize {
+    match g {
+        "\n" | "\r\n" => 0,
+        _ if g.contains('\u{fe0f}') => 2,
+        _ => g.chars().next().and_then(|c| c.width()).unwrap_or(1).max(1)
+    }
+}
+
+/// The column after grapheme cluster `g`, when it is drawn at column `col`. A tab reaches up to the next tab stop,
+/// which are `tab_width` columns apart.
+pub fn next_column(col: usize, g: &str, tab_width: usize) -> usize {
+    match g {
+        "\t" => (col / tab_width.max(1) + 1) * tab_width.max(1),
+        _ => col + grapheme_width(g)
+    }
+}
+
+/// How many columns a tab takes up, unless the buffer has been told otherwise
+pub const DEFAULT_TAB_WIDTH: usize = 4;
+
+/// How many lines before and after a position brackets are matched among, so that huge buffers stay responsive
+const BRACKET_WINDOW_LINES: usize = 1000;
+
 #[derive(Clone, Copy)]
 pub enum Cursor {
     Absolute(usize),
@@ -120,54 +170,99 @@ pub enum SeekDir {
 }
 
 impl Cursor {
+    /// The display column and the line of the cursor, both 0-indexed
     pub fn to_row_col(&self, tb: &Textbuffer) -> (usize, usize) {
-        match self {
-            Cursor::Absolute(pos) => {
-                let text_pos = tb.get_text_position_info(*pos);
-                (pos - text_pos.line_start_absolute, text_pos.line_index)
-            },
-            _ => {
-                (0, 0)
-            }
-        }
+        let pos = match self {
+            Cursor::Absolute(pos) => *pos,
+            Cursor::Buffer => tb.get_absolute_cursor_pos()
+        };
+        let (line, col) = tb.display_column(pos);
+        (col, line)
     }
 }
 
 pub struct Textbuffer {
-    data: GapBuffer<char>,
+    data: Box<dyn TextStorage>,
     _scratch: Vec<String>,
     observer: Option<Arc<View>>,
     cursor: TextPosition,
+    mark: Option<usize>,
     dirty: bool,
-    pub line_count: usize
+    lines: LineIndex,
+    tab_width: usize,
+    word_chars: WordChars,
+    brackets_skip_literals: bool,
+    read_only: bool,
+    file_name: Option<PathBuf>
 }
 
 impl Textbuffer {
     pub fn new() -> Textbuffer {
-        let gb = GapBuffer::new();
-        let mut tp = TextPosition::new();
-        tp.absolute = gb.get_pos();
+        Textbuffer::with_storage(Box::new(PieceTable::new()))
+    }
+
+    /// Creates a buffer that keeps its text in `storage`, which is expected to be empty
+    pub fn with_storage(storage: Box<dyn TextStorage>) -> Textbuffer {
         Textbuffer {
-            cursor: tp,
-            data: gb,
+            cursor: TextPosition::new(),
+            data: storage,
             _scratch: Vec::new(),
             observer: None,
+            mark: None,
             dirty: false,
-            line_count: 1,
+            lines: LineIndex::new(),
+            tab_width: DEFAULT_TAB_WIDTH,
+            word_chars: WordChars::default(),
+            brackets_skip_literals: true,
+            read_only: false,
+            file_name: None
         }
     }
 
     pub fn get_textpos(&self) -> TextPosition {
         self.cursor.clone()
     }
-    pub fn get_gap_textpos(&self) -> TextPosition {
-        self.get_text_position_info(self.data.get_pos())
+
+    /// A copy of the buffer's text as it is right now, which later edits don't change
+    pub fn snapshot(&self) -> Box<dyn TextStorage> {
+        self.data.snapshot()
     }
 
     pub fn set_textpos(&mut self, pos: usize) {
         if pos <= self.len() {
             self.cursor = self.get_text_position_info(pos);
-            self.data.set_gap_position(pos);
+        }
+    }
+
+    /// Sets the mark at the cursor. The selection is everything between the mark and the cursor.
+    pub fn set_mark(&mut self) {
+        self.mark = Some(self.cursor.absolute);
+    }
+
+    pub fn clear_mark(&mut self) {
+        self.mark = None;
+    }
+
+    pub fn get_mark(&self) -> Option<usize> {
+        self.mark
+    }
+
+    /// Keeps the mark on the character it was set at, when `len` characters are inserted at `pos`. Text inserted at
+    /// the mark itself goes after it.
+    fn shift_mark_on_insert(&mut self, pos: usize, len: usize) {
+        if let Some(mark) = self.mark.as_mut().filter(|mark| **mark > pos) {
+            *mark += len;
+        }
+    }
+
+    /// Keeps the mark on the character it was set at, when `range` is removed. A mark inside of it ends up where it was.
+    fn shift_mark_on_remove(&mut self, range: Range<usize>) {
+        if let Some(mark) = self.mark.as_mut() {
+            if *mark >= range.end {
+                *mark -= range.len();
+            } else if *mark > range.start {
+                *mark = range.start;
+            }
         }
     }
 
@@ -179,6 +274,25 @@ impl Textbuffer {
         self.dirty = false;
     }
 
+    /// Whether the buffer is only there to be looked at, like a list of search results. The command engine
+    /// refuses to edit a read-only buffer, the editor itself can still fill it.
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    pub fn set_read_only(&mut self, read_only: bool) {
+        self.read_only = read_only;
+    }
+
+    /// The file the buffer was opened from, or last saved to
+    pub fn file_name(&self) -> Option<&Path> {
+        self.file_name.as_deref()
+    }
+
+    pub fn set_file_name(&mut self, file_name: &Path) {
+        self.file_name = Some(file_name.to_path_buf());
+    }
+
 
     /// Ranges in rust are by default end-exclusive. So "word" is data, in the index span of a range between 0..4.
     /// This is also convenient for keeping track of the word's length, as it is always the end-boundary (word.len() == 4)
@@ -193,7 +307,7 @@ impl Textbuffer {
                 let found_start_pos =
                 match self.get_at(start) {
                     Some(ch) if ch.is_whitespace() => {
-                        let p = (start..self.len()).into_iter().position(|idx| !self.data[idx].is_whitespace());
+                        let p = (start..self.len()).into_iter().position(|idx| !self.is_whitespace_at(idx));
                         if p.is_none() {
                             return None;
                         } else {
@@ -202,7 +316,7 @@ impl Textbuffer {
                     },
                     Some(ch) if !ch.is_whitespace() => {
                         (0..start).into_iter().rposition(|idx| {
-                            self.data[idx].is_whitespace()
+                            self.is_whitespace_at(idx)
                         }).and_then(|v| Some(v+1)).unwrap_or(0)
                     },
                     _ => return None
@@ -213,7 +327,7 @@ impl Textbuffer {
                     },
                     Some(ch) if !ch.is_whitespace() => {
                         (found_start_pos+1 .. self.len()).into_iter().position(|idx| {
-                            self.data[idx].is_whitespace()
+                            self.is_whitespace_at(idx)
                         }).and_then(|v| Some(found_start_pos+1+v)).unwrap_or(self.len())
                     },
                     _ => return None
@@ -225,17 +339,15 @@ impl Textbuffer {
                     if c == '\n' {
                         let end_pos = start;
                         let start_pos = (0..start).into_iter().rposition(|i| {
-                            let ch = self.data[i];
-                            ch == '\n'
+                            self.data.get(i) == Some('\n')
                         }).and_then(|v| Some(v+1)).unwrap_or(0);
                         return Some(start_pos..end_pos+1);
                     } else {
                         let start_pos = (0..start).into_iter().rposition(|i| {
-                            let ch = self.data[i];
-                            ch == '\n'
+                            self.data.get(i) == Some('\n')
                         }).and_then(|v| Some(v+1)).unwrap_or(0);
                         let end_pos = (start..self.len()).into_iter().position(|v| {
-                            self.data[v] == '\n'
+                            self.data.get(v) == Some('\n')
                         }).and_then(|v| Some(start+v)).unwrap_or(self.len()-1);
                         return Some(start_pos..end_pos+1);
                     }
@@ -243,16 +355,28 @@ impl Textbuffer {
                     None
                 }
             },
-            ObjectKind::Block => {
-                return None;
+            ObjectKind::Block(range_type) => {
+                let (open, close) = self.find_block(start)?;
+                match range_type {
+                    RangeType::FullExclusive => Some(open + 1..close),
+                    RangeType::EndExclusive => Some(open..close),
+                    RangeType::FullInclusive => Some(open..close + 1)
+                }
+            },
+            ObjectKind::Selection => {
+                let mark = std::cmp::min(self.mark?, self.len());
+                if mark == start {
+                    None
+                } else {
+                    Some(std::cmp::min(mark, start)..std::cmp::max(mark, start))
+                }
             }
         }
     }
 
     pub fn get_line_at_cursor(&self) -> String {
-        let len = self.len();
-        let line_begin_absolute = (0..self.data.get_pos()).into_iter().rposition(|idx| self.data[idx] == '\n').and_then(|pos| Some(pos+1)).unwrap_or(0usize);
-        let line_end_absolute = (self.data.get_pos()..self.data.len()).into_iter().position(|idx| self.data[idx] == '\n' || idx == len-1).and_then(|pos| Some(pos+1)).unwrap_or(self.data.len());
+        let line_begin_absolute = self.lines.line_start(self.lines.line_of(self.cursor.absolute)).unwrap_or(0);
+        let line_end_absolute = self.find_next_line_abs_offset(self.cursor.absolute).unwrap_or(self.data.len());
         self.data.read_string(line_begin_absolute..line_end_absolute)
     }
 
@@ -271,8 +395,7 @@ impl Textbuffer {
     }
 
     pub fn get_line_number(&self) -> usize {
-        let lv1: Vec<usize> = (0..self.data.get_pos()).into_iter().rev().filter(|idx| self.data[*idx] == '\n').collect();
-        lv1.len()
+        self.lines.line_of(self.cursor.absolute)
     }
 
     pub fn get_line_number_editing(&self) -> usize {
@@ -280,18 +403,13 @@ impl Textbuffer {
     }
 
     pub fn get_text_position_info(&self, pos: usize) -> TextPosition {
-        let mut tp = TextPosition::new();
-        let lv: Vec<usize> = (0..pos).into_iter().rev().filter(|i| self.data[*i] == '\n').collect();
-        let lineno = lv.len();
-        tp.line_start_absolute = (0..pos).into_iter().rposition(|i| self.data[i] == '\n').and_then(|pos| Some(pos + 1)).unwrap_or(0usize);
-        tp.line_index = lineno;
-        tp.absolute = pos;
-        tp
+        let line = self.lines.line_of(pos);
+        TextPosition::from((pos, self.lines.line_start(line).unwrap_or(0), line))
     }
 
     /**
     * The line number is 0-indexed. When converting a line in the text document, to a line on screen,
-    * for writing characters to screen, use ViewCursor::from(&TextPosition) to convert to proper
+    * for writing characters to screen, use ViewCursor::at(&Textbuffer, &TextPosition) to convert to proper
     * terminal row/cell indexing. (which uses a 1,1 index).
     */
     pub fn get_line_end_pos(&self, line: usize) -> Option<TextPosition> {
@@ -299,19 +417,14 @@ impl Textbuffer {
         if buf_len == 0 {
             return Some(TextPosition::default());
         }
-        let mut line_counter = 0;
-        let nlcp = (0..self.data.len()).into_iter().take_while(|index| {
-            if self.data[*index] == '\n' {
-                line_counter += 1;
-            }
-            line_counter <= line+1
-        }).collect::<Vec<usize>>().into_iter().filter(|i| self.data[*i] == '\n').collect::<Vec<usize>>();
-        let line_end = *nlcp.last().unwrap_or(&buf_len);
-        let line_begin =
-            (0..line_end).into_iter()
-                .rposition(|i| self.data[i] == '\n').and_then(|v| Some(v+1)).unwrap_or(0usize);
-        // println!("\x1b[25;10H Line begin: {} End: {}: #{}", line_begin, line_end, line);
-        Some(TextPosition::from((line_end, line_begin, nlcp.len())))
+        // the newlines up to and including the one ending `line`
+        let newlines = std::cmp::min(self.lines.line_count() - 1, line + 1);
+        if newlines == 0 {
+            return Some(TextPosition::from((buf_len, 0, 0)));
+        }
+        let line_end = self.lines.line_end(newlines - 1)?;
+        let line_begin = self.lines.line_start(newlines - 1)?;
+        Some(TextPosition::from((line_end, line_begin, newlines)))
     }
 
     pub fn get_line_end_pos_0_idx(&self, line: usize) -> Option<TextPosition> {
@@ -319,108 +432,36 @@ impl Textbuffer {
         if buf_len == 0 {
             return Some(TextPosition::default());
         }
-        let vec = (0..self.len()).into_iter().filter(|uidx|{
-            self.data[*uidx] == '\n'
-        }).collect::<Vec<usize>>();
-
-        let line_end = vec.get(line).unwrap();
-        let line_begin =
-            (0..*line_end).into_iter()
-                .rposition(|i| self.data[i] == '\n').and_then(|v| Some(v+1)).unwrap_or(0usize);
-        // println!("\x1b[25;10H Line begin: {} End: {}: #{}", line_begin, line_end, line);
-        Some(TextPosition::from((*line_end, line_begin, line)))
+        let line_end = self.lines.line_end(line)?;
+        let line_begin = self.lines.line_start(line)?;
+        Some(TextPosition::from((line_end, line_begin, line)))
     }
 
-    pub fn get_line_end_abs(&self, line_number: usize) -> Option<TextPosition> {
-        let len = self.data.len();
-        let lines_endings: Vec<usize> =
-            (0..self.data.len())
-                .into_iter()
-                .filter(|idx| self.data[*idx] == '\n' || *idx == len-1)
-                .collect::<Vec<usize>>().into_iter().enumerate().take_while(|(index, _)| {
-                index <= &(line_number+1)
-            }).map(|(_, l)| {
-                l
-            }).collect();
-
-        if lines_endings.len() == line_number-1 && line_number -1 > 0 {
-            let linebegin = lines_endings[line_number-1]+1;
-            let line_end = (linebegin..self.data.len()).into_iter().rposition(|idx| self.data[idx] == '\n' || idx == len-1).unwrap_or(len);
-            return Some(TextPosition::from((line_end, linebegin, line_number-1)));
-        }
-
-        // lines_endings.get(line_number).unwrap_or(len)
-
-        if line_number > lines_endings.len() && lines_endings.len() == 0 {
-
-            Some(TextPosition::from((self.data.len(), 0, 0)))
-        } else if line_number > lines_endings.len() {
-            let this_line_abs = lines_endings[lines_endings.len()-1]; // here it means, this value is self.data.len()
-            Some(TextPosition::from((self.data.len(), this_line_abs, lines_endings.len())))
-        } else if line_number == lines_endings.len() {
-            let this_line_abs = lines_endings[line_number-1]; // here it means, this value is self.data.len()
-            Some(TextPosition::from((self.data.len(), this_line_abs, lines_endings.len())))
-        } else {
-            let this_line_abs = lines_endings[line_number]; // here it means, this value is self.data.len()
-            Some(TextPosition::from((lines_endings[line_number+1], this_line_abs, lines_endings.len())))
-        }
+    /// Start of the line with the 0-indexed line number `line`, or None if the buffer doesn't have that many lines.
+    pub fn get_line_start_0_idx(&self, line: usize) -> Option<TextPosition> {
+        self.lines.line_start(line).map(|start| TextPosition::from((start, start, line)))
     }
 
+    /// Start of the line with the 1-indexed line number `line_number`, or the start of the buffer if there is no such line.
     pub fn get_line_abs_index(&self, line_number: usize) -> Option<TextPosition> {
-        let lines: Vec<usize> =
-            (0..self.len())
-                .into_iter()
-                .filter(|&index| index == 0 || self.data[index] == '\n')
-                .collect();
-        let line_pos = lines.get(line_number-1).and_then(|&value| Some(value+1)).unwrap_or(0usize);
-        Some(TextPosition::from((line_pos, line_pos, line_number-1)))
+        let line = line_number.saturating_sub(1);
+        let line_pos = self.lines.line_start(line).unwrap_or(0usize);
+        Some(TextPosition::from((line_pos, line_pos, line)))
     }
 
+    /// Start of the line with the 1-indexed line number `line_number`, or the end of the buffer if there is no such line.
     pub fn get_line_abs_end_index(&self, line_number: usize) -> Option<TextPosition> {
-        let lines: Vec<usize> =
-            (0..self.len())
-                .into_iter()
-                .filter(|&index| index == 0 || self.data[index] == '\n')
-                .collect();
-        let line_pos = lines.get(line_number-1).and_then(|&value| Some(value+1)).unwrap_or(self.len());
-        Some(TextPosition::from((line_pos, line_pos, line_number-1)))
-    }
-
-    pub fn get_line_abs_end_index2(&self, line_number: usize) -> Option<TextPosition> {
-        let lines: Vec<usize> =
-            (0..self.len())
-                .into_iter()
-                .filter(|&index| self.data[index] == '\n')
-                .collect();
-        let line_pos = lines.get(line_number).and_then(|&value| Some(value+1)).unwrap_or(0usize);
-        Some(TextPosition::from((line_pos, line_pos, line_number)))
-    }
-
-    pub fn get_line_start_abs(&self, line_number: usize) -> Option<TextPosition> {
-        let lines_endings: Vec<usize> =
-            (0..self.data.len())
-                .into_iter()
-                .filter(|idx| self.data[*idx] == '\n')
-                .collect::<Vec<usize>>().into_iter().enumerate().take_while(|(index, _)| {
-                index <= &(line_number)
-            }).map(|(_, l)| {
-                l
-            }).collect();
-        if line_number > lines_endings.len() && lines_endings.len() == 0 {
-            Some(TextPosition::from((self.len(), 0, 0)))
-        } else if line_number >= lines_endings.len() {
-            let this_line_abs = lines_endings.last().and_then(|v| Some(v+1)).unwrap_or(0);
-            Some(TextPosition::from((this_line_abs, this_line_abs, lines_endings.len()-1)))
-        } else {
-            let this_line_abs = lines_endings[lines_endings.len()-1] + 1;
-            Some(TextPosition::from((this_line_abs, this_line_abs, line_number)))
-        }
+        let line = line_number.saturating_sub(1);
+        let line_pos = self.lines.line_start(line).unwrap_or(self.len());
+        Some(TextPosition::from((line_pos, line_pos, line)))
     }
 
     pub fn insert_data(&mut self, data: &str) {
-        self.data.set_gap_position(self.cursor.absolute);
-        self.data.map_to(data.chars());
-        self.cursor.absolute += data.len();
+        self.data.insert(self.cursor.absolute, data);
+        self.lines.insert(self.cursor.absolute, data);
+        self.shift_mark_on_insert(self.cursor.absolute, data.chars().count());
+        self.cursor = self.get_text_position_info(self.cursor.absolute + data.chars().count());
+        self.dirty = true;
     }
 
     pub fn get_absolute_cursor_pos(&self) -> usize {
@@ -428,11 +469,13 @@ impl Textbuffer {
     }
 
     pub fn insert_ch(&mut self, ch: char) {
-        self.data.set_gap_position(self.cursor.absolute);
-        self.data.insert(ch);
+        let text = ch.encode_utf8(&mut [0; 4]).to_string();
+        self.data.insert(self.cursor.absolute, &text);
+        self.lines.insert(self.cursor.absolute, &text);
+        self.shift_mark_on_insert(self.cursor.absolute, 1);
+        self.dirty = true;
         if ch == '\n' {
-            self.line_count += 1;
-            self.cursor = self.get_text_position_info(self.data.get_pos());
+            self.cursor = self.get_text_position_info(self.cursor.absolute + 1);
         } else {
             self.cursor.absolute += 1;
         }
@@ -444,150 +487,254 @@ impl Textbuffer {
     pub fn move_cursor(&mut self, movement: MoveKind) -> Option<TextPosition>  {
         match movement {
             MoveKind::Char(dir) => {
-                match dir {
-                    Previous => {
-                        if self.cursor.absolute > 0 {
-                            if self.cursor.absolute-1 < self.cursor.line_start_absolute {
-                                self.cursor = self.get_text_position_info(self.cursor.absolute-1);
-                            } else {
-                                self.cursor.absolute -= 1;
-                            }
-                        }
-                        Some(self.cursor.clone())
-                    },
-                    Next => {
-                        if self.cursor.absolute < self.data.len() {
-                            self.cursor.absolute += 1;
-                            if let Some(ch) = self.data.get(self.cursor.absolute-1) {
-                                if *ch == '\n' {
-                                    self.cursor.line_start_absolute = self.cursor.absolute;
-                                    self.cursor.line_index += 1;
-                                }
-                            }
-                        }
-                        Some(self.cursor.clone())
-                    }
-                }
+                // a "character" here is what the user sees as one, which is a grapheme cluster
+                let pos = match dir {
+                    Previous => self.prev_grapheme_boundary(self.cursor.absolute),
+                    Next => self.next_grapheme_boundary(self.cursor.absolute)
+                };
+                self.cursor = self.get_text_position_info(pos);
+                Some(self.cursor.clone())
             },
-            MoveKind::Word(dir) => {
-                match dir {
-                    Previous => {},
-                    Next => {}
-                }
+            MoveKind::Word(ref dir) | MoveKind::BigWord(ref dir) | MoveKind::WordEnd(ref dir) | MoveKind::BigWordEnd(ref dir) => {
+                let big = matches!(movement, MoveKind::BigWord(_) | MoveKind::BigWordEnd(_));
+                let end = matches!(movement, MoveKind::WordEnd(_) | MoveKind::BigWordEnd(_));
+                let pos = self.word_motion(self.cursor.absolute, dir, big, end);
+                self.cursor = self.get_text_position_info(pos);
                 Some(self.cursor.clone())
             },
-            MoveKind::Line(dir) => {
-                let column_pos = self.cursor.get_line_position();
-                match dir {
-                    Previous => {
-                        if self.cursor.absolute > 0 && self.cursor.line_index > 0 {
-                            if let Some(next_line_start) = self.find_prev_line_abs_offset(self.cursor.absolute) {
-                                self.cursor.line_index -= 1;
-                                let begin = self.find_prev_line_abs_offset(next_line_start).and_then(|val| Some(val+1)).unwrap_or(0);
-                                self.cursor.line_start_absolute = begin;
-                                let line_len = next_line_start - begin;
-                                if column_pos > line_len {
-                                    self.cursor.absolute = next_line_start;
-                                } else {
-                                    self.cursor.absolute = begin + column_pos;
-                                }
-                            }
-                        }
-                    },
-                    Next => {
-                        if self.cursor.absolute < self.len() && (self.cursor.line_index + 1) < self.line_count {
-                            if let Some(next_line_start) = self.find_next_line_abs_offset(self.cursor.absolute) {
-                                self.cursor.line_index += 1;
-                                self.cursor.line_start_absolute = next_line_start;
-                                let end = self.find_next_line_abs_offset(next_line_start).and_then(|val| Some(val-1)).unwrap_or(self.len());
-                                let line_len = end - next_line_start;
-                                if column_pos > line_len {
-                                    self.cursor.absolute = next_line_start + line_len;
-                                } else {
-                                    self.cursor.absolute = next_line_start + column_pos;
-                                }
-                            }
-                        }
-                    }
+            MoveKind::MatchingBracket => {
+                if let Some(pos) = self.matching_bracket_near(self.cursor.absolute) {
+                    self.cursor = self.get_text_position_info(pos);
                 }
                 Some(self.cursor.clone())
+            },
+            MoveKind::Line(dir) => Some(self.move_lines(&dir, 1)),
+            // how many lines a page is, is up to the view; see move_lines
+            MoveKind::Page(_) => None,
+            MoveKind::LineStart | MoveKind::LineEnd => {
+                let line = self.lines.line_of(self.cursor.absolute);
+                let pos = match movement {
+                    MoveKind::LineStart => self.lines.line_start(line).unwrap_or(0),
+                    _ => self.lines.line_end(line).unwrap_or(self.len())
+                };
+                self.cursor = self.get_text_position_info(pos);
+                Some(self.cursor.clone())
+            },
+            MoveKind::BufferStart | MoveKind::BufferEnd => {
+                let pos = if let MoveKind::BufferStart = movement { 0 } else { self.len() };
+                self.cursor = self.get_text_position_info(pos);
+                Some(self.cursor.clone())
             }
         }
     }
 
-    pub fn find_prev_line_abs_offset(&self, current: usize) -> Option<usize> {
-        (0..current).into_iter().rposition(|ch_idx| {
-            let a = self.data.get(ch_idx);
-            if a.is_some() && *a.unwrap() == '\n' {
-                true
-            } else {
-                false
+    /// Moves the cursor `count` lines up or down, or as far as there are lines. It keeps the display column, or ends
+    /// up at the end of the line if it's shorter than that.
+    pub fn move_lines(&mut self, dir: &MoveDir, count: usize) -> TextPosition {
+        let (line, column) = self.display_column(self.cursor.absolute);
+        let target = match dir {
+            Previous => line.saturating_sub(count),
+            Next => std::cmp::min(line + count, self.lines.line_count() - 1)
+        };
+        if target != line {
+            if let Some(tp) = self.text_position(Offset::LineColumn(target, column)) {
+                self.cursor = tp;
             }
-        }).and_then(|index_neg_offset| {
-            Some(index_neg_offset)
-        })
+        }
+        self.cursor.clone()
     }
 
-    pub fn find_next_line_abs_offset(&self, current: usize) -> Option<usize> {
-        (current..self.len())
-            .into_iter()
-            .position(|i| {
-                let a = self.data.get(i);
-                if a.is_some() && *a.unwrap() == '\n' {
-                    true
-                } else {
-                    false
+    /**
+        Where a word motion from `pos` ends up. Forwards that's the start of the next word (or with `end`, the last
+        character of this word, or the next one if `pos` is already at the end of one), backwards the start of this
+        word or the previous one (with `end`, the last character of the previous word). With `big` it moves by WORDs.
+    */
+    pub fn word_motion(&self, pos: usize, dir: &MoveDir, big: bool, end: bool) -> usize {
+        let pos = std::cmp::min(pos, self.len());
+        let class = |ch: char| self.word_chars.class(ch, big);
+        match (dir, end) {
+            (Next, false) => {
+                let mut classes = self.data.chars(pos..self.len()).map(class).peekable();
+                let mut p = pos;
+                if let Some(&first) = classes.peek().filter(|c| **c != CharClass::Whitespace) {
+                    p += run_of(&mut classes, first);
+                }
+                p + run_of(&mut classes, CharClass::Whitespace)
+            },
+            (Next, true) => {
+                if pos + 1 >= self.len() {
+                    return self.len();
+                }
+                let mut classes = self.data.chars(pos + 1..self.len()).map(class).peekable();
+                let p = pos + 1 + run_of(&mut classes, CharClass::Whitespace);
+                match classes.peek() {
+                    Some(&first) => p + run_of(&mut classes, first) - 1,
+                    None => self.len()
+                }
+            },
+            (Previous, false) => {
+                let mut classes = self.data.chars(0..pos).rev().map(class).peekable();
+                let mut p = pos - run_of(&mut classes, CharClass::Whitespace);
+                if let Some(&first) = classes.peek() {
+                    p -= run_of(&mut classes, first);
+                }
+                p
+            },
+            (Previous, true) => {
+                let from = std::cmp::min(pos + 1, self.len());
+                let mut classes = self.data.chars(0..from).rev().map(class).peekable();
+                let mut p = from;
+                if let Some(&first) = classes.peek().filter(|c| **c != CharClass::Whitespace) {
+                    p -= run_of(&mut classes, first);
                 }
-            })
-            .and_then(|val| {
-                Some(current+val+1)
-            })
+                (p - run_of(&mut classes, CharClass::Whitespace)).saturating_sub(1)
+            }
+        }
     }
 
-    pub fn get_at(&self, pos: usize) -> Option<char> {
-        if let Some(c) = self.data.get(pos) {
-            Some(*c)
-        } else {
+    /// Which characters word motions treat as parts of words
+    pub fn set_word_chars(&mut self, word_chars: WordChars) {
+        self.word_chars = word_chars;
+    }
+
+    /// Whether brackets inside of string literals and comments are left out when matching brackets
+    pub fn set_brackets_skip_literals(&mut self, skip: bool) {
+        self.brackets_skip_literals = skip;
+    }
+
+    /// Where the text that brackets around `pos` are matched among starts, that text, and which of its characters
+    /// are code. That's `BRACKET_WINDOW_LINES` lines either way of the line `pos` is on, which a literal or comment
+    /// starting before the window is missed in.
+    fn bracket_text(&self, pos: usize) -> (usize, Vec<char>, Vec<bool>) {
+        let line = self.lines.line_of(std::cmp::min(pos, self.len()));
+        let start = self.lines.line_start(line.saturating_sub(BRACKET_WINDOW_LINES)).unwrap_or(0);
+        let end = self.lines.line_end(line + BRACKET_WINDOW_LINES).unwrap_or(self.len());
+        let text: Vec<char> = self.data.chars(start..end).collect();
+        let code = if self.brackets_skip_literals { code_mask(&text) } else { vec![true; text.len()] };
+        (start, text, code)
+    }
+
+    /// The positions of the brackets of the innermost pair around `pos`, or of the pair the bracket at `pos` is a part of
+    pub fn find_block(&self, pos: usize) -> Option<(usize, usize)> {
+        let (start, text, code) = self.bracket_text(pos);
+        enclosing_pair(&text, &code, pos - start).map(|(open, close)| (open + start, close + start))
+    }
+
+    /// The position of the bracket matching the one at `pos`, or if there isn't one, the one matching the bracket
+    /// right before `pos`. The cursor sits between characters, so that's the bracket that was just typed.
+    pub fn matching_bracket_near(&self, pos: usize) -> Option<usize> {
+        let before = pos.checked_sub(1);
+        // this runs on every frame, so most of the time there's no bracket to match and nothing to collect
+        if !self.get_at(pos).is_some_and(is_bracket) && !before.and_then(|b| self.get_at(b)).is_some_and(is_bracket) {
+            return None;
+        }
+        let (start, text, code) = self.bracket_text(pos);
+        matching_bracket(&text, &code, pos - start)
+            .or_else(|| before.and_then(|before| matching_bracket(&text, &code, before - start)))
+            .map(|other| other + start)
+    }
+
+    /// The ranges of every match of `search` in the buffer, in order. Whole word searches go by the buffer's word characters.
+    pub fn find_all(&self, search: &Search) -> Vec<Range<usize>> {
+        search.find_all(&self.data.read_string(0..self.len()), &self.word_chars)
+    }
+
+    /// The ranges of every match of `search` in the buffer, and the text `replacement` would replace each of them with
+    pub fn replacements(&self, search: &Search, replacement: &str) -> Vec<(Range<usize>, String)> {
+        search.replacements(&self.data.read_string(0..self.len()), &self.word_chars, replacement)
+    }
+
+    /// Position of the last newline before `current`
+    pub fn find_prev_line_abs_offset(&self, current: usize) -> Option<usize> {
+        let line = self.lines.line_of(current);
+        if line == 0 {
             None
+        } else {
+            self.lines.line_end(line - 1)
+        }
+    }
+
+    /// Start of the line after the one `current` is on
+    pub fn find_next_line_abs_offset(&self, current: usize) -> Option<usize> {
+        if current > self.len() {
+            return None;
         }
+        self.lines.line_start(self.lines.line_of(current) + 1)
+    }
+
+    pub fn get_at(&self, pos: usize) -> Option<char> {
+        self.data.get(pos)
+    }
+
+    fn is_whitespace_at(&self, pos: usize) -> bool {
+        self.data.get(pos).is_some_and(char::is_whitespace)
     }
 
-    pub fn remove(&mut self) -> Option<char> {
-        if let Some(c) = self.data.remove() {
-            if c == '\n' {
-                self.line_count -= 1;
+    /// Works like the "backspace" key, removing the grapheme cluster before the cursor
+    pub fn remove(&mut self) -> Option<String> {
+        let end = self.cursor.absolute;
+        let pos = self.prev_grapheme_boundary(end);
+        if pos == end {
+            return None;
+        }
+        let removed = self.data.delete(pos..end);
+        self.lines.remove(pos..end);
+        self.shift_mark_on_remove(pos..end);
+        self.cursor = self.get_text_position_info(pos);
+        self.dirty = true;
+        Some(removed)
+    }
+
+    /// Where the grapheme cluster after the one that `pos` is in starts. Clusters never span lines
+    /// (other than "\r\n", which is a cluster of its own), so only the line is looked at.
+    pub fn next_grapheme_boundary(&self, pos: usize) -> usize {
+        if pos >= self.len() {
+            return self.len();
+        }
+        let line = self.lines.line_of(pos);
+        let start = self.lines.line_start(line).unwrap_or(0);
+        let end = self.lines.line_start(line + 1).unwrap_or(self.len());
+        let mut boundary = start;
+        for g in self.data.read_string(start..end).graphemes(true) {
+            boundary += g.chars().count();
+            if boundary > pos {
+                return boundary;
             }
-            self.cursor = self.get_text_position_info(self.data.get_pos());
-            Some(c)
-        } else {
-            None
         }
+        end
+    }
+
+    /// Where the grapheme cluster before `pos` starts
+    pub fn prev_grapheme_boundary(&self, pos: usize) -> usize {
+        if pos == 0 {
+            return 0;
+        }
+        let pos = std::cmp::min(pos, self.len());
+        let start = self.lines.line_start(self.lines.line_of(pos - 1)).unwrap_or(0);
+        let text = self.data.read_string(start..pos);
+        pos - text.graphemes(true).next_back().map_or(1, |g| g.chars().count())
     }
 
     pub fn clear_buffer_contents(&mut self) {
-        self.data = GapBuffer::new();
+        let len = self.data.len();
+        self.data.delete(0..len);
         self.cursor = TextPosition::default();
-        self.line_count = 1;
+        self.mark = None;
+        self.lines = LineIndex::new();
     }
 
+    /// Works like the "delete" key, removing the character at the cursor
     pub fn delete(&mut self) -> Option<char> {
-        if let Some(character) = self.data.delete() {
-            if character == '\n' {
-                self.line_count -= 1;
-            }
-            Some(character)
-        } else {
-            None
-        }
+        let pos = self.cursor.absolute;
+        let character = self.data.delete(pos..pos + 1).chars().next()?;
+        self.lines.remove(pos..pos + 1);
+        self.shift_mark_on_remove(pos..pos + 1);
+        self.dirty = true;
+        Some(character)
     }
 
     pub fn line_from_buffer_index(&self, absolute: usize) -> Option<TextPosition> {
-        let safe_pos_value = std::cmp::min(absolute, self.data.len());
-        let line_begin = (0..safe_pos_value).rposition(|index| index == 0 || self.data[index] == '\n').and_then(|v| Some(v+1)).unwrap_or(0usize);
-        let line_numbers = (0..safe_pos_value).rev().filter(|&index| index == 0 || self.data[index] == '\n').collect::<Vec<usize>>();
-        let mut tp = TextPosition::new();
-        tp.line_start_absolute = line_begin;
-        tp.line_index = line_numbers.len() - 1;
+        let mut tp = self.get_text_position_info(std::cmp::min(absolute, self.data.len()));
         tp.absolute = absolute;
         Some(tp)
     }
@@ -600,52 +747,172 @@ impl Textbuffer {
         use std::fs::read_to_string as read_content;
         let p = Path::new(&f_name);
         let contents = read_content(p).unwrap();
-        let mut tb = Textbuffer {
-            data: GapBuffer::new_with_capacity(contents.len()),
+        Textbuffer {
+            lines: LineIndex::from_text(&contents),
+            data: Box::new(PieceTable::from_text(contents)),
             _scratch: vec![],
             observer: None,
             cursor: TextPosition::new(),
-            line_count: contents.chars().filter(|c| *c == '\n').collect::<Vec<char>>().len() + 1,
-            dirty: false
-        };
-        tb.data.map_to(contents.chars());
-        tb
+            mark: None,
+            dirty: false,
+            tab_width: DEFAULT_TAB_WIDTH,
+            word_chars: WordChars::default(),
+            brackets_skip_literals: true,
+            read_only: false,
+            file_name: None
+        }
     }
 
     pub fn dump_to_string(&self) -> String {
         self.data.read_string(0..self.data.len()+1)
     }
 
+    /// Writes the text to `file_name`. Unless the options say to overwrite it, a file that exists already is left be.
     pub fn save_to_file(&self, file_name: &Path, save_opts: Option<FileOpt>) -> FileResult<usize> {
-        if file_name.exists() {
+        let overwrite = match save_opts {
+            Some(FileOpt::Overwrite) => true,
+            None | Some(FileOpt::NoOverwrite) => false
+        };
+        if file_name.exists() && !overwrite {
             return Err(SaveFileError::FileExisted(file_name.to_str().unwrap().into()));
         }
 
-        match save_opts {
-            None => {
-                match File::create(file_name) {
-                    Ok(ref mut f) => {
-                        f.write(self.dump_to_string().as_bytes()).map_err(|std_err| SaveFileError::Other(file_name.to_str().unwrap().into(), std_err.description().into()))
-                    },
-                    Err(e) => {
-                        Err(SaveFileError::Other(file_name.to_str().unwrap().to_string(), e.description().into()))
+        match File::create(file_name) {
+            Ok(ref mut f) => {
+                let data = self.dump_to_string();
+                f.write_all(data.as_bytes()).map(|_| data.len()).map_err(|std_err| SaveFileError::Other(file_name.to_str().unwrap().into(), std_err.to_string()))
+            },
+            Err(e) => {
+                Err(SaveFileError::Other(file_name.to_str().unwrap().to_string(), e.to_string()))
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    /// The number of lines in the buffer. An empty buffer has one (empty) line.
+    pub fn line_count(&self) -> usize {
+        self.lines.line_count()
+    }
+
+    /// The length of the buffer's text, encoded as UTF-8
+    pub fn byte_len(&self) -> usize {
+        self.data.byte_len()
+    }
+
+    /// The text position of an offset given in any unit, or None if it doesn't point at a character in the buffer.
+    /// Columns past the end of a line are the end of that line, and a display column in the middle of a wide
+    /// character is that character.
+    pub fn text_position(&self, offset: Offset) -> Option<TextPosition> {
+        let pos = match offset {
+            Offset::Char(pos) if pos <= self.len() => pos,
+            Offset::Char(_) => return None,
+            Offset::Byte(byte) => self.data.byte_to_char(byte)?,
+            Offset::Utf16(line, unit) => {
+                let (start, text) = self.line_text(line)?;
+                let mut units = 0;
+                let mut pos = start;
+                for ch in text.chars() {
+                    units += ch.len_utf16();
+                    if units > unit {
+                        break;
                     }
+                    pos += 1;
                 }
+                pos
             },
-            Some(fopt) => {
-                match fopt {
-                    FileOpt::NoOverwrite => {
-                        Ok(0)
-                    },
-                    FileOpt::Overwrite => {
-                        Ok(0)
+            Offset::LineColumn(line, col) => {
+                let (start, text) = self.line_text(line)?;
+                let mut column = 0;
+                let mut pos = start;
+                for g in text.graphemes(true) {
+                    column = next_column(column, g, self.tab_width);
+                    if column > col {
+                        break;
                     }
+                    pos += g.chars().count();
                 }
+                pos
             }
+        };
+        Some(self.get_text_position_info(pos))
+    }
+
+    /// Where line `line` starts, and its text, without the newline that ends it
+    fn line_text(&self, line: usize) -> Option<(usize, String)> {
+        let start = self.lines.line_start(line)?;
+        let end = self.lines.line_end(line).unwrap_or(self.len());
+        Some((start, self.data.read_string(start..end)))
+    }
+
+    /// The UTF-8 byte offset of the character at `pos`
+    pub fn byte_offset(&self, pos: usize) -> usize {
+        self.data.char_to_byte(pos)
+    }
+
+    /// The line of the character at `pos`, and how many UTF-16 code units into that line it is
+    pub fn utf16_column(&self, pos: usize) -> (usize, usize) {
+        let tp = self.get_text_position_info(pos);
+        (tp.line_index, self.data.chars(tp.line_start_absolute..pos).map(char::len_utf16).sum())
+    }
+
+    /// The line of the character at `pos`, and the column it is displayed at
+    pub fn display_column(&self, pos: usize) -> (usize, usize) {
+        let tp = self.get_text_position_info(pos);
+        let text = self.data.read_string(tp.line_start_absolute..pos);
+        (tp.line_index, text.graphemes(true).fold(0, |col, g| next_column(col, g, self.tab_width)))
+    }
+
+    /// How many columns apart the tab stops are
+    pub fn tab_width(&self) -> usize {
+        self.tab_width
+    }
+
+    pub fn set_tab_width(&mut self, width: usize) {
+        self.tab_width = width.max(1);
+    }
+
+    /// The whitespace that a new line, broken off at `pos`, should start with: the indentation of the line that `pos`
+    /// is on, and one `unit` more if the text before `pos` ends with an opening bracket.
+    pub fn newline_indentation(&self, pos: usize, unit: &str) -> String {
+        let pos = std::cmp::min(pos, self.len());
+        let start = self.lines.line_start(self.lines.line_of(pos)).unwrap_or(0);
+        let before = self.data.read_string(start..pos);
+        let mut indentation: String = before.chars().take_while(|c| *c == ' ' || *c == '\t').collect();
+        if let Some('{') | Some('(') | Some('[') = before.trim_end().chars().last() {
+            indentation.push_str(unit);
         }
+        indentation
     }
 
-    pub fn len(&self) -> usize {
-        self.data.len()
+    /// How many characters of indentation, right before `pos`, a closing bracket typed at `pos` should remove.
+    /// That's one `unit` (or what there is of it), but only if the line is blank apart from its indentation.
+    pub fn dedent_width(&self, pos: usize, unit: &str) -> usize {
+        let (start, text) = match self.line_text(self.lines.line_of(pos)) {
+            Some(line) => line,
+            None => return 0
+        };
+        if pos < start || text.chars().any(|c| c != ' ' && c != '\t') {
+            return 0;
+        }
+        let before: String = text.chars().take(pos - start).collect();
+        if before.ends_with(unit) {
+            unit.chars().count()
+        } else if before.ends_with('\t') {
+            1
+        } else {
+            before.chars().rev().take_while(|c| *c == ' ').take(unit.chars().count()).count()
+        }
+    }
+}
+
+/// Consumes the run of `class` at the front of `classes`, and returns how long it was
+fn run_of(classes: &mut std::iter::Peekable<impl Iterator<Item=CharClass>>, class: CharClass) -> usize {
+    let mut n = 0;
+    while classes.next_if_eq(&class).is_some() {
+        n += 1;
     }
+    n
 }
@@ -0,0 +1,64 @@
+use unicode_width::UnicodeWidthChar;
+use crate::{Deserialize, Serialize};
+
+/// What kind of character something is, as far as word motions are concerned. A word is a run of characters
+/// of the same class, other than whitespace.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CharClass {
+    Whitespace,
+    Word,
+    Punctuation
+}
+
+/**
+    Which characters words are made of. Letters, digits and `extra` make up words, like identifiers, and runs of
+    any other characters (punctuation) are words of their own. A WORD is simply everything between whitespace.
+*/
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct WordChars {
+    /// characters that are part of words, besides letters and digits
+    #[serde(default = "default_extra")]
+    pub extra: String,
+    /// whether letters and digits outside of ASCII are part of words, or punctuation
+    #[serde(default = "default_unicode_letters")]
+    pub unicode_letters: bool
+}
+
+fn default_extra() -> String {
+    "_".into()
+}
+
+fn default_unicode_letters() -> bool {
+    true
+}
+
+impl Default for WordChars {
+    fn default() -> Self {
+        WordChars {
+            extra: default_extra(),
+            unicode_letters: default_unicode_letters()
+        }
+    }
+}
+
+impl WordChars {
+    /// The class of `ch`. With `big` (WORD motions) there is no punctuation, everything but whitespace is a word.
+    pub fn class(&self, ch: char, big: bool) -> CharClass {
+        if ch.is_whitespace() {
+            CharClass::Whitespace
+        } else if big || self.is_word_char(ch) {
+            CharClass::Word
+        } else {
+            CharClass::Punctuation
+        }
+    }
+
+    fn is_word_char(&self, ch: char) -> bool {
+        if ch.is_ascii() {
+            ch.is_ascii_alphanumeric() || self.extra.contains(ch)
+        } else {
+            // combining marks belong to the letter they're on
+            self.unicode_letters && (ch.is_alphanumeric() || ch.width() == Some(0)) || self.extra.contains(ch)
+        }
+    }
+}
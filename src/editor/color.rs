@@ -2,7 +2,7 @@ use std::fmt::{Display, Formatter, Error as FmtError};
 // use std::fmt::Error as FmtError;
 use crate::{Deserialize, Serialize};
 
-#[derive(Clone, Deserialize, Serialize, Debug)]
+#[derive(Clone, Copy, PartialEq, Eq, Deserialize, Serialize, Debug)]
 pub enum Color {
     Black = 30,
     Red = 31,
@@ -15,7 +15,25 @@ pub enum Color {
     BrightCyan = 96
 }
 
-#[derive(Clone, Deserialize, Serialize, Debug)]
+impl Color {
+    /// The color with the given foreground SGR code, if it is one of ours
+    pub fn from_code(code: u8) -> Option<Color> {
+        match code {
+            30 => Some(Color::Black),
+            31 => Some(Color::Red),
+            32 => Some(Color::Green),
+            33 => Some(Color::Yellow),
+            34 => Some(Color::Blue),
+            35 => Some(Color::Magenta),
+            36 => Some(Color::Cyan),
+            37 => Some(Color::White),
+            96 => Some(Color::BrightCyan),
+            _ => None
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Deserialize, Serialize, Debug)]
 pub enum SetColor {
     Foreground(Color),
     Background(Color)
@@ -1,38 +1,78 @@
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use crate::data::text_buffer::Textbuffer;
 use std::sync::{Arc, Mutex};
-use std::os::unix::io::RawFd;
-use termios::{Termios, tcsetattr};
-use std::str::from_utf8;
-use std::fs::{read_to_string};
-use libc::read as read;
-use std::io::{Write, stdout};
+use std::fs::{read_to_string, canonicalize};
 use crate::cmd::{MoveKind, MoveDir, Command};
 use crate::editor::{view::ViewCursor, view::View};
 use self::StatlineCommand::{SaveFile};
 use crate::editor::view::ViewOperations;
 use crate::cfg::Config;
-use crate::editor::key::{KeyCode, EscapeKeyCode};
+use crate::editor::key::{KeyCode, EscapeKeyCode, MouseEvent, MouseAction, MouseButton};
+use crate::editor::input::decode_key;
 use crate::cmd::command_engine::CommandEngine;
 use crate::cmd::command_engine::Operation;
 use crate::cmd::command_engine::OperationResult;
 use std::thread::sleep;
 use std::time::Duration;
-use crate::cmd::StatlineCommand;
+use crate::cmd::{StatlineCommand, MacroRepeat, ReplaceScope, find_title, parse_macro_args, parse_replace_args, parse_grep_args, parse_replace_in_files_args};
+use std::collections::VecDeque;
 use crate::cmd::StatlineCommandFlagList;
 use crate::cmd::SeekFrom;
-use crate::data::text_buffer::Cursor;
 use crate::data::text_buffer::ObjectKind;
+use crate::data::search::{Search, SearchOptions, CaseMatching, next_match};
+use crate::clipboard::system::copy_to_system;
+use crate::grep::{GrepMatch, FileChanges, spawn as spawn_grep, walk_files, relative_path};
+use crate::editor::FileOpt;
+use std::sync::mpsc::{Receiver, TryRecvError};
+use crate::editor::terminal::{Terminal, TermiosTerminal};
 
-use crate::data::text_buffer::FileResult;
+use crate::data::text_buffer::{FileResult, TextPosition};
+use std::ops::Range;
 
 type FResult = FileResult<usize>;
 
+/// Where the cursor and the view were when the find prompt opened, which an incremental search searches from,
+/// and goes back to if it's cancelled
+struct SearchOrigin {
+    pos: usize,
+    line_range: Range<usize>,
+    top_line: TextPosition
+}
+
+/// The read-only buffer that the results of a grep or a replace across files are listed in, and the buffer that was
+/// being edited before it was shown
+struct ResultsBuffer {
+    buffer: usize,
+    return_to: usize
+}
+
+/// A search through the files of a directory tree, whose matches are listed in the results buffer, one per line
+struct GrepResults {
+    pattern: String,
+    root: PathBuf,
+    matches: Vec<GrepMatch>,
+    /// where the matches come from while the search is still going on
+    incoming: Option<Receiver<GrepMatch>>
+}
+
+/// A replace across the files of a directory tree, listed in the results buffer until the changes to make are picked
+struct ReplacePreview {
+    root: PathBuf,
+    files: Vec<FileChanges>,
+    /// the file, and the hunk (None on the file's own line), that each line of the results buffer shows
+    lines: Vec<(usize, Option<usize>)>
+}
+
 pub fn debug_sleep(msg: Option<String>, val: Option<u64>) {
     println!("\x1b[25;10H{}", msg.unwrap_or(" ".into()));
     sleep(Duration::from_millis(val.unwrap_or(1500)));
 }
 
+/// Where the character at `char_index` of `text` starts, in bytes, which is the length of `text` past its end
+fn byte_index(text: &str, char_index: usize) -> usize {
+    text.char_indices().nth(char_index).map_or(text.len(), |(at, _)| at)
+}
+
 pub enum Mode {
     Normal,
     Movement,
@@ -45,16 +85,29 @@ pub struct Editor {
     current_view: usize,
     current_buffer: usize,
     running: bool,
-    original_terminal_settings: Option<Termios>,
-    _input_mode: Mode,
+    terminal: Terminal,
+    raw_mode: bool,
+    input_mode: Mode,
     config: Config,
-    cmd_engine: CommandEngine
+    /// where the config is read from, and recorded macros are saved to. Without one, the defaults are used and
+    /// macros only last as long as the editor does.
+    config_path: Option<PathBuf>,
+    cmd_engine: CommandEngine,
+    pending_input: VecDeque<KeyCode>,
+    /// the pattern of the last search, and the options it was made with
+    last_search: Option<(String, SearchOptions)>,
+    results: Option<ResultsBuffer>,
+    grep: Option<GrepResults>,
+    replace_preview: Option<ReplacePreview>
 }
 
 impl Drop for Editor {
     fn drop(&mut self) {
-        if let Some(settings) = self.original_terminal_settings {
-            match tcsetattr(0, libc::TCSANOW, &settings) {
+        if self.raw_mode {
+            let mut terminal = self.terminal.lock().unwrap();
+            let _ = write!(terminal, "{}{}", ViewOperations::DisableMouse, ViewOperations::DisableBracketedPaste);
+            let _ = terminal.flush();
+            match terminal.disable_raw_mode() {
                 Ok(_) => {
                     println!("Restored terminal settings");
                 },
@@ -69,16 +122,28 @@ impl Drop for Editor {
 
 impl Editor {
     pub fn new() -> Editor {
+        Editor::with_terminal(Arc::new(Mutex::new(TermiosTerminal::new(0))))
+    }
+
+    /// Creates an editor that runs in the given terminal, instead of the one it was started in
+    pub fn with_terminal(terminal: Terminal) -> Editor {
         Editor {
             buffers: vec![Arc::new(Mutex::new(Textbuffer::new()))],
             views: vec![],
             current_buffer: 0,
             current_view: 0,
             running: false,
-            original_terminal_settings: None,
-            _input_mode: Mode::Normal,
+            terminal,
+            raw_mode: false,
+            input_mode: Mode::Normal,
             config: Config::default(),
-            cmd_engine: CommandEngine::new(Arc::new(Mutex::new(Textbuffer::new())))
+            config_path: None,
+            cmd_engine: CommandEngine::new(Arc::new(Mutex::new(Textbuffer::new()))),
+            pending_input: VecDeque::new(),
+            last_search: None,
+            results: None,
+            grep: None,
+            replace_preview: None
         }
     }
 
@@ -86,19 +151,47 @@ impl Editor {
         !self.buffers[0].lock().unwrap().is_dirty()
     }
 
-    pub fn init(&mut self, settings: Option<Termios>) {
-        self.original_terminal_settings = settings;
-        self.config = Config::read_config(Path::new("config.rc"));
+    /// Reads the config from `path` when the editor is initialized, and saves recorded macros to it
+    pub fn set_config_path(&mut self, path: &Path) {
+        self.config_path = Some(path.to_path_buf());
+    }
+
+    pub fn init(&mut self) {
+        self.config = match &self.config_path {
+            Some(path) => Config::read_config(path),
+            None => Config::default()
+        };
 
         if self.buffers.len() != 0 {
             self.buffers.push(Arc::new(Mutex::new(Textbuffer::new())));
         }
-        let mut v = View::new().unwrap_or_else(|| View::new().unwrap());
+        for buffer in self.buffers.iter() {
+            let mut guard = buffer.lock().unwrap();
+            guard.set_tab_width(self.config.tab_width());
+            guard.set_word_chars(self.config.word_chars().clone());
+            guard.set_brackets_skip_literals(self.config.brackets_skip_literals());
+        }
+        let mut v = View::new(self.terminal.clone()).unwrap_or_else(|| View::new(self.terminal.clone()).unwrap());
         v.set_viewed_buf(self.buffers[0].clone());
         v.init();
         self.views.push(v);
+        {
+            let mut terminal = self.terminal.lock().unwrap();
+            let _ = write!(terminal, "{}{}", ViewOperations::EnableBracketedPaste, ViewOperations::EnableMouse);
+            let _ = terminal.flush();
+        }
         self.running = true;
         self.cmd_engine.register_buffer(self.buffers[0].clone());
+        self.cmd_engine.set_group_timeout(Duration::from_millis(self.config.undo_group_timeout()));
+        for (name, m) in self.config.macros() {
+            self.cmd_engine.add_macro(name, m.clone());
+        }
+    }
+
+    /// Switching input modes always ends the current undo group.
+    pub fn set_input_mode(&mut self, mode: Mode) {
+        self.cmd_engine.break_group();
+        self.input_mode = mode;
     }
 
     pub fn open(&mut self, f: &Path) {
@@ -117,8 +210,6 @@ impl Editor {
                         }
                         lindex < lines_to_print
                     }).collect::<String>();
-                    let line_count: usize = data.chars().filter(|c| *c == '\n').collect::<Vec<char>>().len() + 1;
-                    self.buffers[0].lock().unwrap().line_count = line_count;
                     self.views[self.current_view].init();
                     d_to_print.chars().for_each(|c| {
                         self.views[self.current_view].write_character(c);
@@ -137,8 +228,7 @@ impl Editor {
     pub fn enter_statline_command(&mut self) -> Option<StatlineCommand> {
         let title = "[command]: ";
         self.views[0].statline_view_cursor.col = title.len() + 1;
-        print!("{}{}{}{}{}", self.views[0].status_line_position, self.views[0].view_cfg.stat_line_color.0, self.views[0].view_cfg.stat_line_color.1, title, ViewOperations::ClearLineRest);
-        stdout().flush();
+        self.views[0].on_prompt(title);
         let title_len = title.len();
         let mut vc = self.views[0].status_line_position;
         vc.col = title_len + 1usize;
@@ -153,34 +243,54 @@ impl Editor {
     }
 
     pub fn statline_input(&mut self, cmd: Command) -> Option<StatlineCommand> {
+        self.set_input_mode(Mode::Command);
+        let res = self.read_statline_input(cmd);
+        self.set_input_mode(Mode::Normal);
+        res
+    }
+
+    fn read_statline_input(&mut self, cmd: Command) -> Option<StatlineCommand> {
         let mut input = String::new();
-        let stat_line_title = String::from(&cmd);
+        // how the find prompt searches, which Alt+b (backwards), Alt+r (regex), Alt+c (case) and Alt+w (whole word) change
+        let (mut dir, mut options) = (SeekFrom::Start, self.config.search_options().clone());
+        let mut stat_line_title = match cmd {
+            Command::Find => find_title(&dir, &options),
+            _ => String::from(&cmd)
+        };
         let title_len = stat_line_title.len();
         let mut vc = self.views[0].status_line_position;
         vc.col = title_len + 1usize;
         let mut buf_index = 0;
+        let origin = match cmd {
+            Command::Find => Some(self.search_origin()),
+            _ => None
+        };
+        let (mut matches, mut current): (Vec<Range<usize>>, Option<usize>) = (vec![], None);
+        let mut cycled = false;
         loop {
+            let previous_input = input.clone();
+            let mut toggled = false;
             match self.handle_keypress() {
                 KeyCode::Character(ch) => {
                     vc.col += 1;
-                    if buf_index == input.len() {
+                    if buf_index == input.chars().count() {
                         input.push(ch);
                         self.views[self.current_view].write_statline_character(ch);
                     } else {
                         self.views[self.current_view].statline_view_cursor = vc;
-                        input.insert(buf_index, ch);
+                        input.insert(byte_index(&input, buf_index), ch);
                         let old_content = stat_line_title.clone().chars().chain(input.chars()).collect::<String>();
                         self.views[self.current_view].update_statline_with(&old_content, &vc);
                     }
                     buf_index += 1;
                 },
                 KeyCode::Backspace => {
-                    if buf_index == input.len() && buf_index > 0 {
+                    if buf_index == input.chars().count() && buf_index > 0 {
                         input.pop();
                         buf_index -= 1;
                         vc.col -= 1;
                     } else if buf_index > 0 {
-                        input.remove(buf_index-1);
+                        input.remove(byte_index(&input, buf_index-1));
                         buf_index -= 1;
                         vc.col -= 1;
                     }
@@ -188,6 +298,14 @@ impl Editor {
                     self.views[self.current_view].update_statline_with(&old_content, &vc);
                 }
                 KeyCode::Enter => {
+                    if let Some(origin) = &origin {
+                        // the search is done over from where it started, so that it can tell if it wrapped around,
+                        // unless another match was picked with Up/Down
+                        if !cycled {
+                            self.restore_search_origin(origin);
+                        }
+                        self.views[self.current_view].clear_search_highlights();
+                    }
                     return match cmd {
                         Command::Open => {
                             let (file, flags) = (input, " ");
@@ -199,15 +317,42 @@ impl Editor {
                         },
                         Command::Find => {
                             if input.len() > 0 {
-                                Some(StatlineCommand::Find(Some(input), SeekFrom::Start))
+                                Some(StatlineCommand::Find(Some(input), dir, options))
                             } else {
                                 None
                             }
                         },
+                        Command::Replace => {
+                            Some(parse_replace_args(&input))
+                        },
+                        Command::Grep => {
+                            Some(parse_grep_args(&input))
+                        },
+                        Command::ReplaceInFiles => {
+                            Some(parse_replace_in_files_args(&input))
+                        },
+                        Command::Register => {
+                            let mut chars = input.trim().chars();
+                            match (chars.next(), chars.next()) {
+                                (Some(reg), None) => Some(StatlineCommand::SelectRegister(reg)),
+                                _ => Some(StatlineCommand::Error(format!("Register names are a single letter, not '{}'", input)))
+                            }
+                        },
+                        Command::Record => {
+                            if !input.trim().is_empty() {
+                                Some(StatlineCommand::RecordMacro(input.trim().into()))
+                            } else {
+                                Some(StatlineCommand::Error("No macro name given".into()))
+                            }
+                        },
+                        Command::Play => {
+                            Some(parse_macro_args(&input))
+                        },
                         Command::Jump => {
                             input.parse::<usize>().ok().and_then(|v| Some(StatlineCommand::Goto(Some(v)))).or(Some(StatlineCommand::Error(format!("Couldn't parse line number from {}", input))))
                         },
-                        Command::Move(_mk) => unimplemented!(),
+                        Command::Move(_) => Some(StatlineCommand::Error("Cursor motions are bound to keys, they take no input".into())),
+                        Command::FindNext(_) => Some(StatlineCommand::Error("Repeating a search is bound to keys, it takes no input".into())),
                         Command::Quit => {
                             unimplemented!();
                         },
@@ -217,8 +362,36 @@ impl Editor {
                         Command::Action(_) => unimplemented!()
                     };
                 },
+                KeyCode::Alt(toggle @ ('b' | 'r' | 'c' | 'w')) if origin.is_some() => {
+                    match toggle {
+                        'b' => dir = if dir == SeekFrom::Start { SeekFrom::End } else { SeekFrom::Start },
+                        'r' => options.regex = !options.regex,
+                        'c' => options.case = match options.case {
+                            CaseMatching::Smart => CaseMatching::Sensitive,
+                            CaseMatching::Sensitive => CaseMatching::Insensitive,
+                            CaseMatching::Insensitive => CaseMatching::Smart
+                        },
+                        _ => options.whole_word = !options.whole_word
+                    }
+                    let title = find_title(&dir, &options);
+                    vc.col = vc.col - stat_line_title.len() + title.len();
+                    stat_line_title = title;
+                    toggled = true;
+                },
                 KeyCode::Escaped(esc_kc) => {
                     match esc_kc {
+                        EscapeKeyCode::Down | EscapeKeyCode::Up if origin.is_some() && !matches.is_empty() => {
+                            // cycles through the matches of the incremental search
+                            let len = matches.len();
+                            current = Some(match (current, esc_kc) {
+                                (Some(idx), EscapeKeyCode::Down) => (idx + 1) % len,
+                                (Some(idx), _) => (idx + len - 1) % len,
+                                (None, _) => 0
+                            });
+                            cycled = true;
+                            let content = stat_line_title.chars().chain(input.chars()).collect::<String>();
+                            self.show_search_matches(&matches, current, origin.as_ref().unwrap(), &content, vc);
+                        },
                         EscapeKeyCode::Down => {    // TODO: perform command history scroll down
 
                         },
@@ -226,36 +399,105 @@ impl Editor {
 
                         },
                         EscapeKeyCode::Right => {   // TODO: perform step right on status line
-                            if buf_index < input.len() {
-                                print!("{}", ViewOperations::StepRight);
+                            if buf_index < input.chars().count() {
                                 buf_index += 1;
                                 vc.col += 1;
-                                stdout().flush();
+                                self.views[self.current_view].set_statline_cursor(vc);
                             }
                         },
                         EscapeKeyCode::Left => {    // TODO: perform step left on status line
                             if buf_index > 0 {
-                                print!("{}", ViewOperations::StepLeft);
                                 buf_index -= 1;
                                 vc.col -= 1;
-                                stdout().flush();
+                                self.views[self.current_view].set_statline_cursor(vc);
                             }
                         },
+                        _ => {}
+                    }
+                },
+                KeyCode::Resize => {
+                    self.on_resize();
+                    vc.row = self.views[self.current_view].status_line_position.row;
+                    let content = stat_line_title.chars().chain(input.chars()).collect::<String>();
+                    self.views[self.current_view].update_statline_with(&content, &vc);
+                },
+                KeyCode::Paste(text) => {
+                    // the status line is a single line, so only the first line of the paste is typed into it
+                    let keys = text.lines().next().unwrap_or("").chars().map(KeyCode::Character).collect::<Vec<KeyCode>>();
+                    for kc in keys.into_iter().rev() {
+                        self.pending_input.push_front(kc);
                     }
                 },
                 KeyCode::Tab => {
                   // TODO: statline autocompletion
                 },
                 KeyCode::Esc => {
+                    if let Some(origin) = &origin {
+                        self.restore_search_origin(origin);
+                        self.views[self.current_view].clear_search_highlights();
+                        self.views[self.current_view].draw_view();
+                    }
                     return None;
                 },
                 _ => {
                     // any other input than enter, a character key or escape, is invalid at this point and will do nothing.
                 }
             }
+            if let Some(origin) = origin.as_ref().filter(|_| toggled || input != previous_input) {
+                let found = self.preview_search(&input, &options, &dir, origin);
+                matches = found.0;
+                current = found.1;
+                cycled = false;
+                let content = stat_line_title.chars().chain(input.chars()).collect::<String>();
+                self.show_search_matches(&matches, current, origin, &content, vc);
+            }
+        }
+    }
+
+    fn search_origin(&self) -> SearchOrigin {
+        let view = &self.views[self.current_view];
+        SearchOrigin {
+            pos: self.buffers[self.current_buffer].lock().unwrap().get_textpos().absolute,
+            line_range: view.line_range.clone(),
+            top_line: view.top_line.clone()
         }
     }
 
+    fn restore_search_origin(&mut self, origin: &SearchOrigin) {
+        self.buffers[self.current_buffer].lock().unwrap().set_textpos(origin.pos);
+        let view = &mut self.views[self.current_view];
+        view.line_range = origin.line_range.clone();
+        view.top_line = origin.top_line.clone();
+    }
+
+    /// The matches of what has been typed into the find prompt so far, and the first of them from where the search
+    /// started, in the direction it goes. Patterns that don't compile (yet), like a regex with an unclosed group, have no matches.
+    fn preview_search(&mut self, pattern: &str, options: &SearchOptions, dir: &SeekFrom, origin: &SearchOrigin) -> (Vec<Range<usize>>, Option<usize>) {
+        let search = match Search::new(pattern, options) {
+            Ok(search) if !pattern.is_empty() => search,
+            _ => return (vec![], None)
+        };
+        let matches = self.buffers[self.current_buffer].lock().unwrap().find_all(&search);
+        let current = next_match(&matches, origin.pos, *dir == SeekFrom::Start).map(|(idx, _)| idx);
+        (matches, current)
+    }
+
+    /// Moves the cursor to match `current` of an incremental search, or back to where the search started if there is
+    /// none, and draws the view with every match highlighted. The find prompt, `content`, is drawn again on top.
+    fn show_search_matches(&mut self, matches: &[Range<usize>], current: Option<usize>, origin: &SearchOrigin, content: &str, vc: ViewCursor) {
+        let cv = self.current_view;
+        match current {
+            Some(idx) => {
+                self.buffers[self.current_buffer].lock().unwrap().set_textpos(matches[idx].start);
+                self.views[cv].scroll_to_cursor();
+            },
+            None => self.restore_search_origin(origin)
+        }
+        self.views[cv].set_search_highlights(matches.to_vec(), current);
+        self.views[cv].draw_view();
+        self.views[cv].update_statline_with(content, &vc);
+    }
+
     pub fn get_view(&mut self) -> &mut View {
         &mut self.views[self.current_view]
     }
@@ -272,11 +514,10 @@ impl Editor {
     pub fn run(&mut self) {
         // TODO: setup code, and also
         // println!("Entering editor main loop:\r");
-        let mut running = self.running;
-        while running {
+        while self.running {
             let kp = self.handle_keypress();
             /*
-            match self._input_mode {
+            match self.input_mode {
                 Mode::Movement => {
 
                 },
@@ -289,23 +530,84 @@ impl Editor {
                 },
             }
             */
-            self.views[0].restore_statline();
-            match kp {
-                KeyCode::Character(c) => {
-                    let abs_pos =self.buffers[0].lock().unwrap().get_textpos();
-                    let pos = abs_pos.absolute;
-                    match self.cmd_engine.execute(Operation::Insert(pos, c)) {
-                        OperationResult::OK => {
-                            self.views[self.current_view].draw_view();
-                        },
-                        OperationResult::ERR(errmsg) => {
+            self.process_key(kp);
+        }
+    }
 
-                        }
+    /// Performs whatever action a key press is bound to. Keys that are played back from a macro are
+    /// processed by this as well.
+    pub fn process_key(&mut self, kp: KeyCode) {
+        if self.cmd_engine.combo_trigger.as_ref().map(|kc| *kc != kp).unwrap_or(false) {
+            self.cmd_engine.combo_trigger = None;
+        }
+        self.views[0].restore_statline();
+        match kp {
+            KeyCode::Character(' ') if self.viewing_results() && self.replace_preview.is_some() => self.toggle_hunk(),
+            KeyCode::Character(c) => {
+                let abs_pos =self.buffers[self.current_buffer].lock().unwrap().get_textpos();
+                let pos = abs_pos.absolute;
+                let dedent = match c {
+                    '}' | ')' | ']' => self.buffers[self.current_buffer].lock().unwrap().dedent_width(pos, self.config.indent_unit()),
+                    _ => 0
+                };
+                let result = if dedent > 0 {
+                    // a closing bracket on a blank line takes the indentation back one level, in one undo step
+                    self.cmd_engine.begin_group();
+                    for p in (pos - dedent + 1..=pos).rev() {
+                        self.cmd_engine.execute(Operation::Remove(p, ' '));
                     }
-                },
-                KeyCode::Enter => {
-                    let pos = *&self.buffers[0].lock().unwrap().get_textpos().absolute;
-                    match self.cmd_engine.execute(Operation::Insert(pos, '\n')) {
+                    let result = self.cmd_engine.execute(Operation::Insert(pos - dedent, c));
+                    self.cmd_engine.end_group();
+                    result
+                } else {
+                    self.cmd_engine.execute(Operation::Insert(pos, c))
+                };
+                match result {
+                    OperationResult::OK => {
+                        self.views[self.current_view].draw_view();
+                    },
+                    OperationResult::ERR(errmsg) => {
+
+                    }
+                }
+            },
+            KeyCode::Paste(text) => {
+                // a paste is inserted as is, one undo step and one redraw, no matter how much text it is
+                let pos = self.buffers[self.current_buffer].lock().unwrap().get_textpos().absolute;
+                self.cmd_engine.break_group();
+                match self.cmd_engine.execute(Operation::InsertData(pos, text)) {
+                    OperationResult::OK => {
+                        self.cmd_engine.break_group();
+                        self.views[self.current_view].draw_view();
+                    },
+                    OperationResult::ERR(errmsg) => self.statline_error_msg(&errmsg)
+                }
+            },
+            KeyCode::Enter if self.viewing_results() => self.results_enter(),
+            KeyCode::Enter => {
+                let (pos, indentation) = {
+                    let guard = self.buffers[self.current_buffer].lock().unwrap();
+                    let pos = guard.get_textpos().absolute;
+                    (pos, guard.newline_indentation(pos, self.config.indent_unit()))
+                };
+                // inserted as one edit, so the newline and the indentation of the new line are undone together
+                match self.cmd_engine.execute(Operation::InsertData(pos, format!("\n{}", indentation))) {
+                    OperationResult::OK => {
+                        self.views[self.current_view].draw_view();
+                    },
+                    OperationResult::ERR(errmsg)=> {
+
+                    }
+                }
+            },
+            KeyCode::Backspace => {
+                let pos = {
+                    let guard = self.buffers[self.current_buffer].lock().unwrap();
+                    guard.get_textpos().absolute
+                };
+                if pos > 0 {
+                    let c = self.buffers[self.current_buffer].lock().unwrap().get_at(pos-1).unwrap();
+                    match self.cmd_engine.execute(Operation::Remove(pos, c)) {
                         OperationResult::OK => {
                             self.views[self.current_view].draw_view();
                         },
@@ -313,260 +615,1017 @@ impl Editor {
 
                         }
                     }
-                },
-                KeyCode::Backspace => {
-                    let pos = {
-                        let guard = self.buffers[0].lock().unwrap();
-                        guard.get_textpos().absolute
-                    };
-                    if pos > 0 {
-                        let c = self.buffers[0].lock().unwrap().get_at(pos-1).unwrap();
-                        match self.cmd_engine.execute(Operation::Remove(pos, c)) {
-                            OperationResult::OK => {
-                                self.views[self.current_view].draw_view();
-                            },
-                            OperationResult::ERR(errmsg)=> {
-
-                            }
-                        }
-                    }
-                },
-                KeyCode::CtrlW => {
-                    self.views[0].restore_statline();
-                    let completed = match &self.cmd_engine.combo_trigger {
-                        Some(kc) if *kc == KeyCode::CtrlW => {
-                            if let Some(cmd) = self.config.get_combo_bindings(kc).and_then(|map| map.get(&KeyCode::CtrlW)) {
-                                match cmd {
-                                    Command::Action(Operation::Copy(ObjectKind::Line)) => {
-                                        let r= self.buffers[0].lock().unwrap().find_range_of(Cursor::Buffer, ObjectKind::Line);
-                                        if r.is_some() {
-                                            let range = r.unwrap();
-                                            let msg: String = self.buffers[0].lock().unwrap().get_data(range.clone()).chars().filter(|c| *c != '\n').collect();
-                                            self.views[self.current_view].on_statline_error(format!("Found range: {}..{}, with contents: '{}'", &range.start, &range.end, msg).as_ref());
-                                        }
-                                    },
-                                    _ => {}
-                                }
-                                true
-                            } else {
-                                false
-                            }
-                        },
-                        Some(_) => {
+                }
+            },
+            KeyCode::CtrlW => {
+                let has_selection = self.buffers[self.current_buffer].lock().unwrap().get_mark().is_some();
+                let completed = match &self.cmd_engine.combo_trigger {
+                    Some(kc) if *kc == KeyCode::CtrlW && !has_selection => {
+                        if let Some(Command::Action(op)) = self.config.get_combo_bindings(kc).and_then(|map| map.get(&KeyCode::CtrlW)).cloned() {
+                            self.clipboard_action(op);
                             true
-                        },
-                        None => {
-                            let r= self.buffers[0].lock().unwrap().find_range_of(Cursor::Buffer, ObjectKind::Word);
-                            if r.is_some() {
-                                let range = r.unwrap();
-                                let msg = self.buffers[0].lock().unwrap().get_data(range.clone());
-                                self.views[0].on_statline_error(format!("Found range: {}..{}, with contents: '{}'", &range.start, &range.end, msg).as_ref());
-                            }
+                        } else {
                             false
                         }
-                    };
-                    if !completed {
-                        self.cmd_engine.combo_trigger = Some(KeyCode::CtrlW)
-                    } else {
-                        self.cmd_engine.combo_trigger = None
+                    },
+                    Some(_) => {
+                        true
+                    },
+                    None => {
+                        let op = self.bound_action(&KeyCode::CtrlW, Operation::Copy(ObjectKind::Word));
+                        self.clipboard_action(op);
+                        has_selection
                     }
+                };
+                if !completed {
+                    self.cmd_engine.combo_trigger = Some(KeyCode::CtrlW)
+                } else {
+                    self.cmd_engine.combo_trigger = None
                 }
-                KeyCode::Tab => {
-                    let pos = *&self.buffers[0].lock().unwrap().get_textpos().absolute;
-                    match self.cmd_engine.execute(Operation::InsertData(pos, "    ".into())) {
-                        OperationResult::OK => {
-                            self.views[self.current_view].draw_view();
-                        },
-                        OperationResult::ERR(errmsg)=> {}
-                    }
-                },
-                KeyCode::Esc => {},
-                KeyCode::CtrlBackspace => {
-                    let current_pos = self.buffers[0].lock().unwrap().get_textpos().absolute;
+            }
+            KeyCode::CtrlK => {
+                let op = self.bound_action(&KeyCode::CtrlK, Operation::Cut(ObjectKind::Line));
+                self.clipboard_action(op);
+            },
+            KeyCode::CtrlP => {
+                let op = self.bound_action(&KeyCode::CtrlP, Operation::YankPop);
+                self.clipboard_action(op);
+            },
+            KeyCode::CtrlSpace => {
+                let mut guard = self.buffers[self.current_buffer].lock().unwrap();
+                if guard.get_mark().is_some() {
+                    guard.clear_mark();
+                    drop(guard);
+                    self.views[self.current_view].on_statline_info("[mark]: ", "cleared");
+                } else {
+                    guard.set_mark();
+                    let tp = guard.get_textpos();
+                    drop(guard);
+                    self.views[self.current_view].on_statline_info("[mark]: ", &format!("set at {}:{}", tp.get_line_number(), tp.get_line_position() + 1));
+                }
+            },
+            KeyCode::CtrlX => {
+                self.views[self.current_view].on_prompt(&String::from(&Command::Register));
+                match self.statline_input(Command::Register) {
+                    Some(StatlineCommand::SelectRegister(reg)) => {
+                        match self.cmd_engine.execute(Operation::SelectRegister(reg)) {
+                            OperationResult::OK => self.views[self.current_view].on_statline_info("[register]: ", &reg.to_string()),
+                            OperationResult::ERR(msg) => self.statline_error_msg(&msg)
+                        }
+                    },
+                    Some(StatlineCommand::Error(msg)) => self.statline_error_msg(&msg),
+                    _ => self.views[self.current_view].restore_statline()
+                }
+            },
+            KeyCode::Tab => {
+                let (pos, column) = {
+                    let guard = self.buffers[self.current_buffer].lock().unwrap();
+                    let pos = guard.get_textpos().absolute;
+                    (pos, guard.display_column(pos).1)
+                };
+                let op = if self.config.expand_tab() {
+                    let tab_width = self.config.tab_width().max(1);
+                    Operation::InsertData(pos, " ".repeat(tab_width - column % tab_width))
+                } else {
+                    Operation::Insert(pos, '\t')
+                };
+                match self.cmd_engine.execute(op) {
+                    OperationResult::OK => {
+                        self.views[self.current_view].draw_view();
+                    },
+                    OperationResult::ERR(errmsg)=> {}
+                }
+            },
+            KeyCode::Esc => self.leave_results(),
+            KeyCode::CtrlBackspace => {
+                let current_pos = self.buffers[self.current_buffer].lock().unwrap().get_textpos().absolute;
 
-                },
-                KeyCode::CtrlG => {
-                    self.views[self.current_view].on_goto();
-                    let cmd = self.statline_input(Command::Jump);
-                    if let Some(StatlineCommand::Goto(Some(line))) = cmd {
-                        let line_pos = self.buffers[0].lock().unwrap().get_line_abs_index(line);
-                        self.buffers[0].lock().unwrap().set_textpos(line_pos.clone().unwrap().absolute);
-                        self.views[0].view_cursor = ViewCursor::from(line_pos.unwrap());
-                        self.views[0].draw_view();
-                    } else if let Some(StatlineCommand::Error(msg)) = cmd {
-                        self.statline_error_msg(&"[goto error]: ".chars().chain(msg.chars()).collect::<String>());
-                    } else {
-                        self.statline_error_msg("Unknown input error, command not performed!");
-                    }
-                },
-                KeyCode::CtrlV => {},
-                KeyCode::CtrlS => {
-                    /* TODO: open status line if we do not have a filename, write in filename
-                            validate provided path, open a new file with that name -> write contents.
-                            reset statusline.
-                    */
-                    // TODO: implement 2 functions, one that will write without asking for new file name/ask if ok, and one that does
-                    // TODO: implement Config for editor. Then request mapping of key to command via self.get_keybinding(KeyCode::CtrlS)
-                    self.views[0].on_save_file();
-                    let cmd = self.statline_input(Command::Save);
-                    if let Some(SaveFile(Some(suggested_fname), flags)) = cmd {
-                        let p = Path::new(&suggested_fname);
-                        self.on_save(&suggested_fname, self.buffers[0].lock().unwrap().save_to_file(p, None));
-                    } else {
-                        self.views[0].restore_statline();
-                    }
-                    self.buffers[0].lock().unwrap().set_pristine();
-                },
-                KeyCode::CtrlO => {
-                    self.views[self.current_view].on_open_file();
-                    let cmd = self.statline_input(Command::Open);
-                    if let Some(StatlineCommand::OpenFile(Some(fname), flags)) = cmd {
-                        self.on_open(&fname);
-                    } else if let None = cmd {
-                        self.views[0].restore_statline();
+            },
+            KeyCode::CtrlG => {
+                self.views[self.current_view].on_goto();
+                let cmd = self.statline_input(Command::Jump);
+                if let Some(StatlineCommand::Goto(Some(line))) = cmd {
+                    let line_pos = self.buffers[self.current_buffer].lock().unwrap().get_line_abs_index(line);
+                    self.buffers[self.current_buffer].lock().unwrap().set_textpos(line_pos.clone().unwrap().absolute);
+                    self.views[0].view_cursor = ViewCursor::at(&self.buffers[self.current_buffer].lock().unwrap(), &line_pos.unwrap());
+                    self.views[0].draw_view();
+                } else if let Some(StatlineCommand::Error(msg)) = cmd {
+                    self.statline_error_msg(&"[goto error]: ".chars().chain(msg.chars()).collect::<String>());
+                } else {
+                    self.statline_error_msg("Unknown input error, command not performed!");
+                }
+            },
+            KeyCode::CtrlF => {
+                self.views[self.current_view].on_prompt(&find_title(&SeekFrom::Start, self.config.search_options()));
+                match self.statline_input(Command::Find) {
+                    Some(StatlineCommand::Find(Some(pattern), dir, options)) => self.find(&pattern, &options, dir, false),
+                    _ => self.views[self.current_view].restore_statline()
+                }
+            },
+            KeyCode::CtrlL => {
+                self.views[self.current_view].on_prompt(&String::from(&Command::Replace));
+                match self.statline_input(Command::Replace) {
+                    Some(StatlineCommand::Replace(pattern, replacement, scope, confirm)) => self.replace(&pattern, &replacement, scope, confirm),
+                    Some(StatlineCommand::Error(msg)) => self.statline_error_msg(&msg),
+                    _ => self.views[self.current_view].restore_statline()
+                }
+            },
+            KeyCode::CtrlN => {
+                self.views[self.current_view].on_prompt(&String::from(&Command::Grep));
+                match self.statline_input(Command::Grep) {
+                    Some(StatlineCommand::Grep(pattern, dir)) => self.grep(&pattern, PathBuf::from(dir)),
+                    Some(StatlineCommand::Error(msg)) => self.statline_error_msg(&msg),
+                    _ => self.views[self.current_view].restore_statline()
+                }
+            },
+            KeyCode::CtrlD => {
+                self.views[self.current_view].on_prompt(&String::from(&Command::ReplaceInFiles));
+                match self.statline_input(Command::ReplaceInFiles) {
+                    Some(StatlineCommand::ReplaceInFiles(pattern, replacement, dir)) => self.replace_in_files(&pattern, &replacement, PathBuf::from(dir)),
+                    Some(StatlineCommand::Error(msg)) => self.statline_error_msg(&msg),
+                    _ => self.views[self.current_view].restore_statline()
+                }
+            },
+            KeyCode::CtrlV => {
+                let op = self.bound_action(&KeyCode::CtrlV, Operation::Paste);
+                self.clipboard_action(op);
+            },
+            KeyCode::CtrlS => {
+                /* TODO: open status line if we do not have a filename, write in filename
+                        validate provided path, open a new file with that name -> write contents.
+                        reset statusline.
+                */
+                // TODO: implement 2 functions, one that will write without asking for new file name/ask if ok, and one that does
+                // TODO: implement Config for editor. Then request mapping of key to command via self.get_keybinding(KeyCode::CtrlS)
+                self.views[0].on_save_file();
+                let cmd = self.statline_input(Command::Save);
+                if let Some(SaveFile(Some(suggested_fname), flags)) = cmd {
+                    let p = Path::new(&suggested_fname);
+                    let result = self.buffers[self.current_buffer].lock().unwrap().save_to_file(p, None);
+                    if result.is_ok() {
+                        let mut guard = self.buffers[self.current_buffer].lock().unwrap();
+                        guard.set_file_name(p);
+                        guard.set_pristine();
                     }
-                },
-                KeyCode::CtrlZ => {
-                    match self.cmd_engine.execute(Operation::Undo) {
-                        OperationResult::OK => {
-                            self.views[0].draw_view();
-                        },
-                        OperationResult::ERR(errmsg)=> {
+                    self.on_save(&suggested_fname, result);
+                } else {
+                    self.views[0].restore_statline();
+                }
+            },
+            KeyCode::CtrlO => {
+                self.views[self.current_view].on_open_file();
+                let cmd = self.statline_input(Command::Open);
+                if let Some(StatlineCommand::OpenFile(Some(fname), flags)) = cmd {
+                    self.on_open(&fname);
+                } else if let None = cmd {
+                    self.views[0].restore_statline();
+                }
+            },
+            KeyCode::CtrlZ => {
+                self.history_action(Operation::Undo);
+            },
+            KeyCode::CtrlY | KeyCode::CtrlU | KeyCode::CtrlT | KeyCode::CtrlB => {
+                let default = match kp {
+                    KeyCode::CtrlY => Operation::Redo,
+                    KeyCode::CtrlU => Operation::UndoOlder,
+                    KeyCode::CtrlT => Operation::UndoNewer,
+                    _ => Operation::UndoBranch(MoveDir::Next)
+                };
+                let op = self.bound_action(&kp, default);
+                self.history_action(op);
+            },
+            KeyCode::CtrlA => {
+                // N.B! This is a debug function ONLY. Used in the beginning for testing display functions, cursor navigation etc
+                // This will become something else entirely.
+                let tp = self.buffers[self.current_buffer].lock().unwrap().get_textpos();
+                let line_number = self.buffers[self.current_buffer].lock().unwrap().get_line_number();
+                let mut terminal = self.terminal.lock().unwrap();
+                let _ = write!(terminal, "Text buffer position: absolute: {}, line_start_absolute: {}, line_number: {}, line column position: {}\r\n", tp.absolute, tp.line_start_absolute, tp.line_index, tp.get_line_position());
+                let _ = write!(terminal, "Text buffer get line at buffer cursor: {}\r\n", line_number);
+                let _ = write!(terminal, "View cursor position: {},{}", self.views[self.current_view].view_cursor.col, self.views[self.current_view].view_cursor.row);
+                let _ = terminal.flush();
+                drop(terminal);
 
-                        }
-                    }
-                },
-                KeyCode::CtrlA => {
-                    // N.B! This is a debug function ONLY. Used in the beginning for testing display functions, cursor navigation etc
-                    // This will become something else entirely.
-                    let tp = self.buffers[self.current_buffer].lock().unwrap().get_textpos();
-                    print!("Text buffer position: absolute: {}, line_start_absolute: {}, line_number: {}, line column position: {}\r\n", tp.absolute, tp.line_start_absolute, tp.line_index, tp.get_line_position());
-                    print!("Text buffer get line at buffer cursor: {}\r\n", self.buffers[self.current_buffer].lock().unwrap().get_line_number());
-                    print!("View cursor position: {},{}", self.views[self.current_view].view_cursor.col, self.views[self.current_view].view_cursor.row);
-                    stdout().flush();
-
-                    self.views[self.current_view].update_cursor();
-                },
-                KeyCode::CtrlB => {
-                    // self.views[self.current_view].draw_view();
-                },
-                KeyCode::CtrlC => {
-                    // TODO: this is how reading from our config will look like, so that the bindings can be customizable
-                    let _command: Option<&Command> = self.config.get_binding(KeyCode::CtrlC);
-                },
-                KeyCode::CtrlQ => {
-                    running = false;
-                },
-                KeyCode::Escaped(_esk) => {
-                    match _esk {
-                        EscapeKeyCode::Right => {
-                            let pos = self.buffers[self.current_buffer].lock().unwrap().move_cursor(MoveKind::Char(MoveDir::Next)).unwrap();
-                            self.views[0].view_cursor = ViewCursor::from(pos.clone());
-                            if (self.views[0].view_cursor.row - self.views[0].line_range.start) == self.views[0].win_size.1 as usize {
-                                self.views[0].scroll_down();
-                                self.views[0].draw_view();
-                                // self.views[0].view_cursor.row -= self.views[0].line_range.start;
-                            } else {
-                                self.views[0].view_cursor.row -= self.views[0].line_range.start;
-                                stdout().flush();
-                            }
-                            self.views[0].statline_update_line_number(pos.line_index+1, pos.get_line_position()+1);
-                        },
-                        EscapeKeyCode::Left => {
-                            let pos = self.buffers[self.current_buffer].lock().unwrap().move_cursor(MoveKind::Char(MoveDir::Previous)).unwrap();
-                            self.views[0].view_cursor = ViewCursor::from(pos.clone());
-                            if (self.views[0].view_cursor.row - self.views[0].line_range.start) == 0 {
-                                self.views[0].scroll_up();
-                                self.views[0].draw_view();
-                            } else {
-                                self.views[0].view_cursor.row -= self.views[0].line_range.start;
-                                stdout().flush();
-                            }
-                            self.views[0].statline_update_line_number(pos.line_index+1, pos.get_line_position()+1);
-                        },
-                        EscapeKeyCode::Up => {
-                            let pos = self.buffers[self.current_buffer].lock().unwrap().move_cursor(MoveKind::Line(MoveDir::Previous)).unwrap();
-                            self.views[0].view_cursor = ViewCursor::from(pos.clone());
-                            if (self.views[0].view_cursor.row - self.views[0].line_range.start) == 0 {
-                                self.views[0].scroll_up();
-                                self.views[0].draw_view();
-                            } else {
-                                self.views[0].view_cursor.row -= self.views[0].line_range.start;
-                                // print!("{}", self.views[0].view_cursor);
-                                stdout().flush();
+                self.views[self.current_view].update_cursor();
+            },
+            KeyCode::CtrlC => {
+                // TODO: this is how reading from our config will look like, so that the bindings can be customizable
+                let _command: Option<&Command> = self.config.get_binding(KeyCode::CtrlC);
+            },
+            KeyCode::Mouse(ev) => self.mouse_action(ev),
+            KeyCode::Resize => self.on_resize(),
+            KeyCode::CtrlQ => {
+                self.running = false;
+            },
+            KeyCode::CtrlR => {
+                if self.cmd_engine.is_recording().is_some() {
+                    self.stop_macro_recording();
+                } else {
+                    self.views[self.current_view].on_prompt(&String::from(&Command::Record));
+                    match self.statline_input(Command::Record) {
+                        Some(StatlineCommand::RecordMacro(name)) => {
+                            match self.cmd_engine.execute(Operation::MacroRecord(name.clone())) {
+                                OperationResult::OK => self.views[self.current_view].on_statline_info("[recording]: ", &name),
+                                OperationResult::ERR(msg) => self.statline_error_msg(&msg)
                             }
-                            self.views[0].statline_update_line_number(pos.line_index+1, pos.get_line_position()+1);
                         },
-                        EscapeKeyCode::Down => {
-                            // self.buffers[self.current_buffer].lock().unwrap().move_cursor();
-                            let pos = self.buffers[0].lock().unwrap().move_cursor(MoveKind::Line(MoveDir::Next)).unwrap();
-                            self.views[0].view_cursor = ViewCursor::from(pos.clone());
-                            if (self.views[0].view_cursor.row - self.views[0].line_range.start) == self.views[0].win_size.1 as usize {
-                                self.views[0].scroll_down();
-                                self.views[0].draw_view();
-                                // self.views[0].view_cursor.row -= self.views[0].line_range.start;
-                            } else {
-                                self.views[0].view_cursor.row -= self.views[0].line_range.start;
-                                // print!("{}", self.views[0].view_cursor);
-                                stdout().flush();
+                        Some(StatlineCommand::Error(msg)) => self.statline_error_msg(&msg),
+                        _ => self.views[self.current_view].restore_statline()
+                    }
+                }
+            },
+            KeyCode::CtrlE => {
+                self.views[self.current_view].on_prompt(&String::from(&Command::Play));
+                match self.statline_input(Command::Play) {
+                    Some(StatlineCommand::PlayMacro(name, repeat)) => self.play_macro(&name, repeat),
+                    Some(StatlineCommand::Error(msg)) => self.statline_error_msg(&msg),
+                    _ => self.views[self.current_view].restore_statline()
+                }
+            },
+            KeyCode::Escaped(_esk) => {
+                self.cmd_engine.break_group();
+                match _esk {
+                    EscapeKeyCode::Right => {
+                        let pos = self.buffers[self.current_buffer].lock().unwrap().move_cursor(MoveKind::Char(MoveDir::Next)).unwrap();
+                        let vc = ViewCursor::at(&self.buffers[self.current_buffer].lock().unwrap(), &pos);
+                        self.views[0].view_cursor = vc;
+                        if (self.views[0].view_cursor.row - self.views[0].line_range.start) == self.views[0].win_size.1 as usize {
+                            self.views[0].scroll_down();
+                            self.views[0].draw_view();
+                            // self.views[0].view_cursor.row -= self.views[0].line_range.start;
+                        } else {
+                            self.views[0].view_cursor.row -= self.views[0].line_range.start;
+                        }
+                        self.views[0].statline_update_line_number(pos.line_index+1, vc.col);
+                    },
+                    EscapeKeyCode::Left => {
+                        let pos = self.buffers[self.current_buffer].lock().unwrap().move_cursor(MoveKind::Char(MoveDir::Previous)).unwrap();
+                        let vc = ViewCursor::at(&self.buffers[self.current_buffer].lock().unwrap(), &pos);
+                        self.views[0].view_cursor = vc;
+                        if (self.views[0].view_cursor.row - self.views[0].line_range.start) == 0 {
+                            self.views[0].scroll_up();
+                            self.views[0].draw_view();
+                        } else {
+                            self.views[0].view_cursor.row -= self.views[0].line_range.start;
+                        }
+                        self.views[0].statline_update_line_number(pos.line_index+1, vc.col);
+                    },
+                    EscapeKeyCode::Up => {
+                        let pos = self.buffers[self.current_buffer].lock().unwrap().move_cursor(MoveKind::Line(MoveDir::Previous)).unwrap();
+                        let vc = ViewCursor::at(&self.buffers[self.current_buffer].lock().unwrap(), &pos);
+                        self.views[0].view_cursor = vc;
+                        if (self.views[0].view_cursor.row - self.views[0].line_range.start) == 0 {
+                            self.views[0].scroll_up();
+                            self.views[0].draw_view();
+                        } else {
+                            self.views[0].view_cursor.row -= self.views[0].line_range.start;
+                            // print!("{}", self.views[0].view_cursor);
+                        }
+                        self.views[0].statline_update_line_number(pos.line_index+1, vc.col);
+                    },
+                    EscapeKeyCode::Down => {
+                        // self.buffers[self.current_buffer].lock().unwrap().move_cursor();
+                        let pos = self.buffers[self.current_buffer].lock().unwrap().move_cursor(MoveKind::Line(MoveDir::Next)).unwrap();
+                        let vc = ViewCursor::at(&self.buffers[self.current_buffer].lock().unwrap(), &pos);
+                        self.views[0].view_cursor = vc;
+                        if (self.views[0].view_cursor.row - self.views[0].line_range.start) == self.views[0].win_size.1 as usize {
+                            self.views[0].scroll_down();
+                            self.views[0].draw_view();
+                            // self.views[0].view_cursor.row -= self.views[0].line_range.start;
+                        } else {
+                            self.views[0].view_cursor.row -= self.views[0].line_range.start;
+                            // print!("{}", self.views[0].view_cursor);
+                        }
+                        // self.views[0].draw_view();
+                        self.views[0].restore_statline();
+                        self.views[0].statline_update_line_number(pos.line_index+1, vc.col);
+                    },
+                    EscapeKeyCode::Delete => {
+                        let (pos, ch) = {
+                            let guard = self.buffers[self.current_buffer].lock().unwrap();
+                            let pos = guard.get_textpos().absolute;
+                            (pos, guard.get_at(pos))
+                        };
+                        if let Some(c) = ch {
+                            if let OperationResult::OK = self.cmd_engine.execute(Operation::Delete(pos, c)) {
+                                self.views[self.current_view].draw_view();
                             }
-                            // self.views[0].draw_view();
-                            self.views[0].restore_statline();
-                            self.views[0].statline_update_line_number(pos.line_index+1, pos.get_line_position()+1);
                         }
+                    },
+                    esk => self.bound_command(KeyCode::Escaped(esk))
+                }
+                // print!("{}", _esk.output());stdout().lock().flush();
+            },
+            KeyCode::Alt(_) | KeyCode::Modified(_, _) => self.bound_command(kp),
+            KeyCode::None => self.statline_error_msg("Could not handle keypress!")
+        }
+    }
+
+    fn stop_macro_recording(&mut self) {
+        let name = self.cmd_engine.is_recording().map(|n| n.to_string());
+        match (self.cmd_engine.execute(Operation::MacroStop), name) {
+            (OperationResult::OK, Some(name)) => {
+                if let Some(m) = self.cmd_engine.get_macro(&name).cloned() {
+                    let len = m.len();
+                    self.config.set_macro(&name, m);
+                    let saved = self.config_path.as_ref().map(|path| (path, self.config.save_config(path)));
+                    match saved {
+                        Some((path, Err(e))) => self.statline_error_msg(&format!("Recorded {}, but couldn't save it to {}: {}", name, path.display(), e)),
+                        _ => self.views[self.current_view].on_statline_info("[recorded]: ", &format!("{} ({} keys)", name, len))
                     }
-                    // print!("{}", _esk.output());stdout().lock().flush();
-                },
-                KeyCode::None => println!("Could not handle keypress!\r")
+                }
+            },
+            (OperationResult::ERR(msg), _) => self.statline_error_msg(&msg),
+            _ => {}
+        }
+    }
+
+    /// Plays back a recorded macro, either a number of times at the cursor, or once at the start of every
+    /// line in a range. However many times it is played, it is undone as one change.
+    pub fn play_macro(&mut self, name: &str, repeat: MacroRepeat) {
+        if let OperationResult::ERR(msg) = self.cmd_engine.execute(Operation::MacroPlay(name.into())) {
+            self.statline_error_msg(&msg);
+            return;
+        }
+        let keys = self.cmd_engine.get_macro(name).map(|m| m.play().to_vec()).unwrap_or_default();
+        self.cmd_engine.begin_group();
+        match repeat {
+            MacroRepeat::Times(n) => {
+                for _ in 0..n {
+                    self.feed_keys(&keys);
+                }
+            },
+            MacroRepeat::Lines(from, to) => self.play_keys_on_lines(&keys, from - 1, to - 1),
+            MacroRepeat::AllLines => {
+                let last = self.buffers[self.current_buffer].lock().unwrap().line_count() - 1;
+                self.play_keys_on_lines(&keys, 0, last);
             }
-            // self.views[self.current_view].draw_view();
-        }
-    }
-
-    pub fn interpret_input_sequence(&self) -> KeyCode {
-        use libc::STDIN_FILENO;
-        let res = unsafe {
-            let mut ch_seq: [u8; 3] = [0,0,0];
-            let kc = if read(STDIN_FILENO, ch_seq.as_mut_ptr() as *mut libc::c_void, 1) == 0 {
-            // here, we are saying "we have not read anything more than 27 (Escape keycode) and/or there was an error reading the next
-            // part of the sequence, therefore -> return as if only "escape" was pressed on the keyboard.
-                KeyCode::Esc
-            } else if read(STDIN_FILENO, ch_seq.as_mut_ptr().offset(1) as *mut libc::c_void, 1) == -1 {
-                KeyCode::Esc // same here
-            } else {
-            // we successfully read more bytes in sequence after the escape keycode was found. Interpret what that sequence means.
-                if ch_seq[0] as char == '[' {
-                    match ch_seq[1] as char {
-                        'A' =>  {
-                            // up
-                            KeyCode::Escaped(EscapeKeyCode::Up)
-                        },
-                        'B' =>  {
-                            // down
-                            KeyCode::Escaped(EscapeKeyCode::Down)
-                        },
-                        'C' =>  {
-                            // right
-                            KeyCode::Escaped(EscapeKeyCode::Right)
-                        },
-                        'D' =>  {
-                            // left
-                            KeyCode::Escaped(EscapeKeyCode::Left)
-                        },
-                        _ => {
-                            KeyCode::None // handled for not yet implemented key sequences
-                        }
+        }
+        self.cmd_engine.end_group();
+        self.cmd_engine.end_macro_play();
+        self.views[self.current_view].draw_view();
+    }
+
+    /// Line indices are 0-indexed here. If the keys add or remove lines, the rest of the range is adjusted accordingly.
+    fn play_keys_on_lines(&mut self, keys: &[KeyCode], from: usize, to: usize) {
+        let mut line = from;
+        let mut last = to;
+        while line <= last {
+            let (start, lines_before) = {
+                let guard = self.buffers[self.current_buffer].lock().unwrap();
+                (guard.get_line_start_0_idx(line).map(|tp| tp.absolute), guard.line_count())
+            };
+            match start {
+                Some(pos) => self.buffers[self.current_buffer].lock().unwrap().set_textpos(pos),
+                None => break
+            }
+            self.feed_keys(keys);
+            let lines_after = self.buffers[self.current_buffer].lock().unwrap().line_count();
+            let delta = lines_after as isize - lines_before as isize;
+            // lines added by the macro are skipped, lines removed by it means the next line moved up to this one
+            line = std::cmp::max(line as isize + 1 + delta, line as isize) as usize;
+            if (last as isize + delta) < 0 {
+                break;
+            }
+            last = (last as isize + delta) as usize;
+        }
+    }
+
+    /// The terminal was resized. Every view is fitted to the new size, and the current one redrawn.
+    fn on_resize(&mut self) {
+        for view in self.views.iter_mut() {
+            view.on_resize();
+        }
+        self.views[self.current_view].draw_view();
+    }
+
+    /// Clicking places the cursor, dragging selects and the scroll wheel scrolls. The status line is left alone.
+    fn mouse_action(&mut self, ev: MouseEvent) {
+        let cv = self.current_view;
+        if ev.row == self.views[cv].status_line_position.row {
+            return;
+        }
+        match ev.action {
+            MouseAction::Press(MouseButton::Left) | MouseAction::Drag(MouseButton::Left) => {
+                let pos = match self.views[cv].buffer_position_at(ev.col, ev.row) {
+                    Some(pos) => pos,
+                    None => return
+                };
+                self.cmd_engine.break_group();
+                let tp = {
+                    let mut guard = self.buffers[self.current_buffer].lock().unwrap();
+                    match ev.action {
+                        MouseAction::Drag(_) if guard.get_mark().is_none() => guard.set_mark(),
+                        MouseAction::Press(_) => guard.clear_mark(),
+                        _ => {}
                     }
+                    guard.set_textpos(pos);
+                    guard.get_textpos()
+                };
+                self.views[cv].check_at_boundary_cross();
+                self.views[cv].statline_update_line_number(tp.line_index + 1, tp.get_line_position() + 1);
+            },
+            MouseAction::ScrollUp | MouseAction::ScrollDown => {
+                if let MouseAction::ScrollUp = ev.action {
+                    self.views[cv].scroll_up();
+                } else if self.views[cv].can_scroll_down() {
+                    self.views[cv].scroll_down();
+                } else {
+                    return;
+                }
+                // keep the cursor inside the view, otherwise drawing would scroll right back to it
+                let range = self.views[cv].line_range.clone();
+                let mut guard = self.buffers[self.current_buffer].lock().unwrap();
+                let line = guard.get_textpos().line_index;
+                let last_line = guard.line_count().saturating_sub(1).min(range.end.saturating_sub(1));
+                let target = if line < range.start {
+                    Some(range.start)
+                } else if line > last_line {
+                    Some(last_line)
                 } else {
-                    KeyCode::None
+                    None
+                };
+                if let Some(tp) = target.and_then(|l| guard.get_line_start_0_idx(l)) {
+                    guard.set_textpos(tp.absolute);
+                }
+                drop(guard);
+                self.views[cv].draw_view();
+            },
+            _ => {}
+        }
+    }
+
+    /// Moves the cursor of the current buffer, and scrolls the view along if the cursor leaves it
+    fn move_cursor(&mut self, mk: MoveKind) {
+        self.cmd_engine.break_group();
+        let cv = self.current_view;
+        let tp = {
+            let mut guard = self.buffers[self.current_buffer].lock().unwrap();
+            match mk {
+                MoveKind::Page(dir) => Some(guard.move_lines(&dir, self.views[cv].get_text_area_height())),
+                mk => guard.move_cursor(mk)
+            }
+        };
+        let tp = match tp {
+            Some(tp) => tp,
+            None => return
+        };
+        self.views[cv].scroll_to_cursor();
+        self.views[cv].draw_view();
+        let col = self.views[cv].view_cursor.col;
+        self.views[cv].statline_update_line_number(tp.line_index + 1, col);
+    }
+
+    /// Runs the command that a key outside of the key_bindings map, like Ctrl+Right or F3, is bound to
+    fn bound_command(&mut self, kc: KeyCode) {
+        match self.config.get_binding(kc.clone()).cloned().or_else(|| Config::default_modified_binding(&kc)) {
+            Some(Command::Move(mk)) => self.move_cursor(mk),
+            Some(Command::FindNext(dir)) => self.find_next(dir),
+            _ => {}
+        }
+    }
+
+    /**
+        Moves the cursor to the next match of `pattern`, forwards from the cursor with SeekFrom::Start and backwards
+        with SeekFrom::End, wrapping around the buffer if there are no more matches that way. A repeated search skips
+        the match the cursor is at. The status line says which of the matches it is.
+    */
+    fn find(&mut self, pattern: &str, options: &SearchOptions, dir: SeekFrom, repeat: bool) {
+        let search = match Search::new(pattern, options) {
+            Ok(search) => search,
+            Err(msg) => {
+                self.statline_error_msg(&msg);
+                return;
+            }
+        };
+        self.last_search = Some((pattern.to_string(), options.clone()));
+        let forward = dir == SeekFrom::Start;
+        let (matches, pos) = {
+            let guard = self.buffers[self.current_buffer].lock().unwrap();
+            (guard.find_all(&search), guard.get_textpos().absolute)
+        };
+        let from = if forward && repeat { pos + 1 } else { pos };
+        match next_match(&matches, from, forward) {
+            Some((idx, wrapped)) => {
+                self.cmd_engine.break_group();
+                self.buffers[self.current_buffer].lock().unwrap().set_textpos(matches[idx].start);
+                let cv = self.current_view;
+                self.views[cv].scroll_to_cursor();
+                self.views[cv].draw_view();
+                let wrap_info = if wrapped { ", wrapped around" } else { "" };
+                self.views[cv].on_statline_info("[find]: ", &format!("match {} of {}{}", idx + 1, matches.len(), wrap_info));
+            },
+            None => self.statline_error_msg(&format!("Pattern not found: {}", pattern))
+        }
+    }
+
+    /**
+        Replaces the matches of `pattern` in `scope` with `replacement`, all of them in one undo step. With `confirm`,
+        the matches are stepped through first, asking whether to replace each one: y(es), n(o), a(ll the rest) or
+        q(uit), which replaces the ones said yes to so far. Escape is the same as q.
+    */
+    fn replace(&mut self, pattern: &str, replacement: &str, scope: ReplaceScope, confirm: bool) {
+        let search = match Search::new(pattern, self.config.search_options()) {
+            Ok(search) => search,
+            Err(msg) => {
+                self.statline_error_msg(&msg);
+                return;
+            }
+        };
+        let (candidates, selected) = {
+            let guard = self.buffers[self.current_buffer].lock().unwrap();
+            let selection = guard.get_mark().map(|mark| {
+                let cursor = guard.get_textpos().absolute;
+                std::cmp::min(mark, cursor)..std::cmp::max(mark, cursor)
+            });
+            let range = match scope {
+                ReplaceScope::Buffer => 0..guard.len(),
+                ReplaceScope::Selection => selection.clone().unwrap_or(0..guard.len()),
+                ReplaceScope::Lines(from, to) => match guard.get_line_start_0_idx(from - 1) {
+                    // up to the newline that ends the last line of the range
+                    Some(start) => start.absolute..guard.get_line_start_0_idx(to).map_or(guard.len(), |tp| tp.absolute - 1),
+                    None => {
+                        drop(guard);
+                        self.statline_error_msg(&format!("Line {} is past the end of the buffer", from));
+                        return;
+                    }
                 }
             };
-            kc
+            let candidates = guard.replacements(&search, replacement).into_iter()
+                .filter(|(m, _)| m.start >= range.start && m.end <= range.end)
+                .collect::<Vec<_>>();
+            (candidates, scope == ReplaceScope::Selection && selection.is_some())
         };
-        res
+        if candidates.is_empty() {
+            self.statline_error_msg(&format!("Pattern not found: {}", pattern));
+            return;
+        }
+        let total = candidates.len();
+        let replacements = if confirm { self.confirm_replacements(candidates) } else { candidates };
+        if replacements.is_empty() {
+            self.views[self.current_view].on_statline_info("[replace]: ", "nothing replaced");
+            return;
+        }
+        let count = replacements.len();
+        match self.cmd_engine.execute(Operation::Replace(replacements)) {
+            OperationResult::OK => {
+                if selected {
+                    self.buffers[self.current_buffer].lock().unwrap().clear_mark();
+                }
+                let cv = self.current_view;
+                self.views[cv].scroll_to_cursor();
+                self.views[cv].draw_view();
+                self.views[cv].on_statline_info("[replaced]: ", &format!("{} of {} matches", count, total));
+            },
+            OperationResult::ERR(msg) => self.statline_error_msg(&msg)
+        }
+    }
+
+    /// Steps through the replacements, with the match at hand highlighted, and returns the ones that were said yes to
+    fn confirm_replacements(&mut self, candidates: Vec<(Range<usize>, String)>) -> Vec<(Range<usize>, String)> {
+        let matches = candidates.iter().map(|(m, _)| m.clone()).collect::<Vec<_>>();
+        let total = candidates.len();
+        let cv = self.current_view;
+        let mut accepted = vec![];
+        let mut all = false;
+        self.set_input_mode(Mode::Command);
+        for (idx, candidate) in candidates.into_iter().enumerate() {
+            if all {
+                accepted.push(candidate);
+                continue;
+            }
+            self.buffers[self.current_buffer].lock().unwrap().set_textpos(candidate.0.start);
+            self.views[cv].scroll_to_cursor();
+            self.views[cv].set_search_highlights(matches.clone(), Some(idx));
+            self.views[cv].draw_view();
+            let prompt = format!("[replace]: with '{}'? y/n/a/q ({} of {}) ", candidate.1, idx + 1, total);
+            self.views[cv].on_prompt(&prompt);
+            let (replace, quit) = loop {
+                match self.handle_keypress() {
+                    KeyCode::Character('y') => break (true, false),
+                    KeyCode::Character('n') => break (false, false),
+                    KeyCode::Character('a') => {
+                        all = true;
+                        break (true, false);
+                    },
+                    KeyCode::Character('q') | KeyCode::Esc => break (false, true),
+                    KeyCode::Resize => {
+                        self.on_resize();
+                        self.views[cv].on_prompt(&prompt);
+                    },
+                    _ => {}
+                }
+            };
+            if replace {
+                accepted.push(candidate);
+            }
+            if quit {
+                break;
+            }
+        }
+        self.set_input_mode(Mode::Normal);
+        self.views[cv].clear_search_highlights();
+        accepted
+    }
+
+    /// Starts searching the files under `root` for `pattern`, and shows the results buffer, which the matches are
+    /// listed in as they are found. Searching again replaces the results of the last search.
+    fn grep(&mut self, pattern: &str, root: PathBuf) {
+        let search = match Search::new(pattern, self.config.search_options()) {
+            Ok(search) => search,
+            Err(msg) => return self.statline_error_msg(&msg)
+        };
+        if !root.exists() {
+            return self.statline_error_msg(&format!("No such file or directory: {}", root.display()));
+        }
+        let incoming = spawn_grep(root.clone(), search, self.config.word_chars().clone());
+        self.replace_preview = None;
+        self.grep = Some(GrepResults { pattern: pattern.to_string(), root, matches: vec![], incoming: Some(incoming) });
+        self.show_results_buffer();
+        self.receive_grep_results(false);
     }
 
-    fn on_save(&self, file_name: &str, result: FResult) {
+    /// Empties the results buffer, which is created the first time it's needed, and shows it. Returns its index.
+    fn show_results_buffer(&mut self) -> usize {
+        let results = match self.results.take() {
+            Some(results) if results.buffer == self.current_buffer => results,
+            Some(results) => ResultsBuffer { buffer: results.buffer, return_to: self.current_buffer },
+            None => {
+                let mut buffer = Textbuffer::new();
+                buffer.set_tab_width(self.config.tab_width());
+                buffer.set_read_only(true);
+                self.buffers.push(Arc::new(Mutex::new(buffer)));
+                ResultsBuffer { buffer: self.buffers.len() - 1, return_to: self.current_buffer }
+            }
+        };
+        let buffer = results.buffer;
+        self.buffers[buffer].lock().unwrap().clear_buffer_contents();
+        self.results = Some(results);
+        self.switch_to_buffer(buffer);
+        buffer
+    }
+
+    fn viewing_results(&self) -> bool {
+        self.results.as_ref().is_some_and(|results| results.buffer == self.current_buffer)
+    }
+
+    /// Goes back from the results buffer to the buffer that was being edited
+    fn leave_results(&mut self) {
+        if let Some(return_to) = self.results.as_ref().filter(|_| self.viewing_results()).map(|results| results.return_to) {
+            self.switch_to_buffer(return_to);
+        }
+    }
+
+    /// Replaces the text of the results buffer with `lines`, keeping the cursor where it is if it can
+    fn set_results_text(&mut self, lines: &[String]) {
+        if let Some(results) = &self.results {
+            let mut guard = self.buffers[results.buffer].lock().unwrap();
+            let pos = guard.get_textpos().absolute;
+            guard.clear_buffer_contents();
+            guard.insert_data(&lines.join("\n"));
+            guard.set_textpos(0);
+            guard.set_textpos(pos);
+        }
+    }
+
+    /// Moves the matches that the search has found since the last time into the results buffer, and redraws it if it's
+    /// being looked at. With `wait`, this waits for the search to finish first. Once the search is done, or if nothing
+    /// was found since the last time, there is nothing to do.
+    fn receive_grep_results(&mut self, wait: bool) {
+        let (grep, buffer) = match (self.grep.as_mut(), &self.results) {
+            (Some(grep), Some(results)) => (grep, results.buffer),
+            _ => return
+        };
+        let incoming = match &grep.incoming {
+            Some(incoming) => incoming,
+            None => return
+        };
+        let mut received = vec![];
+        let done = loop {
+            let next = if wait { incoming.recv().map_err(|_| TryRecvError::Disconnected) } else { incoming.try_recv() };
+            match next {
+                Ok(m) => received.push(m),
+                Err(TryRecvError::Empty) => break false,
+                Err(TryRecvError::Disconnected) => break true
+            }
+        };
+        if done {
+            grep.incoming = None;
+        } else if received.is_empty() {
+            return;
+        }
+        if !received.is_empty() {
+            let lines = received.iter().map(|m| m.result_line(&grep.root)).collect::<Vec<String>>().join("\n");
+            let separator = if grep.matches.is_empty() { "" } else { "\n" };
+            // appended at the end, without moving the cursor of someone looking through the results already
+            let mut guard = self.buffers[buffer].lock().unwrap();
+            let pos = guard.get_textpos().absolute;
+            let end = guard.len();
+            guard.set_textpos(end);
+            guard.insert_data(&format!("{}{}", separator, lines));
+            guard.set_textpos(pos);
+            grep.matches.extend(received);
+        }
+        if self.current_buffer == buffer {
+            let status = match (grep.matches.len(), grep.incoming.is_some()) {
+                (0, false) => format!("no matches for {}", grep.pattern),
+                (n, false) => format!("{} matches for {}", n, grep.pattern),
+                (n, true) => format!("{} matches for {} so far, searching...", n, grep.pattern)
+            };
+            let cv = self.current_view;
+            self.views[cv].draw_view();
+            self.views[cv].on_statline_info("[grep]: ", &status);
+        }
+    }
+
+    /// Enter in the results buffer opens the grep match under the cursor, or makes the selected changes of a
+    /// replace across files
+    fn results_enter(&mut self) {
+        if self.grep.is_some() {
+            self.open_grep_result();
+        } else if self.replace_preview.is_some() {
+            self.apply_replace_in_files();
+        }
+    }
+
+    /// Goes to the match on the cursor's line of the results buffer. A file that is open already is switched to,
+    /// any other is opened in the buffer that was being edited, unless that has unsaved changes.
+    fn open_grep_result(&mut self) {
+        let line = self.buffers[self.current_buffer].lock().unwrap().get_textpos().line_index;
+        let m = match self.grep.as_ref().and_then(|grep| grep.matches.get(line)) {
+            Some(m) => m.clone(),
+            None => return
+        };
+        match (self.buffer_of_file(&m.path), self.results.as_ref().map(|results| results.return_to)) {
+            (Some(idx), _) => self.switch_to_buffer(idx),
+            (None, Some(return_to)) if self.buffers[return_to].lock().unwrap().is_dirty() => {
+                return self.statline_error_msg("Unsaved changes, save them first");
+            },
+            _ => {
+                self.on_open(&m.path.to_string_lossy());
+                if self.viewing_results() {
+                    return self.statline_error_msg(&format!("Couldn't open {}", m.path.display()));
+                }
+            }
+        }
+        let cv = self.current_view;
+        let tp = {
+            let mut guard = self.buffers[self.current_buffer].lock().unwrap();
+            if let Some(line_start) = guard.get_line_start_0_idx(m.line) {
+                guard.set_textpos(line_start.absolute + m.column);
+            }
+            guard.get_textpos()
+        };
+        self.views[cv].scroll_to_cursor();
+        self.views[cv].draw_view();
+        let col = self.views[cv].view_cursor.col;
+        self.views[cv].statline_update_line_number(tp.line_index + 1, col);
+    }
+
+    /// Finds what replacing `pattern` with `replacement` would change in the files under `root`, and lists it in the
+    /// results buffer, where the changes can be picked before they are made. A file that is open is replaced in as it
+    /// is in its buffer, rather than on disk.
+    fn replace_in_files(&mut self, pattern: &str, replacement: &str, root: PathBuf) {
+        let search = match Search::new(pattern, self.config.search_options()) {
+            Ok(search) => search,
+            Err(msg) => return self.statline_error_msg(&msg)
+        };
+        if !root.exists() {
+            return self.statline_error_msg(&format!("No such file or directory: {}", root.display()));
+        }
+        let word_chars = self.config.word_chars().clone();
+        let mut files = vec![];
+        walk_files(&root, |path| {
+            let text = match self.buffer_of_file(path) {
+                Some(idx) => Some(self.buffers[idx].lock().unwrap().dump_to_string()),
+                None => read_to_string(path).ok()
+            };
+            if let Some(changes) = text.and_then(|text| FileChanges::new(path.to_path_buf(), &text, &search, &word_chars, replacement)) {
+                files.push(changes);
+            }
+            true
+        });
+        if files.is_empty() {
+            return self.statline_error_msg(&format!("Pattern not found: {}", pattern));
+        }
+        self.grep = None;
+        self.replace_preview = Some(ReplacePreview { root, files, lines: vec![] });
+        self.show_results_buffer();
+        self.draw_replace_preview();
+    }
+
+    /// The buffer that has the file at `path` open, if there is one
+    fn buffer_of_file(&self, path: &Path) -> Option<usize> {
+        let path = canonicalize(path).ok()?;
+        self.buffers.iter().position(|buffer| {
+            buffer.lock().unwrap().file_name().and_then(|f| canonicalize(f).ok()).as_ref() == Some(&path)
+        })
+    }
+
+    /// Lists the changes of a replace across files in the results buffer: a line with the name of each file, and two
+    /// for each hunk in it, the line as it is and as it will be, marked by whether the hunk is selected.
+    fn draw_replace_preview(&mut self) {
+        let preview = match self.replace_preview.as_mut() {
+            Some(preview) => preview,
+            None => return
+        };
+        let (mut text, mut lines) = (vec![], vec![]);
+        for (f, changes) in preview.files.iter().enumerate() {
+            text.push(relative_path(&changes.path, &preview.root).display().to_string());
+            lines.push((f, None));
+            for (h, hunk) in changes.hunks.iter().enumerate() {
+                let mark = if hunk.selected { "[x]" } else { "[ ]" };
+                text.push(format!("{} {}: -{}", mark, hunk.line + 1, hunk.before));
+                text.push(format!("{} {}: +{}", mark, hunk.line + 1, hunk.after));
+                lines.extend_from_slice(&[(f, Some(h)), (f, Some(h))]);
+            }
+        }
+        preview.lines = lines;
+        let hunks = preview.files.iter().flat_map(|changes| changes.hunks.iter());
+        let (selected, total) = hunks.fold((0, 0), |(selected, total), hunk| (selected + hunk.selected as usize, total + 1));
+        let status = format!("{} of {} changes in {} files", selected, total, preview.files.len());
+        self.set_results_text(&text);
+        let cv = self.current_view;
+        self.views[cv].draw_view();
+        self.views[cv].on_statline_info("[replace in files]: ", &status);
+    }
+
+    /// Selects or deselects the hunk under the cursor in the results buffer. On the line of a file, all of the file's
+    /// hunks are deselected, or selected if none of them are.
+    fn toggle_hunk(&mut self) {
+        let line = self.buffers[self.current_buffer].lock().unwrap().get_textpos().line_index;
+        if let Some(preview) = self.replace_preview.as_mut() {
+            match preview.lines.get(line).cloned() {
+                Some((f, Some(h))) => preview.files[f].hunks[h].selected ^= true,
+                Some((f, None)) => {
+                    let select = preview.files[f].hunks.iter().all(|hunk| !hunk.selected);
+                    preview.files[f].hunks.iter_mut().for_each(|hunk| hunk.selected = select);
+                },
+                None => return
+            }
+        }
+        self.draw_replace_preview();
+    }
+
+    /// Makes the selected changes of a replace across files, and lists what was done to each file in the results buffer.
+    /// A file that is open with unsaved changes in it is only changed in its buffer, so that nothing in it is lost,
+    /// the others are saved. A file that has changed since the changes were listed is left as it is.
+    fn apply_replace_in_files(&mut self) {
+        let preview = match self.replace_preview.take() {
+            Some(preview) => preview,
+            None => return
+        };
+        let (mut summary, mut files, mut replaced) = (vec![], 0, 0);
+        for changes in preview.files.iter() {
+            let replacements = changes.selected_replacements();
+            if replacements.is_empty() {
+                continue;
+            }
+            let count = replacements.len();
+            let outcome = match self.buffer_of_file(&changes.path) {
+                Some(idx) if self.buffers[idx].lock().unwrap().dump_to_string() != changes.text => Err("it has changed since".to_string()),
+                Some(idx) => self.replace_in_buffer(idx, replacements),
+                None => read_to_string(&changes.path).map_err(|e| e.to_string()).and_then(|text| {
+                    if text != changes.text {
+                        return Err("it has changed since".to_string());
+                    }
+                    let mut buffer = Textbuffer::new();
+                    buffer.insert_data(&changes.apply());
+                    buffer.save_to_file(&changes.path, Some(FileOpt::Overwrite)).map(|_| "saved").map_err(|e| e.to_string())
+                })
+            };
+            let shown = relative_path(&changes.path, &preview.root).display();
+            match outcome {
+                Ok(how) => {
+                    files += 1;
+                    replaced += count;
+                    summary.push(format!("{}: {} replaced, {}", shown, count, how));
+                },
+                Err(e) => summary.push(format!("{}: not changed, {}", shown, e))
+            }
+        }
+        self.set_results_text(&summary);
+        let cv = self.current_view;
+        self.views[cv].draw_view();
+        self.views[cv].on_statline_info("[replaced]: ", &format!("{} matches in {} files", replaced, files));
+    }
+
+    /// Makes `replacements` in buffer `idx`, as one undo step. A buffer without unsaved changes is saved afterwards,
+    /// one with them is left for its changes to be saved along with the rest. Returns which of the two it was.
+    fn replace_in_buffer(&mut self, idx: usize, replacements: Vec<(Range<usize>, String)>) -> Result<&'static str, String> {
+        let dirty = self.buffers[idx].lock().unwrap().is_dirty();
+        self.cmd_engine.break_group();
+        self.cmd_engine.register_buffer(self.buffers[idx].clone());
+        let result = self.cmd_engine.execute(Operation::Replace(replacements));
+        self.cmd_engine.register_buffer(self.buffers[self.current_buffer].clone());
+        if let OperationResult::ERR(msg) = result {
+            return Err(msg);
+        }
+        if dirty {
+            return Ok("updated in its buffer");
+        }
+        let mut guard = self.buffers[idx].lock().unwrap();
+        let file_name = guard.file_name().map(Path::to_path_buf).ok_or("the buffer has no file")?;
+        guard.save_to_file(&file_name, Some(FileOpt::Overwrite)).map_err(|e| e.to_string())?;
+        guard.set_pristine();
+        Ok("saved")
+    }
+
+    /// Shows buffer `idx` in the current view, and makes it the one that edits go to
+    fn switch_to_buffer(&mut self, idx: usize) {
+        self.cmd_engine.break_group();
+        self.current_buffer = idx;
+        self.cmd_engine.register_buffer(self.buffers[idx].clone());
+        let cv = self.current_view;
+        let view = &mut self.views[cv];
+        view.set_viewed_buf(self.buffers[idx].clone());
+        view.clear_search_highlights();
+        view.line_range = 0..view.line_range.len();
+        view.scroll_to_cursor();
+        view.draw_view();
+    }
+
+    fn find_next(&mut self, dir: SeekFrom) {
+        match self.last_search.clone() {
+            Some((pattern, options)) => self.find(&pattern, &options, dir, true),
+            None => self.statline_error_msg("No previous search to repeat")
+        }
+    }
+
+    /// Processes keys as if they were typed. Anything that reads input while processing them (like the status line
+    /// prompts), will read the rest of the keys first.
+    fn feed_keys(&mut self, keys: &[KeyCode]) {
+        self.pending_input.extend(keys.iter().cloned());
+        while let Some(kc) = self.pending_input.pop_front() {
+            self.process_key(kc);
+        }
+    }
+
+    /// The operation a key is bound to in the config, or `default` if the config doesn't bind the key to an operation.
+    fn bound_action(&self, kc: &KeyCode, default: Operation) -> Operation {
+        match self.config.get_binding(kc.clone()) {
+            Some(Command::Action(op)) => op.clone(),
+            _ => default
+        }
+    }
+
+    /// Runs a copy, cut or paste operation. If there is an active selection, copying and cutting acts on that.
+    fn clipboard_action(&mut self, op: Operation) {
+        let has_selection = self.buffers[self.current_buffer].lock().unwrap().get_mark().is_some();
+        let op = match op {
+            Operation::Copy(_) if has_selection => Operation::Copy(ObjectKind::Selection),
+            Operation::Cut(_) if has_selection => Operation::Cut(ObjectKind::Selection),
+            op => op
+        };
+        let title = match &op {
+            Operation::Copy(_) => "[copied]: ",
+            Operation::Cut(_) => "[cut]: ",
+            _ => "[pasted]: "
+        };
+        let modifies_buffer = op.modifies_buffer();
+        let stores_text = matches!(op, Operation::Copy(_) | Operation::Cut(_));
+        match self.cmd_engine.execute(op) {
+            OperationResult::OK => {
+                if modifies_buffer {
+                    self.views[self.current_view].draw_view();
+                }
+                let text = self.cmd_engine.clipboard().last_stored().cloned().unwrap_or_default();
+                if stores_text {
+                    let copied = copy_to_system(self.config.system_clipboard(), &text, &mut *self.terminal.lock().unwrap());
+                    if let Err(e) = copied {
+                        self.statline_error_msg(&format!("Could not set the system clipboard: {}", e));
+                        return;
+                    }
+                }
+                let msg = if stores_text {
+                    let first_line = text.lines().next().unwrap_or("");
+                    format!("'{}' ({} characters)", first_line, text.chars().count())
+                } else {
+                    String::new()
+                };
+                self.views[self.current_view].on_statline_info(title, &msg);
+            },
+            OperationResult::ERR(msg) => {
+                self.statline_error_msg(&msg);
+            }
+        }
+    }
+
+    /// Runs one of the undo tree operations, and shows where in the history we ended up.
+    fn history_action(&mut self, op: Operation) {
+        match self.cmd_engine.execute(op) {
+            OperationResult::OK => {
+                self.views[self.current_view].draw_view();
+                let info = self.cmd_engine.history_info();
+                self.views[self.current_view].on_statline_info("[undo]: ", &info);
+            },
+            OperationResult::ERR(errmsg) => {
+                self.statline_error_msg(&errmsg);
+            }
+        }
+    }
+
+    fn on_save(&mut self, file_name: &str, result: FResult) {
         match result {
             Ok(file_size) => {
                 self.views[0].write_statline_line("[saved]: ", file_name.chars().chain(" successfully! Size: ".chars()).chain(file_size.to_string().chars()).collect::<String>().as_ref());
@@ -580,6 +1639,8 @@ impl Editor {
     fn on_open(&mut self, file_name: &str) {
         match read_to_string(Path::new(&file_name)) {
             Ok(data) => {
+                // files are opened in the buffer being edited, never in the results buffer
+                self.leave_results();
                 let lines_to_print = self.get_view().win_size.1 - 1;
                 let mut lindex = 0;
                 self.buffers[self.current_buffer].lock().unwrap().clear_buffer_contents();
@@ -594,7 +1655,13 @@ impl Editor {
                 d_to_print.chars().for_each(|c| {
                     self.views[self.current_view].write_character(c);
                 });
-                self.buffers[self.current_buffer].lock().unwrap().set_textpos(0);
+                {
+                    let mut guard = self.buffers[self.current_buffer].lock().unwrap();
+                    guard.set_textpos(0);
+                    guard.set_file_name(Path::new(file_name));
+                    guard.set_pristine();
+                }
+                self.cmd_engine.clear_history();
                 self.views[self.current_view].view_cursor = ViewCursor::default();
                 self.views[0].write_statline_line("[open]: ", &format!("successfully opened {}", file_name))
             },
@@ -604,76 +1671,69 @@ impl Editor {
         }        
     }
 
-    /// This function will not be able to handle any character keys other than English to begin with.
-    /// Since this will be a text editor meant solely for programming, it doesn't feel necessary either...
-    /// no sane compiler will compile variables with names that aren't in english.
-
+    /// Returns the next key to handle, which is queued up input (like a macro being played) if there is any,
+    /// otherwise the next keypress from the terminal. Characters are read as UTF-8, so any character can be typed.
     pub fn handle_keypress(&mut self) -> KeyCode {
-        use libc::STDIN_FILENO;
-        // :read from keyboard
-        //   if input == some key sequence (e.g. CTRL+S) -> create command
-        //   else if input == some char -> create operation(data)
-        // let mut c: u8;
-        let mut ch_buf: [u8; 3] = [0,0,0];
-        unsafe {
-            while read(STDIN_FILENO, ch_buf.as_mut_ptr() as *mut libc::c_void, 1) == 0 {}
-            let input = match ch_buf[0] {
-                1 => KeyCode::CtrlA,
-                2 => KeyCode::CtrlB,
-                3 => KeyCode::CtrlC,
-                7 => KeyCode::CtrlG,
-                8 => KeyCode::CtrlBackspace,
-                9 => KeyCode::Tab,
-                13 => KeyCode::Enter,
-                15 => KeyCode::CtrlO,
-                17 => KeyCode::CtrlQ,
-                19 => KeyCode::CtrlS,
-                22 => KeyCode::CtrlV,
-                23 => KeyCode::CtrlW,
-                26 => KeyCode::CtrlZ,
-                27 => {
-                    self.interpret_input_sequence()
-                },
-                195 => {
-                    if read(STDIN_FILENO, ch_buf.as_mut_ptr().offset(1) as *mut libc::c_void, 1) < 1 {
-                        KeyCode::None
-                    } else {
-                        KeyCode::Character(from_utf8(&ch_buf[..]).unwrap().chars().take(1).collect::<Vec<char>>()[0])
-                    }
-                },
-                127 => KeyCode::Backspace,
-                c @ _ if c >= 32 || c < 127 => {
-                    KeyCode::Character(c as char)
-                },
-                c @ _ if c > 127 => {
-                    KeyCode::None
-                },
-                _ => {
-                    KeyCode::None
-                }
-            };
-            input
+        if let Some(kc) = self.pending_input.pop_front() {
+            return kc;
         }
+        let kc = self.read_keypress();
+        if kc != KeyCode::Resize {
+            self.cmd_engine.record_key(&kc);
+        }
+        kc
     }
 
-    pub fn setup_rawmode(&mut self, fd: RawFd) -> std::io::Result<Termios> {
-        let mut term = Termios::from_fd(fd)?;
-        let original_setting = Termios::from_fd(0).unwrap();
-
-        term.c_iflag &= !(libc::BRKINT | libc::ICRNL | libc::INPCK | libc::ISTRIP | libc::IXON);
-        term.c_oflag &= !(libc::OPOST);
-        term.c_cflag |= libc::CS8 | libc::CREAD | libc::CLOCAL;
-        term.c_lflag &= !(libc::ECHO | libc::ICANON | libc::IEXTEN | libc::ISIG);
+    fn read_keypress(&mut self) -> KeyCode {
+        loop {
+            if let Some(kc) = self.poll_keypress() {
+                return kc;
+            }
+            // a grep going on in the background is shown as it goes, but not while a prompt is being typed into
+            if let Mode::Normal = self.input_mode {
+                self.receive_grep_results(false);
+            }
+        }
+    }
 
-        term.c_cc[libc::VMIN] = 0;
-        term.c_cc[libc::VTIME] = 1;
-        term.c_lflag = 0;
+    /// Returns the next keypress from the terminal, or None if nothing arrived within the read timeout.
+    /// A resize of the terminal is reported as KeyCode::Resize.
+    fn poll_keypress(&mut self) -> Option<KeyCode> {
+        let mut terminal = self.terminal.lock().unwrap();
+        if terminal.resized() {
+            return Some(KeyCode::Resize);
+        }
+        let first = terminal.read_byte()?;
+        Some(decode_key(first, &mut || terminal.read_byte()))
+    }
 
+    /// Handles keypresses until the terminal has no more input to give, or the editor quits. This is
+    /// what run() does, except that it returns instead of waiting for more input, which is what tests need.
+    pub fn process_input(&mut self) {
+        while self.running {
+            let kc = match self.pending_input.pop_front() {
+                Some(kc) => kc,
+                None => match self.poll_keypress() {
+                    Some(kc) => {
+                        if kc != KeyCode::Resize {
+                            self.cmd_engine.record_key(&kc);
+                        }
+                        kc
+                    },
+                    None if self.grep.as_ref().is_some_and(|grep| grep.incoming.is_some()) => {
+                        self.receive_grep_results(true);
+                        continue;
+                    },
+                    None => return
+                }
+            };
+            self.process_key(kc);
+        }
+    }
 
-        termios::cfsetspeed(&mut term, libc::B9600)?;
-        termios::tcsetattr(fd, libc::TCSANOW, &term)?;
-        termios::tcflush(fd, libc::TCIOFLUSH)?;
-        self.original_terminal_settings = Some(original_setting);
-        Ok(original_setting)
+    pub fn setup_rawmode(&mut self) -> std::io::Result<()> {
+        self.terminal.lock().unwrap().enable_raw_mode()?;
+        self.raw_mode = true;
+        Ok(())
     }
 }
\ No newline at end of file
@@ -0,0 +1,218 @@
+use crate::editor::key::{KeyCode, EscapeKeyCode, Modifiers, MouseEvent, MouseAction, MouseButton};
+
+/**
+    Decodes one keypress out of the bytes the terminal sends. `first` is the first byte of the keypress and `next`
+    returns the bytes after it, or None if nothing more has arrived. That's what tells a lone Escape apart from
+    the start of an escape sequence, so in the editor `next` reads with a timeout.
+*/
+pub fn decode_key<F: FnMut() -> Option<u8>>(first: u8, next: &mut F) -> KeyCode {
+    match first {
+        0 => KeyCode::CtrlSpace,
+        1 => KeyCode::CtrlA,
+        2 => KeyCode::CtrlB,
+        3 => KeyCode::CtrlC,
+        4 => KeyCode::CtrlD,
+        5 => KeyCode::CtrlE,
+        6 => KeyCode::CtrlF,
+        7 => KeyCode::CtrlG,
+        8 => KeyCode::CtrlBackspace,
+        9 => KeyCode::Tab,
+        11 => KeyCode::CtrlK,
+        12 => KeyCode::CtrlL,
+        10 | 13 => KeyCode::Enter,
+        14 => KeyCode::CtrlN,
+        15 => KeyCode::CtrlO,
+        16 => KeyCode::CtrlP,
+        17 => KeyCode::CtrlQ,
+        18 => KeyCode::CtrlR,
+        19 => KeyCode::CtrlS,
+        20 => KeyCode::CtrlT,
+        21 => KeyCode::CtrlU,
+        22 => KeyCode::CtrlV,
+        23 => KeyCode::CtrlW,
+        24 => KeyCode::CtrlX,
+        25 => KeyCode::CtrlY,
+        26 => KeyCode::CtrlZ,
+        27 => decode_escape(next),
+        127 => KeyCode::Backspace,
+        c if (32..127).contains(&c) => KeyCode::Character(c as char),
+        c => decode_utf8(c, next)
+    }
+}
+
+/// How many bytes a UTF-8 sequence starting with `lead` is made of, or None if it can't start one
+/// (continuation bytes, and the lead bytes that would only ever make overlong or out of range sequences).
+fn utf8_sequence_len(lead: u8) -> Option<usize> {
+    match lead {
+        0xc2..=0xdf => Some(2),
+        0xe0..=0xef => Some(3),
+        0xf0..=0xf4 => Some(4),
+        _ => None
+    }
+}
+
+/// Reads the rest of a multi-byte character. Anything that isn't valid UTF-8 (a stray continuation byte, a sequence
+/// cut short, an overlong encoding or a surrogate) is dropped, and decodes to KeyCode::None.
+fn decode_utf8<F: FnMut() -> Option<u8>>(lead: u8, next: &mut F) -> KeyCode {
+    let len = match utf8_sequence_len(lead) {
+        Some(len) => len,
+        None => return KeyCode::None
+    };
+    let mut bytes = [lead, 0, 0, 0];
+    for b in bytes[1..len].iter_mut() {
+        match next() {
+            Some(c) if c & 0xc0 == 0x80 => *b = c,
+            _ => return KeyCode::None
+        }
+    }
+    std::str::from_utf8(&bytes[..len]).ok()
+        .and_then(|s| s.chars().next())
+        .map_or(KeyCode::None, KeyCode::Character)
+}
+
+/// Decodes what follows an Escape byte: a CSI sequence (ESC [), an SS3 sequence (ESC O), Alt+key (ESC key)
+/// or nothing at all, which means Escape itself was pressed.
+fn decode_escape<F: FnMut() -> Option<u8>>(next: &mut F) -> KeyCode {
+    match next() {
+        None => KeyCode::Esc,
+        Some(b'[') => decode_csi(next),
+        Some(b'O') => {
+            match next() {
+                Some(b) => escaped(ss3_key(b), Modifiers::default()),
+                None => KeyCode::Alt('O')
+            }
+        },
+        Some(c) if (32..127).contains(&c) => KeyCode::Alt(c as char),
+        Some(_) => KeyCode::None
+    }
+}
+
+/// CSI sequences look like ESC [ <parameters> <final byte>, where the parameters are numbers separated by ';'.
+/// The second parameter, if there is one, holds the modifiers, like in ESC[1;5C (Ctrl+Right) or ESC[3;2~ (Shift+Delete).
+fn decode_csi<F: FnMut() -> Option<u8>>(next: &mut F) -> KeyCode {
+    let mut params = String::new();
+    let final_byte = loop {
+        match next() {
+            Some(b) if (0x30..0x40).contains(&b) => params.push(b as char),
+            Some(b) if (0x40..0x7f).contains(&b) => break b,
+            _ => return KeyCode::None
+        }
+    };
+    if params.starts_with('<') && (final_byte == b'M' || final_byte == b'm') {
+        return decode_sgr_mouse(&params[1..], final_byte == b'M');
+    }
+    let mut numbers = params.split(';').map(|p| p.parse::<u32>().ok());
+    let first = numbers.next().flatten();
+    let modifiers = numbers.next().flatten().map_or(Modifiers::default(), Modifiers::from_param);
+    let key = match final_byte {
+        b'~' if first == Some(200) => return read_paste(next),
+        b'~' => first.and_then(tilde_key),
+        b'Z' => Some(EscapeKeyCode::BackTab),
+        b => ss3_key(b)
+    };
+    escaped(key, modifiers)
+}
+
+/// SGR mouse reports look like ESC [ < button ; column ; row M, with a final m instead of M when a button is released.
+/// The button number also carries the modifiers (4 shift, 8 alt, 16 ctrl), whether the mouse moved while the button
+/// was held down (32) and whether it's the scroll wheel (64).
+fn decode_sgr_mouse(params: &str, pressed: bool) -> KeyCode {
+    let values = params.split(';').map(|p| p.parse::<usize>().ok()).collect::<Option<Vec<usize>>>();
+    let (b, col, row) = match values.as_deref() {
+        Some(&[b, col, row]) => (b, col, row),
+        _ => return KeyCode::None
+    };
+    let button = match b & 3 {
+        0 => Some(MouseButton::Left),
+        1 => Some(MouseButton::Middle),
+        2 => Some(MouseButton::Right),
+        _ => None
+    };
+    let action = if b & 64 != 0 {
+        match b & 3 {
+            0 => Some(MouseAction::ScrollUp),
+            1 => Some(MouseAction::ScrollDown),
+            _ => None
+        }
+    } else if !pressed {
+        Some(MouseAction::Release)
+    } else if b & 32 != 0 {
+        button.map(MouseAction::Drag)
+    } else {
+        button.map(MouseAction::Press)
+    };
+    let modifiers = Modifiers {
+        shift: b & 4 != 0,
+        alt: b & 8 != 0,
+        ctrl: b & 16 != 0
+    };
+    action.map_or(KeyCode::None, |action| KeyCode::Mouse(MouseEvent { action, col, row, modifiers }))
+}
+
+const PASTE_END: &[u8] = b"\x1b[201~";
+
+/// How many reads in a row may come back empty in the middle of a paste, before giving up on the end marker ever arriving
+const PASTE_MAX_WAITS: usize = 10;
+
+/// Reads bracketed paste contents, i.e. everything up until ESC[201~. Line endings are turned into \n, since
+/// terminals send the pasted newlines as \r.
+fn read_paste<F: FnMut() -> Option<u8>>(next: &mut F) -> KeyCode {
+    let mut data: Vec<u8> = vec![];
+    let mut waits = 0;
+    while !data.ends_with(PASTE_END) {
+        match next() {
+            Some(b) => {
+                data.push(b);
+                waits = 0;
+            },
+            None if waits < PASTE_MAX_WAITS => waits += 1,
+            None => break
+        }
+    }
+    if data.ends_with(PASTE_END) {
+        data.truncate(data.len() - PASTE_END.len());
+    }
+    let text = String::from_utf8_lossy(&data).replace("\r\n", "\n").replace('\r', "\n");
+    KeyCode::Paste(text)
+}
+
+fn escaped(key: Option<EscapeKeyCode>, modifiers: Modifiers) -> KeyCode {
+    match key {
+        Some(key) if modifiers.is_empty() => KeyCode::Escaped(key),
+        Some(key) => KeyCode::Modified(key, modifiers),
+        None => KeyCode::None
+    }
+}
+
+/// The keys that are identified by a final letter, which are the same for CSI and SS3 sequences.
+fn ss3_key(b: u8) -> Option<EscapeKeyCode> {
+    match b {
+        b'A' => Some(EscapeKeyCode::Up),
+        b'B' => Some(EscapeKeyCode::Down),
+        b'C' => Some(EscapeKeyCode::Right),
+        b'D' => Some(EscapeKeyCode::Left),
+        b'H' => Some(EscapeKeyCode::Home),
+        b'F' => Some(EscapeKeyCode::End),
+        b'P' => Some(EscapeKeyCode::F(1)),
+        b'Q' => Some(EscapeKeyCode::F(2)),
+        b'R' => Some(EscapeKeyCode::F(3)),
+        b'S' => Some(EscapeKeyCode::F(4)),
+        _ => None
+    }
+}
+
+/// The keys sent as ESC [ <number> ~ (the vt220 style)
+fn tilde_key(n: u32) -> Option<EscapeKeyCode> {
+    match n {
+        1 | 7 => Some(EscapeKeyCode::Home),
+        2 => Some(EscapeKeyCode::Insert),
+        3 => Some(EscapeKeyCode::Delete),
+        4 | 8 => Some(EscapeKeyCode::End),
+        5 => Some(EscapeKeyCode::PageUp),
+        6 => Some(EscapeKeyCode::PageDown),
+        11..=15 => Some(EscapeKeyCode::F((n - 10) as u8)),
+        17..=21 => Some(EscapeKeyCode::F((n - 11) as u8)),
+        23 | 24 => Some(EscapeKeyCode::F((n - 12) as u8)),
+        _ => None
+    }
+}
@@ -2,22 +2,40 @@ use crate::{Serialize, Deserialize};
 #[derive(Hash, Eq, PartialOrd, PartialEq, Clone, Serialize, Deserialize, Debug)]
 pub enum KeyCode {
     CtrlBackspace,
+    CtrlSpace,
     CtrlA,
     CtrlB,
     CtrlC,
+    CtrlD,
+    CtrlE,
+    CtrlF,
     CtrlG,
+    CtrlK,
+    CtrlL,
+    CtrlN,
+    CtrlP,
     CtrlS,
+    CtrlT,
+    CtrlU,
     CtrlV,
     CtrlO,
     CtrlQ,
+    CtrlR,
     CtrlZ,
     CtrlW,
+    CtrlX,
+    CtrlY,
     Enter,
     Tab,
     Esc,
     Backspace,
     Character(char),
+    Alt(char),
     Escaped(EscapeKeyCode),
+    Modified(EscapeKeyCode, Modifiers),
+    Paste(String),
+    Mouse(MouseEvent),
+    Resize,
     None,
 }
 
@@ -27,15 +45,27 @@ impl KeyCode {
             KeyCode::CtrlA=> 1,
             KeyCode::CtrlB=> 2,
             KeyCode::CtrlC=> 3,
+            KeyCode::CtrlD=> 4,
+            KeyCode::CtrlE=> 5,
+            KeyCode::CtrlF=> 6,
             KeyCode::CtrlG=> 7,
             KeyCode::CtrlBackspace=> 8,
             KeyCode::Tab=> 9,
+            KeyCode::CtrlK=> 11,
+            KeyCode::CtrlL=> 12,
             KeyCode::Enter =>13,
+            KeyCode::CtrlN =>14,
             KeyCode::CtrlO =>15,
+            KeyCode::CtrlP =>16,
             KeyCode::CtrlQ =>17,
+            KeyCode::CtrlR =>18,
             KeyCode::CtrlS =>19,
+            KeyCode::CtrlT =>20,
+            KeyCode::CtrlU =>21,
             KeyCode::CtrlV =>22,
             KeyCode::CtrlW => 23,
+            KeyCode::CtrlX => 24,
+            KeyCode::CtrlY =>25,
             KeyCode::CtrlZ =>26,
             _ => 0
         }
@@ -46,9 +76,21 @@ impl KeyCode {
             1 => KeyCode::CtrlA,
             2 => KeyCode::CtrlB,
             3 => KeyCode::CtrlC,
+            11 => KeyCode::CtrlK,
+            12 => KeyCode::CtrlL,
+            14 => KeyCode::CtrlN,
+            16 => KeyCode::CtrlP,
+            24 => KeyCode::CtrlX,
+            4 => KeyCode::CtrlD,
+            5 => KeyCode::CtrlE,
+            6 => KeyCode::CtrlF,
             19 => KeyCode::CtrlS,
             15 => KeyCode::CtrlO,
             17 => KeyCode::CtrlQ,
+            18 => KeyCode::CtrlR,
+            20 => KeyCode::CtrlT,
+            21 => KeyCode::CtrlU,
+            25 => KeyCode::CtrlY,
             26 => KeyCode::CtrlZ,
             _ => KeyCode::None
         }
@@ -60,7 +102,15 @@ pub enum EscapeKeyCode {
     Left,
     Right,
     Up,
-    Down
+    Down,
+    Home,
+    End,
+    Insert,
+    Delete,
+    PageUp,
+    PageDown,
+    BackTab,
+    F(u8)
 }
 
 impl EscapeKeyCode {
@@ -69,7 +119,58 @@ impl EscapeKeyCode {
             EscapeKeyCode::Down => "\x1b[1B",
             EscapeKeyCode::Up => "\x1b[1A",
             EscapeKeyCode::Left => "\x1b[1D",
-            EscapeKeyCode::Right => "\x1b[1C"
+            EscapeKeyCode::Right => "\x1b[1C",
+            _ => ""
         }
     }
-}
\ No newline at end of file
+}
+
+/// Modifier keys held down together with one of the escaped keys, e.g. Ctrl+Right
+#[derive(Hash, Eq, PartialOrd, PartialEq, Clone, Copy, Serialize, Deserialize, Debug, Default)]
+pub struct Modifiers {
+    pub shift: bool,
+    pub alt: bool,
+    pub ctrl: bool
+}
+
+impl Modifiers {
+    /// Terminals send the modifiers as 1 + a bitmask of shift (1), alt (2), ctrl (4) and meta (8).
+    /// Meta is treated as alt, as most keyboards do not have a separate meta key.
+    pub fn from_param(param: u32) -> Modifiers {
+        let mask = param.saturating_sub(1);
+        Modifiers {
+            shift: mask & 1 != 0,
+            alt: mask & (2 | 8) != 0,
+            ctrl: mask & 4 != 0
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        !(self.shift || self.alt || self.ctrl)
+    }
+}
+
+#[derive(Hash, Eq, PartialOrd, PartialEq, Clone, Copy, Serialize, Deserialize, Debug)]
+pub enum MouseButton {
+    Left,
+    Middle,
+    Right
+}
+
+#[derive(Hash, Eq, PartialOrd, PartialEq, Clone, Copy, Serialize, Deserialize, Debug)]
+pub enum MouseAction {
+    Press(MouseButton),
+    Drag(MouseButton),
+    Release,
+    ScrollUp,
+    ScrollDown
+}
+
+/// A mouse event, at a 1-indexed terminal column and row (the same coordinates as ViewCursor)
+#[derive(Hash, Eq, PartialOrd, PartialEq, Clone, Copy, Serialize, Deserialize, Debug)]
+pub struct MouseEvent {
+    pub action: MouseAction,
+    pub col: usize,
+    pub row: usize,
+    pub modifiers: Modifiers
+}
@@ -3,8 +3,239 @@ pub mod view;
 pub mod color;
 pub mod mode;
 pub mod key;
+pub mod input;
+pub mod screen;
+pub mod terminal;
 
 pub enum FileOpt {
     NoOverwrite,
     Overwrite
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::input::decode_key;
+    use super::view::{fit_line_range, View, ViewCursor, ViewOperations};
+    use super::screen::Screen;
+    use super::terminal::{VirtualTerminal, TerminalBackend};
+    use std::io::Write;
+    use super::color::{SetColor, Color};
+    use super::key::{KeyCode, EscapeKeyCode, Modifiers, MouseEvent, MouseAction, MouseButton};
+
+    fn decode(bytes: &[u8]) -> KeyCode {
+        let mut rest = bytes[1..].iter().cloned();
+        decode_key(bytes[0], &mut || rest.next())
+    }
+
+    fn with(shift: bool, alt: bool, ctrl: bool) -> Modifiers {
+        Modifiers { shift, alt, ctrl }
+    }
+
+    #[test]
+    fn test_decode_plain_keys() {
+        assert_eq!(KeyCode::Character('a'), decode(b"a"));
+        assert_eq!(KeyCode::CtrlSpace, decode(&[0]));
+        assert_eq!(KeyCode::CtrlS, decode(&[19]));
+        assert_eq!(KeyCode::Enter, decode(&[13]));
+        assert_eq!(KeyCode::Backspace, decode(&[127]));
+        assert_eq!(KeyCode::CtrlD, decode(&[4]));
+        assert_eq!(KeyCode::None, decode(&[28]));
+        assert_eq!(KeyCode::Esc, decode(&[27]));
+        assert_eq!(KeyCode::Alt('x'), decode(b"\x1bx"));
+    }
+
+    #[test]
+    fn test_decode_escape_sequences() {
+        assert_eq!(KeyCode::Escaped(EscapeKeyCode::Up), decode(b"\x1b[A"));
+        assert_eq!(KeyCode::Escaped(EscapeKeyCode::Home), decode(b"\x1b[H"));
+        assert_eq!(KeyCode::Escaped(EscapeKeyCode::Home), decode(b"\x1b[1~"));
+        assert_eq!(KeyCode::Escaped(EscapeKeyCode::End), decode(b"\x1bOF"));
+        assert_eq!(KeyCode::Escaped(EscapeKeyCode::Insert), decode(b"\x1b[2~"));
+        assert_eq!(KeyCode::Escaped(EscapeKeyCode::Delete), decode(b"\x1b[3~"));
+        assert_eq!(KeyCode::Escaped(EscapeKeyCode::PageUp), decode(b"\x1b[5~"));
+        assert_eq!(KeyCode::Escaped(EscapeKeyCode::PageDown), decode(b"\x1b[6~"));
+        assert_eq!(KeyCode::Escaped(EscapeKeyCode::F(1)), decode(b"\x1bOP"));
+        assert_eq!(KeyCode::Escaped(EscapeKeyCode::F(5)), decode(b"\x1b[15~"));
+        assert_eq!(KeyCode::Escaped(EscapeKeyCode::F(12)), decode(b"\x1b[24~"));
+        assert_eq!(KeyCode::Escaped(EscapeKeyCode::BackTab), decode(b"\x1b[Z"));
+        assert_eq!(KeyCode::None, decode(b"\x1b[99~"));
+        assert_eq!(KeyCode::None, decode(b"\x1b[1;5"));
+    }
+
+    #[test]
+    fn test_decode_modifiers() {
+        assert_eq!(KeyCode::Modified(EscapeKeyCode::Right, with(false, false, true)), decode(b"\x1b[1;5C"));
+        assert_eq!(KeyCode::Modified(EscapeKeyCode::Left, with(true, false, false)), decode(b"\x1b[1;2D"));
+        assert_eq!(KeyCode::Modified(EscapeKeyCode::Up, with(false, true, false)), decode(b"\x1b[1;3A"));
+        assert_eq!(KeyCode::Modified(EscapeKeyCode::Delete, with(true, false, true)), decode(b"\x1b[3;6~"));
+        assert_eq!(KeyCode::Modified(EscapeKeyCode::F(1), with(true, true, true)), decode(b"\x1b[1;8P"));
+        assert_eq!(KeyCode::Escaped(EscapeKeyCode::Down), decode(b"\x1b[1;1B"));
+    }
+
+    #[test]
+    fn test_decode_utf8() {
+        for ch in "é—λ中😀".chars() {
+            let mut bytes = [0; 4];
+            assert_eq!(KeyCode::Character(ch), decode(ch.encode_utf8(&mut bytes).as_bytes()));
+        }
+        // stray continuation byte, invalid lead byte, cut short, overlong and a surrogate
+        assert_eq!(KeyCode::None, decode(&[0x80]));
+        assert_eq!(KeyCode::None, decode(&[0xff]));
+        assert_eq!(KeyCode::None, decode(&[0xe2, 0x80]));
+        assert_eq!(KeyCode::None, decode(&[0xe2, b'a', b'b']));
+        assert_eq!(KeyCode::None, decode(&[0xe0, 0x80, 0xaf]));
+        assert_eq!(KeyCode::None, decode(&[0xed, 0xa0, 0x80]));
+    }
+
+    #[test]
+    fn test_decode_bracketed_paste() {
+        assert_eq!(KeyCode::Paste("fn main() {\n\tprintln!(\"hé\");\n}".into()),
+                   decode("\x1b[200~fn main() {\r\tprintln!(\"hé\");\r\n}\x1b[201~".as_bytes()));
+        assert_eq!(KeyCode::Paste("\x1b[A".into()), decode(b"\x1b[200~\x1b[A\x1b[201~"));
+        // the end marker never arrives
+        assert_eq!(KeyCode::Paste("abc".into()), decode(b"\x1b[200~abc"));
+    }
+
+    #[test]
+    fn test_decode_sgr_mouse() {
+        let mouse = |action, col, row, modifiers| KeyCode::Mouse(MouseEvent { action, col, row, modifiers });
+        assert_eq!(mouse(MouseAction::Press(MouseButton::Left), 10, 5, Modifiers::default()), decode(b"\x1b[<0;10;5M"));
+        assert_eq!(mouse(MouseAction::Release, 10, 5, Modifiers::default()), decode(b"\x1b[<0;10;5m"));
+        assert_eq!(mouse(MouseAction::Drag(MouseButton::Left), 12, 6, Modifiers::default()), decode(b"\x1b[<32;12;6M"));
+        assert_eq!(mouse(MouseAction::Press(MouseButton::Right), 1, 1, with(false, false, true)), decode(b"\x1b[<18;1;1M"));
+        assert_eq!(mouse(MouseAction::ScrollUp, 3, 4, Modifiers::default()), decode(b"\x1b[<64;3;4M"));
+        assert_eq!(mouse(MouseAction::ScrollDown, 3, 4, with(true, false, false)), decode(b"\x1b[<69;3;4M"));
+        assert_eq!(KeyCode::None, decode(b"\x1b[<0;10M"));
+        assert_eq!(KeyCode::None, decode(b"\x1b[<35;10;5M"));
+    }
+
+    #[test]
+    fn test_fit_line_range_on_resize() {
+        // growing, or shrinking with the cursor still in view, keeps the top line
+        assert_eq!(10..40, fit_line_range(10, 30, 15));
+        assert_eq!(10..15, fit_line_range(10, 5, 14));
+        // shrinking past the cursor moves the range down so the cursor is on the last line
+        assert_eq!(11..16, fit_line_range(10, 5, 15));
+        assert_eq!(3..8, fit_line_range(10, 5, 3));
+    }
+
+    #[test]
+    fn test_screen_renders_only_changes() {
+        let mut screen = Screen::new(4, 2);
+        screen.put_str("ab");
+        let first = screen.render();
+        assert!(first.starts_with("\x1b[1;1H\x1b[0mab  \x1b[2;1H    "));
+        // nothing changed, so only the cursor is placed
+        assert_eq!("\x1b[1;1H", screen.render());
+
+        screen.move_to(ViewCursor { row: 2, col: 2 });
+        screen.set_color(&SetColor::Foreground(Color::Red));
+        screen.put_str("xy");
+        screen.set_cursor(ViewCursor { row: 2, col: 4 });
+        assert_eq!("\x1b[2;2H\x1b[0;31mxy\x1b[m\x1b[2;4H", screen.render());
+
+        // writing the same contents again is not a change
+        screen.move_to(ViewCursor { row: 1, col: 1 });
+        screen.reset_style();
+        screen.put_str("ab");
+        assert_eq!("\x1b[2;4H", screen.render());
+    }
+
+    #[test]
+    fn test_screen_wide_characters() {
+        let mut screen = Screen::new(5, 1);
+        screen.put_str("a中e\u{301}");
+        assert_eq!(ViewCursor { row: 1, col: 5 }, screen.pen());
+        assert!(screen.cell(1, 3).unwrap().is_continuation());
+        assert_eq!("\x1b[1;1H\x1b[0ma中e\u{301} \x1b[m\x1b[1;1H", screen.render());
+        // overwriting half of a wide character blanks the other half
+        screen.move_to(ViewCursor { row: 1, col: 3 });
+        screen.put_char('x');
+        assert_eq!("a xe\u{301} ", screen.row_text(1));
+        // a wide character that doesn't fit at the end of the row
+        screen.move_to(ViewCursor { row: 1, col: 5 });
+        screen.put_str("中");
+        assert_eq!("a xe\u{301} ", screen.row_text(1));
+    }
+
+    #[test]
+    fn test_screen_operations() {
+        let mut screen = Screen::new(6, 2);
+        screen.put_str("hello\nworld!!");
+        assert_eq!("hello ", screen.row_text(1));
+        // clipped at the edge of the screen
+        assert_eq!("world!", screen.row_text(2));
+        screen.move_to(ViewCursor { row: 1, col: 3 });
+        screen.apply(ViewOperations::ClearLineRest);
+        screen.apply(ViewOperations::StepDown);
+        screen.put_char('_');
+        assert_eq!("he    ", screen.row_text(1));
+        assert_eq!("wo_ld!", screen.row_text(2));
+        screen.resize(3, 1);
+        assert_eq!((3, 1), screen.size());
+        assert_eq!("   ", screen.row_text(1));
+    }
+
+    #[test]
+    fn test_buffers_and_views_are_send_and_sync() {
+        use std::sync::{Arc, Mutex};
+        use crate::data::text_buffer::Textbuffer;
+        // buffers, like the results buffer of a grep or a replace across files, are shared behind an Arc<Mutex>
+        fn shareable<T: Send + Sync>() {}
+        shareable::<Arc<Mutex<Textbuffer>>>();
+        shareable::<Arc<View>>();
+    }
+
+    #[test]
+    fn test_view_search_highlights() {
+        use std::sync::{Arc, Mutex};
+        use crate::data::text_buffer::Textbuffer;
+        let mut view = View::new(Arc::new(Mutex::new(VirtualTerminal::new(20, 4)))).unwrap();
+        let mut tb = Textbuffer::new();
+        tb.insert_data("foo bar\n中foo");
+        view.set_viewed_buf(Arc::new(Mutex::new(tb)));
+        view.set_search_highlights(vec![0..3, 9..12], Some(1));
+        view.draw_view();
+        let bg = |view: &View, row, col| view.screen.cell(row, col).unwrap().style.bg;
+        assert_eq!(Some(Color::Yellow), bg(&view, 1, 1));
+        assert_eq!(Some(Color::Yellow), bg(&view, 1, 3));
+        assert_eq!(Some(Color::Blue), bg(&view, 1, 4));
+        assert_eq!(Some(Color::Blue), bg(&view, 2, 1));
+        assert_eq!(Some(Color::Green), bg(&view, 2, 3));
+        assert_eq!(Some(Color::Green), bg(&view, 2, 5));
+        assert_eq!(Some(Color::Blue), bg(&view, 2, 6));
+        view.clear_search_highlights();
+        view.draw_view();
+        assert_eq!(Some(Color::Blue), bg(&view, 1, 1));
+    }
+
+    #[test]
+    fn test_virtual_terminal() {
+        let mut vt = VirtualTerminal::new(8, 3);
+        // a rendered frame draws what the screen holds
+        let mut screen = Screen::new(8, 3);
+        screen.set_color(&SetColor::Foreground(Color::Red));
+        screen.put_str("red\n");
+        screen.reset_style();
+        screen.put_str("hallå");
+        screen.set_cursor(ViewCursor { row: 2, col: 4 });
+        screen.present(&mut vt).unwrap();
+        assert_eq!("red", vt.row_text(1));
+        assert_eq!("hallå", vt.row_text(2));
+        assert_eq!(Some(Color::Red), vt.cell(1, 1).unwrap().style.fg);
+        assert_eq!(None, vt.cell(2, 1).unwrap().style.fg);
+        assert_eq!(ViewCursor { row: 2, col: 4 }, vt.cursor());
+        // erasing, and modes and OSC strings that are skipped
+        vt.write_all(b"\x1b[?1006h\x1b]52;c;aGk=\x07\x1b[1;2H\x1b[K\x1b[3;1Hok").unwrap();
+        assert_eq!("r\nhallå\nok", vt.contents());
+        // scripted input
+        vt.feed(b"ab");
+        assert_eq!(Some(b'a'), vt.read_byte());
+        assert_eq!(Some(b'b'), vt.read_byte());
+        assert_eq!(None, vt.read_byte());
+        vt.resize(4, 2);
+        assert!(vt.resized());
+        assert!(!vt.resized());
+        assert_eq!("r\nhall", vt.contents());
+    }
+}
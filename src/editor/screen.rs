@@ -0,0 +1,300 @@
+use std::borrow::Cow;
+use std::fmt::Write as FmtWrite;
+use std::io::{self, Write};
+use unicode_segmentation::UnicodeSegmentation;
+use crate::data::text_buffer::grapheme_width;
+use crate::editor::color::{Color, SetColor};
+use crate::editor::view::{ViewCursor, ViewOperations};
+
+/// The colors a cell is drawn with. None means the terminal's default color.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct Style {
+    pub fg: Option<Color>,
+    pub bg: Option<Color>
+}
+
+impl Style {
+    pub fn with(mut self, color: &SetColor) -> Style {
+        match color {
+            SetColor::Foreground(c) => self.fg = Some(*c),
+            SetColor::Background(c) => self.bg = Some(*c)
+        }
+        self
+    }
+
+    /// The SGR sequence that resets the terminal's colors, and then sets this style's colors
+    fn sgr(&self) -> String {
+        let mut seq = String::from("\x1b[0");
+        if let Some(fg) = self.fg {
+            let _ = write!(seq, ";{}", fg as u8);
+        }
+        if let Some(bg) = self.bg {
+            let _ = write!(seq, ";{}", bg as u8 + 10);
+        }
+        seq.push('m');
+        seq
+    }
+}
+
+/// Printable ASCII, so that the text of most cells can be borrowed from here instead of being allocated
+const PRINTABLE: &str = " !\"#$%&'()*+,-./0123456789:;<=>?@ABCDEFGHIJKLMNOPQRSTUVWXYZ[\\]^_`abcdefghijklmnopqrstuvwxyz{|}~";
+
+/// One column on screen. A wide character takes up two cells: the first holds the grapheme cluster,
+/// the second one is a continuation cell, with no text of its own.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Cell {
+    pub text: Cow<'static, str>,
+    pub style: Style
+}
+
+impl Cell {
+    pub fn new(g: &str, style: Style) -> Cell {
+        let text = match g.as_bytes() {
+            [b @ 0x20..=0x7e] => Cow::Borrowed(&PRINTABLE[(*b - 0x20) as usize..(*b - 0x1f) as usize]),
+            _ => Cow::Owned(g.to_string())
+        };
+        Cell { text, style }
+    }
+
+    pub fn blank(style: Style) -> Cell {
+        Cell { text: Cow::Borrowed(" "), style }
+    }
+
+    /// The right half of a wide character
+    pub fn continuation(style: Style) -> Cell {
+        Cell { text: Cow::Borrowed(""), style }
+    }
+
+    pub fn is_continuation(&self) -> bool {
+        self.text.is_empty()
+    }
+}
+
+impl Default for Cell {
+    fn default() -> Self {
+        Cell::blank(Style::default())
+    }
+}
+
+/// What the front buffer is filled with when we don't know what the terminal shows. It never equals a
+/// real cell, so the next frame repaints everything.
+const UNKNOWN: Cell = Cell { text: Cow::Borrowed("\0"), style: Style { fg: None, bg: None } };
+
+/**
+    A grid of cells, double buffered. Everything is drawn into the back buffer, using a "pen" that is moved around
+    and writes with its current style, much like the escape sequences the terminal understands. A frame is
+    then rendered by diffing the back buffer against the front buffer (what the terminal is showing right now),
+    which gives the escape sequences that update only the cells that changed.
+    Rows and columns are 1-indexed, just like ViewCursor and the terminal's own coordinates.
+*/
+pub struct Screen {
+    width: usize,
+    height: usize,
+    front: Vec<Cell>,
+    back: Vec<Cell>,
+    pen: ViewCursor,
+    style: Style,
+    cursor: ViewCursor
+}
+
+impl Screen {
+    pub fn new(width: usize, height: usize) -> Screen {
+        Screen {
+            width,
+            height,
+            front: vec![UNKNOWN; width * height],
+            back: vec![Cell::default(); width * height],
+            pen: ViewCursor::default(),
+            style: Style::default(),
+            cursor: ViewCursor::default()
+        }
+    }
+
+    /// Resizes the grid. The contents are cleared, and the next frame repaints the entire screen.
+    pub fn resize(&mut self, width: usize, height: usize) {
+        *self = Screen::new(width, height);
+    }
+
+    /// Forgets what the terminal is showing, so that the next frame repaints the entire screen
+    pub fn invalidate(&mut self) {
+        self.front.iter_mut().for_each(|c| *c = UNKNOWN.clone());
+    }
+
+    pub fn size(&self) -> (usize, usize) {
+        (self.width, self.height)
+    }
+
+    pub fn move_to(&mut self, pos: ViewCursor) {
+        self.pen = ViewCursor { row: pos.row.max(1), col: pos.col.max(1) };
+    }
+
+    pub fn pen(&self) -> ViewCursor {
+        self.pen
+    }
+
+    pub fn set_color(&mut self, color: &SetColor) {
+        self.style = self.style.with(color);
+    }
+
+    pub fn reset_style(&mut self) {
+        self.style = Style::default();
+    }
+
+    /// Where the terminal's cursor is put after the frame has been rendered
+    pub fn set_cursor(&mut self, pos: ViewCursor) {
+        self.cursor = ViewCursor { row: pos.row.max(1), col: pos.col.max(1) };
+    }
+
+    pub fn cursor(&self) -> ViewCursor {
+        self.cursor
+    }
+
+    fn index(&self, row: usize, col: usize) -> Option<usize> {
+        if row >= 1 && row <= self.height && col >= 1 && col <= self.width {
+            Some((row - 1) * self.width + col - 1)
+        } else {
+            None
+        }
+    }
+
+    /// Writes a character at the pen, and moves the pen past it. A newline moves the pen to the start of the next row.
+    /// Anything written outside of the grid is clipped.
+    pub fn put_char(&mut self, ch: char) {
+        let mut buf = [0; 4];
+        self.put_grapheme(ch.encode_utf8(&mut buf));
+    }
+
+    /// Writes a grapheme cluster at the pen, and moves the pen past the columns it takes up. A wide character
+    /// that doesn't fit on the row anymore is drawn as a blank.
+    pub fn put_grapheme(&mut self, g: &str) {
+        if g == "\n" || g == "\r\n" {
+            self.pen.row += 1;
+            self.pen.col = 1;
+            return;
+        }
+        let ViewCursor { row, col } = self.pen;
+        let width = grapheme_width(g);
+        for c in col..col + width {
+            self.split_wide(row, c);
+        }
+        if let Some(idx) = self.index(row, col) {
+            if width > 1 && col + 1 > self.width {
+                self.back[idx] = Cell::blank(self.style);
+            } else {
+                self.back[idx] = Cell::new(g, self.style);
+                for idx in idx + 1..idx + width {
+                    self.back[idx] = Cell::continuation(self.style);
+                }
+            }
+        }
+        self.pen.col += width;
+    }
+
+    pub fn put_str(&mut self, s: &str) {
+        for g in s.graphemes(true) {
+            self.put_grapheme(g);
+        }
+    }
+
+    /// Blanks the other half of a wide character, when the half at `row`, `col` is about to be overwritten
+    fn split_wide(&mut self, row: usize, col: usize) {
+        let idx = match self.index(row, col) {
+            Some(idx) => idx,
+            None => return
+        };
+        let other = if self.back[idx].is_continuation() {
+            self.index(row, col - 1)
+        } else {
+            self.index(row, col + 1).filter(|next| self.back[*next].is_continuation())
+        };
+        if let Some(other) = other {
+            self.back[other] = Cell::blank(self.back[other].style);
+        }
+    }
+
+    /// Fills the entire grid with blanks in the current style
+    pub fn fill(&mut self) {
+        let blank = Cell::blank(self.style);
+        self.back.iter_mut().for_each(|c| *c = blank.clone());
+    }
+
+    /// Does to the grid what the ViewOperation's escape sequence would do to the terminal
+    pub fn apply(&mut self, op: ViewOperations) {
+        match op {
+            ViewOperations::ClearLineRest => {
+                let row = self.pen.row;
+                self.split_wide(row, self.pen.col);
+                for col in self.pen.col..=self.width {
+                    if let Some(idx) = self.index(row, col) {
+                        self.back[idx] = Cell::blank(self.style);
+                    }
+                }
+            },
+            ViewOperations::StepRight => self.pen.col += 1,
+            ViewOperations::StepLeft => self.pen.col = (self.pen.col - 1).max(1),
+            ViewOperations::StepUp => self.pen.row = (self.pen.row - 1).max(1),
+            ViewOperations::StepDown => self.pen.row += 1,
+            ViewOperations::LineStart => self.pen.col = 1,
+            // terminal modes, nothing that is drawn
+            _ => {}
+        }
+    }
+
+    /// Changes the style a cell is drawn with, but not what's in it
+    pub fn set_style_at(&mut self, pos: ViewCursor, style: Style) {
+        if let Some(idx) = self.index(pos.row, pos.col) {
+            self.back[idx].style = style;
+        }
+    }
+
+    pub fn cell(&self, row: usize, col: usize) -> Option<&Cell> {
+        self.index(row, col).map(|idx| &self.back[idx])
+    }
+
+    /// The characters of a row, as drawn in the back buffer
+    pub fn row_text(&self, row: usize) -> String {
+        (1..=self.width).filter_map(|col| self.cell(row, col)).map(|c| c.text.as_ref()).collect()
+    }
+
+    /// Diffs the frame against what the terminal is showing, and returns what has to be written to update it.
+    /// After this, the terminal is assumed to show the frame.
+    pub fn render(&mut self) -> String {
+        let mut out = String::new();
+        let mut style: Option<Style> = None;
+        // where the terminal cursor is after what has been written so far, if we know
+        let mut at: Option<usize> = None;
+        for idx in 0..self.back.len() {
+            let cell = &self.back[idx];
+            if self.front[idx] == *cell {
+                continue;
+            }
+            if cell.is_continuation() {
+                // drawn along with the wide character it is the right half of
+                self.front[idx] = cell.clone();
+                continue;
+            }
+            if at != Some(idx) || idx % self.width == 0 {
+                let _ = write!(out, "\x1b[{};{}H", idx / self.width + 1, idx % self.width + 1);
+            }
+            if style != Some(cell.style) {
+                out.push_str(&cell.style.sgr());
+                style = Some(cell.style);
+            }
+            out.push_str(&cell.text);
+            at = Some(idx + grapheme_width(&cell.text));
+            self.front[idx] = cell.clone();
+        }
+        if style.is_some() {
+            out.push_str("\x1b[m");
+        }
+        let _ = write!(out, "{}", self.cursor);
+        out
+    }
+
+    /// Renders the frame and writes it to the terminal, in one write.
+    pub fn present(&mut self, out: &mut dyn Write) -> io::Result<()> {
+        let frame = self.render();
+        out.write_all(frame.as_bytes())?;
+        out.flush()
+    }
+}
@@ -0,0 +1,410 @@
+use std::collections::VecDeque;
+use std::io::{self, Write, Stdout, stdout};
+use std::os::unix::io::RawFd;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::mem::zeroed;
+use libc::{STDOUT_FILENO, STDIN_FILENO, c_int, c_ulong, winsize};
+use termios::Termios;
+use crate::editor::view::{WinDim, ViewCursor};
+use crate::editor::screen::{Cell, Style};
+use unicode_width::UnicodeWidthChar;
+use crate::editor::color::Color;
+
+static TIOCGWINSZ: c_ulong = 0x5413; // Enum value basically, for requesting terminal window size
+
+extern "C" {
+    fn ioctl(fd: c_int, request: c_ulong, ...) -> c_int;
+}
+
+/**
+    Everything the editor needs from the terminal it runs in: its size, the bytes typed into it, somewhere to
+    write the output (the io::Write part) and putting it in and out of raw mode. Backends are Send, since the
+    views that write to them are observers of buffers, which are shared behind an Arc<Mutex>.
+*/
+pub trait TerminalBackend: Write + Send {
+    /// The size of the terminal, in columns and rows
+    fn size(&self) -> Option<WinDim>;
+    /// Reads one byte of input. Returns None if nothing arrived within a short timeout.
+    fn read_byte(&mut self) -> Option<u8>;
+    /// Whether the terminal has been resized since the last time this was asked
+    fn resized(&mut self) -> bool;
+    fn enable_raw_mode(&mut self) -> io::Result<()>;
+    fn disable_raw_mode(&mut self) -> io::Result<()>;
+}
+
+pub type Terminal = Arc<Mutex<dyn TerminalBackend>>;
+
+/// Set by the SIGWINCH handler, and picked up by TermiosTerminal::resized.
+static RESIZED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn on_sigwinch(_signal: c_int) {
+    RESIZED.store(true, Ordering::SeqCst);
+}
+
+/// The terminal the editor is started in, set up with termios.
+pub struct TermiosTerminal {
+    fd: RawFd,
+    original: Option<Termios>,
+    out: Stdout
+}
+
+impl TermiosTerminal {
+    pub fn new(fd: RawFd) -> TermiosTerminal {
+        TermiosTerminal {
+            fd,
+            original: None,
+            out: stdout()
+        }
+    }
+}
+
+impl TerminalBackend for TermiosTerminal {
+    fn size(&self) -> Option<WinDim> {
+        unsafe {
+            let mut window: winsize = zeroed();
+            if ioctl(STDOUT_FILENO, TIOCGWINSZ, &mut window) == -1 {
+                window = zeroed();
+                if ioctl(STDIN_FILENO, TIOCGWINSZ, &mut window) == -1 {
+                    return None;
+                }
+            }
+            Some(WinDim(window.ws_col, window.ws_row))
+        }
+    }
+
+    fn read_byte(&mut self) -> Option<u8> {
+        let mut b: u8 = 0;
+        let n = unsafe { libc::read(self.fd, &mut b as *mut u8 as *mut libc::c_void, 1) };
+        if n == 1 {
+            Some(b)
+        } else {
+            None
+        }
+    }
+
+    fn resized(&mut self) -> bool {
+        RESIZED.swap(false, Ordering::SeqCst)
+    }
+
+    fn enable_raw_mode(&mut self) -> io::Result<()> {
+        let mut term = Termios::from_fd(self.fd)?;
+        let original_setting = term;
+
+        term.c_iflag &= !(libc::BRKINT | libc::ICRNL | libc::INPCK | libc::ISTRIP | libc::IXON);
+        term.c_oflag &= !(libc::OPOST);
+        term.c_cflag |= libc::CS8 | libc::CREAD | libc::CLOCAL;
+        term.c_lflag &= !(libc::ECHO | libc::ICANON | libc::IEXTEN | libc::ISIG);
+
+        term.c_cc[libc::VMIN] = 0;
+        term.c_cc[libc::VTIME] = 1;
+        term.c_lflag = 0;
+
+        termios::cfsetspeed(&mut term, libc::B9600)?;
+        termios::tcsetattr(self.fd, libc::TCSANOW, &term)?;
+        termios::tcflush(self.fd, libc::TCIOFLUSH)?;
+        self.original = Some(original_setting);
+        unsafe {
+            libc::signal(libc::SIGWINCH, on_sigwinch as extern "C" fn(libc::c_int) as libc::sighandler_t);
+        }
+        Ok(())
+    }
+
+    fn disable_raw_mode(&mut self) -> io::Result<()> {
+        match self.original.take() {
+            Some(settings) => termios::tcsetattr(self.fd, libc::TCSANOW, &settings),
+            None => Ok(())
+        }
+    }
+}
+
+impl Write for TermiosTerminal {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.out.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+/// How many reads in a row may find the scripted input empty, before the virtual terminal decides that whatever
+/// is reading is going to wait forever, and panics instead of hanging.
+const MAX_EMPTY_READS: usize = 1000;
+
+#[derive(Clone, Debug, PartialEq)]
+enum ParseState {
+    Ground,
+    Escape,
+    Csi(String),
+    /// an OSC or DCS string, which lasts until BEL or ESC \
+    Str(bool)
+}
+
+/**
+    An in-memory terminal, for testing. Input is scripted with `feed`, and everything written to it is interpreted
+    like a terminal would: printable characters, cursor movement, clearing and colors end up in a grid of cells,
+    that tests can then look at. Other escape sequences (modes, OSC and DCS strings) are consumed and ignored.
+*/
+pub struct VirtualTerminal {
+    width: usize,
+    height: usize,
+    cells: Vec<Cell>,
+    cursor: ViewCursor,
+    style: Style,
+    input: VecDeque<u8>,
+    empty_reads: usize,
+    resized: bool,
+    raw_mode: bool,
+    state: ParseState,
+    utf8: Vec<u8>
+}
+
+impl VirtualTerminal {
+    pub fn new(width: usize, height: usize) -> VirtualTerminal {
+        VirtualTerminal {
+            width,
+            height,
+            cells: vec![Cell::default(); width * height],
+            cursor: ViewCursor::default(),
+            style: Style::default(),
+            input: VecDeque::new(),
+            empty_reads: 0,
+            resized: false,
+            raw_mode: false,
+            state: ParseState::Ground,
+            utf8: vec![]
+        }
+    }
+
+    /// Queues up input, as if it had been typed
+    pub fn feed(&mut self, input: &[u8]) {
+        self.input.extend(input.iter().cloned());
+        self.empty_reads = 0;
+    }
+
+    pub fn has_input(&self) -> bool {
+        !self.input.is_empty()
+    }
+
+    /// Resizes the terminal. Like a real terminal, the contents are kept where they fit.
+    pub fn resize(&mut self, width: usize, height: usize) {
+        let mut cells = vec![Cell::default(); width * height];
+        for row in 0..height.min(self.height) {
+            for col in 0..width.min(self.width) {
+                cells[row * width + col] = self.cells[row * self.width + col].clone();
+            }
+        }
+        self.cells = cells;
+        self.width = width;
+        self.height = height;
+        self.resized = true;
+    }
+
+    pub fn is_raw_mode(&self) -> bool {
+        self.raw_mode
+    }
+
+    pub fn cursor(&self) -> ViewCursor {
+        self.cursor
+    }
+
+    pub fn cell(&self, row: usize, col: usize) -> Option<&Cell> {
+        self.index(row, col).map(|idx| &self.cells[idx])
+    }
+
+    /// The text on a row (1-indexed), with trailing blanks removed
+    pub fn row_text(&self, row: usize) -> String {
+        let text: String = (1..=self.width).filter_map(|col| self.cell(row, col)).map(|c| c.text.as_ref()).collect();
+        text.trim_end().to_string()
+    }
+
+    /// The text on the whole screen, one line per row
+    pub fn contents(&self) -> String {
+        (1..=self.height).map(|row| self.row_text(row)).collect::<Vec<String>>().join("\n")
+    }
+
+    fn index(&self, row: usize, col: usize) -> Option<usize> {
+        if row >= 1 && row <= self.height && col >= 1 && col <= self.width {
+            Some((row - 1) * self.width + col - 1)
+        } else {
+            None
+        }
+    }
+
+    fn clear(&mut self, from: usize, to: usize) {
+        let blank = Cell::blank(self.style);
+        for cell in self.cells[from.min(to)..to].iter_mut() {
+            *cell = blank.clone();
+        }
+    }
+
+    /// Prints a character at the cursor. Wide characters take up two cells, combining marks are added to the
+    /// character before them.
+    fn print(&mut self, ch: char) {
+        let width = ch.width().unwrap_or(1);
+        if width == 0 {
+            if let Some(idx) = self.index(self.cursor.row, self.cursor.col - 1) {
+                self.cells[idx].text.to_mut().push(ch);
+            }
+            return;
+        }
+        if self.cursor.col + width - 1 > self.width {
+            self.cursor.col = 1;
+            self.cursor.row = (self.cursor.row + 1).min(self.height);
+        }
+        if let Some(idx) = self.index(self.cursor.row, self.cursor.col) {
+            let mut buf = [0; 4];
+            self.cells[idx] = Cell::new(ch.encode_utf8(&mut buf), self.style);
+            if width > 1 {
+                self.cells[idx + 1] = Cell::continuation(self.style);
+            }
+        }
+        self.cursor.col += width;
+    }
+
+    fn process(&mut self, b: u8) {
+        match std::mem::replace(&mut self.state, ParseState::Ground) {
+            ParseState::Ground => self.ground(b),
+            ParseState::Escape => {
+                self.state = match b {
+                    b'[' => ParseState::Csi(String::new()),
+                    b']' | b'P' => ParseState::Str(false),
+                    _ => ParseState::Ground
+                }
+            },
+            ParseState::Csi(mut params) => {
+                if (0x40..0x7f).contains(&b) {
+                    self.csi(&params, b);
+                } else {
+                    params.push(b as char);
+                    self.state = ParseState::Csi(params);
+                }
+            },
+            ParseState::Str(after_esc) => {
+                if !(b == 0x07 || (after_esc && b == b'\\')) {
+                    self.state = ParseState::Str(b == 0x1b);
+                }
+            }
+        }
+    }
+
+    fn ground(&mut self, b: u8) {
+        if !self.utf8.is_empty() || b >= 0x80 {
+            self.utf8.push(b);
+            match std::str::from_utf8(&self.utf8) {
+                Ok(s) => {
+                    let ch = s.chars().next().unwrap_or(' ');
+                    self.utf8.clear();
+                    self.print(ch);
+                },
+                Err(e) if e.error_len().is_some() || self.utf8.len() >= 4 => {
+                    self.utf8.clear();
+                    self.print('\u{fffd}');
+                },
+                Err(_) => {}
+            }
+            return;
+        }
+        match b {
+            0x1b => self.state = ParseState::Escape,
+            b'\r' => self.cursor.col = 1,
+            b'\n' => self.cursor.row = (self.cursor.row + 1).min(self.height),
+            0x08 => self.cursor.col = (self.cursor.col - 1).max(1),
+            b if b >= 0x20 && b != 0x7f => self.print(b as char),
+            _ => {}
+        }
+    }
+
+    fn csi(&mut self, params: &str, final_byte: u8) {
+        if params.starts_with('?') {
+            // private modes, like bracketed paste and mouse reporting
+            return;
+        }
+        let numbers: Vec<usize> = params.split(';').map(|p| p.parse::<usize>().unwrap_or(0)).collect();
+        let arg = |i: usize, default: usize| numbers.get(i).cloned().filter(|n| *n != 0).unwrap_or(default);
+        match final_byte {
+            b'H' | b'f' => {
+                self.cursor = ViewCursor { row: arg(0, 1).min(self.height), col: arg(1, 1).min(self.width) };
+            },
+            b'A' => self.cursor.row = self.cursor.row.saturating_sub(arg(0, 1)).max(1),
+            b'B' => self.cursor.row = (self.cursor.row + arg(0, 1)).min(self.height),
+            b'C' => self.cursor.col = (self.cursor.col + arg(0, 1)).min(self.width),
+            b'D' => self.cursor.col = self.cursor.col.saturating_sub(arg(0, 1)).max(1),
+            b'J' => {
+                let at = self.index(self.cursor.row, self.cursor.col.min(self.width)).unwrap_or(0);
+                match numbers.first().cloned().unwrap_or(0) {
+                    0 => self.clear(at, self.cells.len()),
+                    1 => self.clear(0, at + 1),
+                    _ => self.clear(0, self.cells.len())
+                }
+            },
+            b'K' => {
+                let row_start = (self.cursor.row - 1) * self.width;
+                let at = row_start + self.cursor.col.min(self.width + 1) - 1;
+                match numbers.first().cloned().unwrap_or(0) {
+                    0 => self.clear(at, row_start + self.width),
+                    1 => self.clear(row_start, (at + 1).min(row_start + self.width)),
+                    _ => self.clear(row_start, row_start + self.width)
+                }
+            },
+            b'm' => {
+                for n in numbers.iter() {
+                    match *n {
+                        0 => self.style = Style::default(),
+                        39 => self.style.fg = None,
+                        49 => self.style.bg = None,
+                        n @ 30..=37 | n @ 90..=97 => self.style.fg = Color::from_code(n as u8),
+                        n @ 40..=47 | n @ 100..=107 => self.style.bg = Color::from_code(n as u8 - 10),
+                        _ => {}
+                    }
+                }
+            },
+            _ => {}
+        }
+    }
+}
+
+impl TerminalBackend for VirtualTerminal {
+    fn size(&self) -> Option<WinDim> {
+        Some(WinDim(self.width as u16, self.height as u16))
+    }
+
+    fn read_byte(&mut self) -> Option<u8> {
+        let b = self.input.pop_front();
+        if b.is_none() {
+            self.empty_reads += 1;
+            if self.empty_reads > MAX_EMPTY_READS {
+                panic!("Reading input from a virtual terminal that has run out of scripted input");
+            }
+        }
+        b
+    }
+
+    fn resized(&mut self) -> bool {
+        std::mem::replace(&mut self.resized, false)
+    }
+
+    fn enable_raw_mode(&mut self) -> io::Result<()> {
+        self.raw_mode = true;
+        Ok(())
+    }
+
+    fn disable_raw_mode(&mut self) -> io::Result<()> {
+        self.raw_mode = false;
+        Ok(())
+    }
+}
+
+impl Write for VirtualTerminal {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        for b in buf.iter() {
+            self.process(*b);
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -1,18 +1,12 @@
 use crate::comms::observer::{EventListener, Event};
 use crate::data::text_buffer::Textbuffer;
-use libc::{STDOUT_FILENO, STDIN_FILENO, c_int, c_ulong, winsize};
-use std::mem::zeroed;
-
-static TIOCGWINSZ: c_ulong = 0x5413; // Enum value basically, for requesting terminal window size
-
 use std::sync::{Arc, Mutex};
 use std::fmt::Display;
 use std::fmt::Formatter;
 use std::fmt::Error as FmtError;
-use crate::data::text_buffer::TextPosition;
+use crate::data::text_buffer::{TextPosition, Offset, grapheme_width, next_column};
+use unicode_segmentation::UnicodeSegmentation;
 use std::fmt::Error;
-use std::io::stdout;
-use std::io::Write;
 
 use crate::editor::color::{SetColor, Color};
 use std::thread::sleep;
@@ -20,6 +14,8 @@ use std::time::Duration;
 use crate::editor::editor::debug_sleep;
 use std::ops::Range;
 use crate::cmd::Command;
+use crate::editor::screen::{Screen, Style};
+use crate::editor::terminal::Terminal;
 
 const STATUS_TITLE: &'static str = "[status]: ";
 
@@ -32,7 +28,11 @@ pub enum ViewOperations {
     StepLeft,
     StepUp,
     StepDown,
-    LineStart
+    LineStart,
+    EnableBracketedPaste,
+    DisableBracketedPaste,
+    EnableMouse,
+    DisableMouse
 }
 
 impl Display for ViewOperations {
@@ -44,23 +44,15 @@ impl Display for ViewOperations {
             ViewOperations::StepUp => "\x1b[1A",
             ViewOperations::StepDown => "\x1b[1B",
             ViewOperations::LineStart => "\x1b[9D",
+            ViewOperations::EnableBracketedPaste => "\x1b[?2004h",
+            ViewOperations::DisableBracketedPaste => "\x1b[?2004l",
+            ViewOperations::EnableMouse => "\x1b[?1002h\x1b[?1006h",
+            ViewOperations::DisableMouse => "\x1b[?1006l\x1b[?1002l",
         };
         write!(f, "{}", res)
     }
 }
 
-impl ViewOperations {
-    fn as_output(&self) -> &str {
-        match self {
-            ViewOperations::ClearLineRest => "\x1b[0K",
-            ViewOperations::StepRight => "\x1b[1C",
-            ViewOperations::StepLeft => "\x1b[1D",
-            ViewOperations::StepUp => "\x1b[1A",
-            ViewOperations::StepDown => "\x1b[1B",
-            ViewOperations::LineStart => "\x1b[9D",
-        }
-    }
-}
 
 
 
@@ -70,33 +62,9 @@ impl Display for WinDim {
     }
 }
 
-pub struct WinSize {
-    ws_row: u16,
-    ws_col: u16,
-    ws_xpixel: u16,
-    ws_ypixel: u16
-}
 
-impl From<WinSize> for WinDim {
-    fn from(ws: WinSize) -> Self {
-        WinDim(ws.ws_col, ws.ws_row)
-    }
-}
 
-impl WinSize {
-    fn new() -> WinSize {
-        WinSize {
-            ws_row: 0,
-            ws_col: 0,
-            ws_xpixel: 0,
-            ws_ypixel: 0
-        }
-    }
-}
 
-extern "C" {
-    fn ioctl(fd: c_int, request: c_ulong, ...) -> c_int;
-}
 
 pub trait ScreenUpdate {
     fn update(&self);
@@ -130,7 +98,12 @@ pub struct ViewConfig {
     pub bg_color: SetColor,
     pub fg_color: SetColor,
     pub error_color: SetColor,
-    pub stat_line_color: (SetColor, SetColor)
+    pub stat_line_color: (SetColor, SetColor),
+    /// what the bracket matching the one at the cursor is highlighted with
+    pub match_color: SetColor,
+    /// what search matches are highlighted with, and the one the cursor is at
+    pub search_color: SetColor,
+    pub current_search_color: SetColor
 }
 
 impl Default for ViewConfig {
@@ -139,22 +112,27 @@ impl Default for ViewConfig {
             bg_color: SetColor::Background(Color::Blue),
             fg_color: SetColor::Foreground(Color::White),
             error_color: SetColor::Foreground(Color::Red),
-            stat_line_color: (SetColor::Background(Color::Cyan), SetColor::Foreground(Color::Magenta))
+            stat_line_color: (SetColor::Background(Color::Cyan), SetColor::Foreground(Color::Magenta)),
+            match_color: SetColor::Background(Color::Magenta),
+            search_color: SetColor::Background(Color::Yellow),
+            current_search_color: SetColor::Background(Color::Green)
         }
     }
 }
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub struct ViewCursor {
     pub row: usize,
     pub col: usize
 }
 
-impl From<TextPosition> for ViewCursor {
-    fn from(tp: TextPosition) -> Self {
+impl ViewCursor {
+    /// Where text position `tp` in `tb` is shown, counting columns the way the terminal does: wide characters take up
+    /// two columns, combining marks none.
+    pub fn at(tb: &Textbuffer, tp: &TextPosition) -> ViewCursor {
         ViewCursor {
             row: tp.line_index + 1,
-            col: tp.get_line_position() + 1
+            col: tb.display_column(tp.absolute).1 + 1
         }
     }
 }
@@ -172,6 +150,18 @@ impl Display for ViewCursor {
     }
 }
 
+/// The range of lines a view of `height` lines shows, starting at `start` if possible, but moved so that `cursor_line` is in it.
+pub fn fit_line_range(start: usize, height: usize, cursor_line: usize) -> Range<usize> {
+    let start = if cursor_line < start {
+        cursor_line
+    } else if cursor_line >= start + height {
+        cursor_line + 1 - height
+    } else {
+        start
+    };
+    start..(start + height)
+}
+
 pub trait ViewRange {
     fn shift_forward(&mut self, len: usize);
     fn shift_backward(&mut self, len: usize);
@@ -198,7 +188,7 @@ impl Drop for View {
         //      if this prints, any error message that might panic! the application, will be lost from the stdout.
         // \x1b[m ends whatever ansi escape sequence currently written to the terminal,
         // and restores to terminal default
-        print!("\x1b[m {}[2J{}[1;1H", esc as char, esc as char);
+        let _ = write!(self.terminal.lock().unwrap(), "\x1b[m {}[2J{}[1;1H", esc as char, esc as char);
     }
 }
 
@@ -211,12 +201,19 @@ pub struct View {
     pub  top_line: TextPosition,
     pub status_line_position: ViewCursor,
     pub win_size: WinDim,
-    pub view_cfg: ViewConfig
+    pub view_cfg: ViewConfig,
+    pub screen: Screen,
+    terminal: Terminal,
+    /// the highlighted matching bracket: where it is on screen, its style before, and with, the highlight
+    bracket_highlight: Option<(ViewCursor, Style, Style)>,
+    /// the buffer ranges of the highlighted search matches, in order, and which of them is the current one
+    search_highlights: Vec<Range<usize>>,
+    current_search_match: Option<usize>
 }
 
 impl View {
 
-    pub fn new_with_cfg(vcfg: &ViewConfig) -> Option<Self> {
+    pub fn new_with_cfg(vcfg: &ViewConfig, terminal: Terminal) -> Option<Self> {
         let mut v = View {
             view_cursor: ViewCursor{ row: 1, col: 1},
             statline_view_cursor: ViewCursor {row: 1, col: 1},
@@ -226,21 +223,27 @@ impl View {
             top_line: TextPosition::new(),
             win_size: WinDim(0, 0),
             status_line_position: ViewCursor { row: 0, col: 0},
-            view_cfg: vcfg.clone()
+            view_cfg: vcfg.clone(),
+            screen: Screen::new(0, 0),
+            terminal: terminal.clone(),
+            bracket_highlight: None,
+            search_highlights: vec![],
+            current_search_match: None
         };
 
         if let Some(winsize) = v.get_window_size() {
-            v.win_size = WinDim::from(winsize);
+            v.win_size = winsize;
             v.status_line_position.row = v.win_size.1 as usize;
             v.statline_view_cursor = v.status_line_position;
             v.line_range = 0..(v.win_size.1 as usize -1);
+            v.screen = Screen::new(v.win_size.0 as usize, v.win_size.1 as usize);
             Some(v)
         } else {
             None
         }
     }
 
-    pub fn new() -> Option<Self> {
+    pub fn new(terminal: Terminal) -> Option<Self> {
         let mut v = View {
             view_cursor: ViewCursor{ row: 1, col: 1},
             statline_view_cursor: ViewCursor {row: 1, col: 1},
@@ -250,14 +253,20 @@ impl View {
             top_line: TextPosition::new(),
             win_size: WinDim(0, 0),
             status_line_position: ViewCursor { row: 0, col: 0},
-            view_cfg: ViewConfig::default()
+            view_cfg: ViewConfig::default(),
+            screen: Screen::new(0, 0),
+            terminal: terminal.clone(),
+            bracket_highlight: None,
+            search_highlights: vec![],
+            current_search_match: None
         };
 
         if let Some(winsize) = v.get_window_size() {
-            v.win_size = WinDim::from(winsize);
+            v.win_size = winsize;
             v.status_line_position.row = v.win_size.1 as usize;
             v.statline_view_cursor = v.status_line_position;
             v.line_range = 0..(v.win_size.1 as usize -1);
+            v.screen = Screen::new(v.win_size.0 as usize, v.win_size.1 as usize);
             Some(v)
         } else {
             None
@@ -271,127 +280,109 @@ impl View {
     }
 
     pub fn init(&mut self) {
-        let esc = 27u8;
-        print!("{}[2J{}[1;1H", esc as char, esc as char);
-        let mut a = " ".repeat(self.win_size.0 as usize);
-        a.push('\n');
-        a.push('\r');
-        let res = a.repeat(self.win_size.1 as usize - 1);
-        let mut status = " ".repeat(self.win_size.0 as usize);
         let status_title = "[status]: ";
+        self.screen.invalidate();
+        self.screen.reset_style();
+        self.screen.set_color(&self.view_cfg.bg_color);
+        self.screen.fill();
         self.statline_view_cursor.col = status_title.len() + 1;
-        status.replace_range(0..status_title.len(), status_title);
-        print!("{}{}{}{}[1;1H",
-               self.view_cfg.bg_color.colorize(res.as_ref()),
-               self.view_cfg.stat_line_color.1,
-               self.view_cfg.stat_line_color.0.colorize(status.as_ref()),
-               esc as char);
-        stdout().flush();
+        self.paint_statline(status_title, "");
         self.view_cursor = ViewCursor::default();
-        // clear the screen
-        // paint the screen with default colors (or color settings provided via .rc file)
-        // set up status line
-        // paint status line
-    }
-
-    pub fn get_window_size(&self) -> Option<WinSize> {
-        unsafe {
-            let mut window: winsize = zeroed();
-            let mut win_size = WinSize::new();
-            let result = ioctl(STDOUT_FILENO, TIOCGWINSZ, &mut window);
-            if result == -1 {
-                window = zeroed();
-                let result = ioctl(STDIN_FILENO, TIOCGWINSZ, &mut window);
-                if result != -1 {
-                    win_size.ws_ypixel = window.ws_ypixel;
-                    win_size.ws_xpixel = window.ws_xpixel;
-                    win_size.ws_row = window.ws_row;
-                    win_size.ws_col = window.ws_col;
-                    Some(win_size)
-                } else {
-                    None
-                }
-            } else {
-                win_size.ws_ypixel = window.ws_ypixel;
-                win_size.ws_xpixel = window.ws_xpixel;
-                win_size.ws_row = window.ws_row;
-                win_size.ws_col = window.ws_col;
-                Some(win_size)
-            }
-        }
+        self.present(self.view_cursor);
+    }
+
+    pub fn get_window_size(&self) -> Option<WinDim> {
+        self.terminal.lock().unwrap().size()
     }
 
 
     pub fn on_goto(&mut self) {
-        let goto_title = "[goto]: ";
-        self.statline_view_cursor.col = goto_title.len() + 1;
-        print!("{}{}{}{}{}", self.status_line_position, self.view_cfg.stat_line_color.0, self.view_cfg.stat_line_color.1, goto_title, ViewOperations::ClearLineRest);
-        stdout().flush();
+        self.on_prompt("[goto]: ");
     }
 
     pub fn on_open_file(&mut self) {
-        let open_title = "[open]: ";
-        self.statline_view_cursor.col = open_title.len() + 1;
-        print!("{}{}{}{}{}", self.status_line_position, self.view_cfg.stat_line_color.0, self.view_cfg.stat_line_color.1, open_title, ViewOperations::ClearLineRest);
-        stdout().flush();
+        self.on_prompt("[open]: ");
     }
 
     pub fn on_save_file(&mut self) {
-        let open_title = "[save]: ";
-        self.statline_view_cursor.col = open_title.len() + 1;
-        print!("{}{}{}{}{}", self.status_line_position, self.view_cfg.stat_line_color.0, self.view_cfg.stat_line_color.1, open_title, ViewOperations::ClearLineRest);
-        stdout().flush();
+        self.on_prompt("[save]: ");
     }
 
-    pub fn on_enter_command(&mut self) {
-        let title = "[command]: ";
+    pub fn on_prompt(&mut self, title: &str) {
         self.statline_view_cursor.col = title.len() + 1;
-        print!("{}{}{}{}{}", self.status_line_position, self.view_cfg.stat_line_color.0, self.view_cfg.stat_line_color.1, title, ViewOperations::ClearLineRest);
-        stdout().flush();
+        self.paint_statline(title, "");
+        self.present(self.statline_view_cursor);
+    }
+
+    pub fn on_enter_command(&mut self) {
+        self.on_prompt("[command]: ");
     }
 
     pub fn on_statline_error(&mut self, msg: &str) {
-        print!("{}{}{}{}{}{}", self.status_line_position, self.view_cfg.stat_line_color.0, self.view_cfg.error_color, ViewOperations::ClearLineRest, msg, self.view_cursor);
-        stdout().flush();
+        self.screen.reset_style();
+        self.screen.move_to(self.status_line_position);
+        self.screen.set_color(&self.view_cfg.stat_line_color.0);
+        self.screen.set_color(&self.view_cfg.error_color);
+        self.screen.apply(ViewOperations::ClearLineRest);
+        self.screen.put_str(msg);
+        self.present(self.view_cursor);
+    }
+
+    pub fn on_statline_info(&mut self, title: &str, msg: &str) {
+        self.paint_statline(title, msg);
+        self.present(self.view_cursor);
     }
 
     pub fn statline_update_line_number(&mut self, line: usize, col: usize) {
-        let info = line.to_string().chars().chain(":".chars()).chain(col.to_string().chars()).collect::<String>();
-        let sl_pos = self.win_size.0 as usize - info.len()+1;
+        let info = format!("{}:{}", line, col);
         let mut tmp_cursor = self.status_line_position;
-        tmp_cursor.col = sl_pos as usize;
-        print!("{}{}{}{}{}", tmp_cursor, self.view_cfg.stat_line_color.0, self.view_cfg.stat_line_color.1, info, self.view_cursor);
-        stdout().flush();
+        tmp_cursor.col = (self.win_size.0 as usize + 1).saturating_sub(info.len());
+        self.screen.reset_style();
+        self.screen.move_to(tmp_cursor);
+        self.screen.set_color(&self.view_cfg.stat_line_color.0);
+        self.screen.set_color(&self.view_cfg.stat_line_color.1);
+        self.screen.put_str(&info);
+        self.present(self.view_cursor);
     }
 
     pub fn restore_statline(&mut self) {
         let stat_title = "[status]: ";
         self.statline_view_cursor.col = stat_title.len() + 1;
-        print!("{}{}{}{}\x1b[m{}", self.status_line_position, self.view_cfg.stat_line_color.0, self.view_cfg.stat_line_color.1, stat_title.chars().chain(" ".repeat(self.win_size.0 as usize- stat_title.len()).chars()).collect::<String>(), self.view_cursor);
-        stdout().flush();
+        self.paint_statline(stat_title, "");
+        self.present(self.view_cursor);
     }
 
     pub fn write_statline_character(&mut self, ch: char) {
-        print!("{}{}{}{}", self.statline_view_cursor, self.view_cfg.stat_line_color.0, self.view_cfg.stat_line_color.1, ch);
-        stdout().flush();
+        self.screen.reset_style();
+        self.screen.move_to(self.statline_view_cursor);
+        self.screen.set_color(&self.view_cfg.stat_line_color.0);
+        self.screen.set_color(&self.view_cfg.stat_line_color.1);
+        self.screen.put_char(ch);
         self.statline_view_cursor.col += 1;
+        self.present(self.statline_view_cursor);
     }
 
-    pub fn write_statline_line(&self, title: &str, content: &str) {
-        let empty = self.win_size.0 as usize - (title.len() + content.len());
-        print!("{}{}{}{}{}{}{}", self.status_line_position, ViewOperations::ClearLineRest.as_output(), self.view_cfg.stat_line_color.0, self.view_cfg.stat_line_color.1, title, content, " ".repeat(empty));
-        stdout().flush();
+    /// Moves the cursor on the status line, while input is being typed into it
+    pub fn set_statline_cursor(&mut self, pos: ViewCursor) {
+        self.statline_view_cursor = pos;
+        self.present(pos);
+    }
+
+    pub fn write_statline_line(&mut self, title: &str, content: &str) {
+        self.paint_statline(title, content);
+        self.present(self.view_cursor);
     }
 
     pub fn update_statline_with(&mut self, data: &str, cursor_pos: &ViewCursor) {
         self.statline_view_cursor = *cursor_pos;
-        print!("{}{}{}{}{}\r{}", self.status_line_position, ViewOperations::ClearLineRest, self.view_cfg.stat_line_color.0, self.view_cfg.stat_line_color.1, data, cursor_pos);
-        stdout().flush();
+        self.paint_statline(data, "");
+        self.present(*cursor_pos);
     }
 
     pub fn reset(&mut self) {
-        let esc: u8 = 27;
-        print!("{}[2J{}[1;1H", esc as char, esc as char);
+        self.screen.reset_style();
+        self.screen.fill();
+        self.screen.move_to(ViewCursor::default());
         self.view_cursor = ViewCursor::default();
         let dat = self.buffer_ref.lock().unwrap().dump_to_string();
         for c in dat.chars() {
@@ -401,53 +392,70 @@ impl View {
         {
             self.buffer_ref.lock().unwrap().set_textpos(dat.len());
         }
-        // self.view_cursor = ViewCursor { row: pos.get_line_position(), col: pos.line_number + 1 };
         self.update_cursor();
-        // stdout().flush();
     }
 
+    /// Writes a character at the view cursor, in the view's colors. Like the other painting functions
+    /// this only draws into the screen grid, it shows up on the terminal with the next presented frame.
     pub fn write_character(&mut self, ch: char) {
         // TODO: check if the last character was a whitespace, if so, scan the buffer backwards one word, and check if it should be syntax colored
-        if ch == '\n' {
-            self.view_cursor.row += 1;
-            self.view_cursor.col = 1;
-            print!("\r\n");
-            stdout().flush();
-        } else {
-            self.view_cursor.col += 1;
-            print!("{}{}{}", self.view_cfg.bg_color, self.view_cfg.fg_color, ch);
-            stdout().flush();
-        }
+        self.screen.reset_style();
+        self.screen.set_color(&self.view_cfg.bg_color);
+        self.screen.set_color(&self.view_cfg.fg_color);
+        self.write_character_buffered(ch);
     }
 
     #[inline]
     pub fn write_character_buffered(&mut self, ch: char) {
-        if ch == '\n' {
-            self.view_cursor.row += 1;
-            self.view_cursor.col = 1;
-            print!("\r\n");
-        } else {
-            self.view_cursor.col += 1;
-            print!("{}", ch);
+        let mut buf = [0; 4];
+        self.write_grapheme(ch.encode_utf8(&mut buf));
+    }
+
+    /// Writes a grapheme cluster at the view cursor, and moves the view cursor past the columns it takes up.
+    /// A tab is drawn as blanks, up to the next tab stop of the viewed buffer.
+    pub fn write_grapheme(&mut self, g: &str) {
+        self.screen.move_to(self.view_cursor);
+        match g {
+            "\n" | "\r\n" => {
+                self.screen.put_grapheme(g);
+                self.view_cursor.row += 1;
+                self.view_cursor.col = 1;
+            },
+            "\t" => {
+                let tab_width = self.buffer_ref.lock().unwrap().tab_width();
+                let col = next_column(self.view_cursor.col - 1, g, tab_width) + 1;
+                while self.view_cursor.col < col {
+                    self.screen.put_char(' ');
+                    self.view_cursor.col += 1;
+                }
+            },
+            _ => {
+                self.screen.put_grapheme(g);
+                self.view_cursor.col += grapheme_width(g);
+            }
         }
     }
 
     pub fn update_with_line(&mut self, data: &str) {
-        let empty_space = self.win_size.0 as usize - data.len();
         let mut vc = self.view_cursor;
         vc.col = 1;
-        print!("{}{}{}{}{}", vc, ViewOperations::ClearLineRest, self.view_cfg.fg_color, self.view_cfg.bg_color, &data.chars().chain(" ".repeat(empty_space as usize).chars()).collect::<String>());
-        stdout().flush();
+        self.screen.reset_style();
+        self.screen.move_to(vc);
+        self.screen.set_color(&self.view_cfg.fg_color);
+        self.screen.set_color(&self.view_cfg.bg_color);
+        self.screen.apply(ViewOperations::ClearLineRest);
+        self.screen.put_str(data);
+        self.present(self.view_cursor);
     }
 
-    pub fn update_cursor(&self) {
+    pub fn update_cursor(&mut self) {
         let WinDim(x, _y) = self.win_size;
-        let cursor_output_pos = WinDim(x-6, 1);
-        let WinDim(valx, valy) = cursor_output_pos;
-        let vc_pos = ViewCursor {col: valx as usize, row: valy as usize};
-        let vop = ViewOperations::ClearLineRest;
-        print!("{}{}{};{}{}", vc_pos, vop.as_output(), self.view_cursor.col, self.view_cursor.row, self.view_cursor);
-        stdout().flush();
+        let vc_pos = ViewCursor { col: (x as usize).saturating_sub(6), row: 1 };
+        self.screen.reset_style();
+        self.screen.move_to(vc_pos);
+        self.screen.apply(ViewOperations::ClearLineRest);
+        self.screen.put_str(&format!("{};{}", self.view_cursor.col, self.view_cursor.row));
+        self.present(self.view_cursor);
     }
 
     pub fn set_viewed_buf(&mut self, buf: Arc<Mutex<Textbuffer>>) {
@@ -480,8 +488,8 @@ impl View {
 
     }
 
-    pub fn draw_cursor(&self) {
-        print!("{}", self.view_cursor);
+    pub fn draw_cursor(&mut self) {
+        self.present(self.view_cursor);
     }
 
     pub fn scroll_up(&mut self) {
@@ -495,6 +503,51 @@ impl View {
         }
     }
 
+    /// Re-reads the window size and fits the status line and the line range to it, keeping the cursor in view.
+    pub fn on_resize(&mut self) {
+        let size = match self.get_window_size() {
+            Some(ws) => ws,
+            None => return
+        };
+        if size.1 < 2 || size.0 == 0 {
+            return;
+        }
+        self.win_size = size;
+        self.screen.resize(self.win_size.0 as usize, self.win_size.1 as usize);
+        self.status_line_position.row = self.win_size.1 as usize;
+        self.statline_view_cursor.row = self.status_line_position.row;
+        self.scroll_to_cursor();
+    }
+
+    /// Scrolls the view just as far as needed for the line the cursor is on to be in view
+    pub fn scroll_to_cursor(&mut self) {
+        let guard = self.buffer_ref.lock().unwrap();
+        let cursor_line = guard.get_textpos().line_index;
+        self.line_range = fit_line_range(self.line_range.start, self.get_text_area_height(), cursor_line);
+        self.top_line = guard.get_line_start_0_idx(self.line_range.start).unwrap_or_default();
+    }
+
+    /// Whether there are lines below the view to scroll down to
+    pub fn can_scroll_down(&self) -> bool {
+        let line_count = self.buffer_ref.lock().unwrap().line_count();
+        self.line_range.start + self.get_text_area_height() / 2 + 1 < line_count
+    }
+
+    /// Maps a position on screen (1-indexed, like ViewCursor) to the absolute buffer position of the character shown
+    /// there. Clicks to the right of a line end up at the end of that line, clicks below the last line at the end
+    /// of the buffer. Returns None for positions outside of the text area, like the status line.
+    pub fn buffer_position_at(&self, col: usize, row: usize) -> Option<usize> {
+        if row == 0 || col == 0 || row > self.get_text_area_height() {
+            return None;
+        }
+        let line = self.line_range.start + row - 1;
+        let guard = self.buffer_ref.lock().unwrap();
+        match guard.text_position(Offset::LineColumn(line, col - 1)) {
+            Some(tp) => Some(tp.absolute),
+            None => Some(guard.len())
+        }
+    }
+
     pub fn scroll_down(&mut self) {
         let shift = self.get_text_area_height() / 2;
         self.line_range.shift_forward(shift);
@@ -503,7 +556,7 @@ impl View {
 
     pub fn check_at_boundary_cross(&mut self) {
         let tp = self.buffer_ref.lock().unwrap().get_textpos();
-        self.view_cursor = ViewCursor::from(tp.clone());
+        self.view_cursor = ViewCursor::at(&self.buffer_ref.lock().unwrap(), &tp);
         if self.view_cursor.row >= self.line_range.end && tp.line_index >= self.line_range.end {
             let diff = self.view_cursor.row - self.line_range.end;
             let begin = self.line_range.start;
@@ -526,42 +579,117 @@ impl View {
             // TODO: Remove this when you are 1000000000% certain scrolling functionality works. This fucking bullshit took me 2 days to get right.
             // debug_sleep(Some(format!("Moving before range.. abs_begin: {}", self.top_line.line_start_absolute)), Some(2500));
         } else if (self.view_cursor.row) <= self.line_range.start+1 && self.line_range.start == 0 {
-            let line_pos = self.buffer_ref.lock().unwrap().get_textpos();
+            let guard = self.buffer_ref.lock().unwrap();
             // debug_sleep(Some(format!("We are trying to move at the topline: {}", line_pos.clone().absolute)), Some(2500));
-            self.view_cursor = ViewCursor::from(line_pos);
+            self.view_cursor = ViewCursor::at(&guard, &guard.get_textpos());
         } else {
-            self.view_cursor = ViewCursor::from(self.buffer_ref.lock().unwrap().get_textpos());
+            let guard = self.buffer_ref.lock().unwrap();
+            self.view_cursor = ViewCursor::at(&guard, &guard.get_textpos());
             self.view_cursor.row -= self.line_range.start;
         }
     }
 
+    /// Highlights `matches` (buffer ranges, in order) the next time the view is drawn, `current` of them differently
+    pub fn set_search_highlights(&mut self, matches: Vec<Range<usize>>, current: Option<usize>) {
+        self.search_highlights = matches;
+        self.current_search_match = current;
+    }
+
+    pub fn clear_search_highlights(&mut self) {
+        self.set_search_highlights(vec![], None);
+    }
+
+    /// The color the character at buffer position `pos` is highlighted with, if it's a part of a search match
+    fn search_color_at(&self, pos: usize) -> Option<SetColor> {
+        let idx = self.search_highlights.partition_point(|m| m.end <= pos);
+        match self.search_highlights.get(idx) {
+            Some(m) if m.start <= pos && Some(idx) == self.current_search_match => Some(self.view_cfg.current_search_color),
+            Some(m) if m.start <= pos => Some(self.view_cfg.search_color),
+            _ => None
+        }
+    }
+
     pub fn draw_view(&mut self) {
         self.check_at_boundary_cross();
         let tmp = self.view_cursor;
         let abs_begin = self.top_line.line_start_absolute; // and "anchor" into the buffer, so that we know where the top line of the view -> buffer is
-        let line_count = self.win_size.1 - 1;
         let d = {
             let guard = self.buffer_ref.lock().unwrap();
             let line_end_abs = guard.get_line_abs_end_index(self.top_line.line_index + self.line_range.len()+1).unwrap().absolute;
-            let d = guard.get_data_range(abs_begin, line_end_abs);
-            let esc = 27u8;
-            print!("{}[2J{}[1;1H", esc as char, esc as char);
-            let a = " ".repeat(self.win_size.0 as usize * self.win_size.1 as usize);
-            print!("\x1b[2J\x1b[1;1H{}{}[1;1H",
-                   self.view_cfg.bg_color.colorize(a.as_ref()),
-                   esc as char);
-            self.view_cursor = ViewCursor::default();
-            d
+            guard.get_data_range(abs_begin, line_end_abs)
         };
-        print!("{}{}", self.view_cfg.bg_color, self.view_cfg.fg_color);
-        for c in d.chars() {
-            self.write_character_buffered(c);
+        self.screen.reset_style();
+        self.screen.set_color(&self.view_cfg.bg_color);
+        self.screen.fill();
+        self.screen.set_color(&self.view_cfg.fg_color);
+        self.view_cursor = ViewCursor::default();
+        let mut pos = abs_begin;
+        let mut highlight = None;
+        for g in d.graphemes(true) {
+            let color = self.search_color_at(pos);
+            if color != highlight {
+                self.screen.reset_style();
+                self.screen.set_color(&self.view_cfg.bg_color);
+                self.screen.set_color(&self.view_cfg.fg_color);
+                if let Some(color) = &color {
+                    self.screen.set_color(color);
+                }
+                highlight = color;
+            }
+            self.write_grapheme(g);
+            pos += g.chars().count();
         }
-        // self.view_cursor = ViewCursor::from(self.buffer_ref.lock().unwrap().get_textpos());
         self.view_cursor = tmp;
-        // self.fix_range_old();
-        // print!("{}", self.view_cursor);
         self.restore_statline();
     }
+
+    /// Paints the status line: the title and message, and the rest of the line blanked, in the status line colors.
+    fn paint_statline(&mut self, title: &str, msg: &str) {
+        self.screen.reset_style();
+        self.screen.move_to(self.status_line_position);
+        self.screen.set_color(&self.view_cfg.stat_line_color.0);
+        self.screen.set_color(&self.view_cfg.stat_line_color.1);
+        self.screen.apply(ViewOperations::ClearLineRest);
+        self.screen.put_str(title);
+        self.screen.put_str(msg);
+    }
+
+    /// Highlights the bracket matching the one at the cursor (or right before it), if it's in view, and takes the
+    /// highlight off of the one highlighted before. A cell that has been drawn over since is left as it is.
+    fn highlight_matching_bracket(&mut self) {
+        if let Some((pos, before, highlighted)) = self.bracket_highlight.take() {
+            if self.screen.cell(pos.row, pos.col).map(|c| c.style) == Some(highlighted) {
+                self.screen.set_style_at(pos, before);
+            }
+        }
+        let target = {
+            let guard = self.buffer_ref.lock().unwrap();
+            guard.matching_bracket_near(guard.get_textpos().absolute).map(|pos| {
+                let tp = guard.get_text_position_info(pos);
+                ViewCursor::at(&guard, &tp)
+            })
+        };
+        let pos = match target {
+            Some(pos) if self.line_range.contains(&(pos.row - 1)) => {
+                ViewCursor { row: pos.row - self.line_range.start, col: pos.col }
+            },
+            _ => return
+        };
+        if pos.row > self.get_text_area_height() {
+            return;
+        }
+        if let Some(before) = self.screen.cell(pos.row, pos.col).map(|c| c.style) {
+            let highlighted = before.with(&self.view_cfg.match_color);
+            self.screen.set_style_at(pos, highlighted);
+            self.bracket_highlight = Some((pos, before, highlighted));
+        }
+    }
+
+    /// Writes the changes since the last frame to the terminal, and puts the terminal cursor at `cursor`
+    fn present(&mut self, cursor: ViewCursor) {
+        self.highlight_matching_bracket();
+        self.screen.set_cursor(cursor);
+        let _ = self.screen.present(&mut *self.terminal.lock().unwrap());
+    }
 }
 
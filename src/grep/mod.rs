@@ -0,0 +1,228 @@
+use std::fs::read_to_string;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver};
+use std::thread;
+use ignore::WalkBuilder;
+use crate::data::search::Search;
+use crate::data::word::WordChars;
+
+/// A match of a search through a directory tree
+#[derive(Debug, Clone, PartialEq)]
+pub struct GrepMatch {
+    /// the file, as the directory that was searched leads to it
+    pub path: PathBuf,
+    /// 0-indexed line the match is on
+    pub line: usize,
+    /// 0-indexed character column the match starts at
+    pub column: usize,
+    /// the whole line the match is on
+    pub text: String
+}
+
+impl GrepMatch {
+    /// How the match is listed among the results: "path:line:column: text", with the path relative to `root`, and line
+    /// and column counted from 1, like the status line does.
+    pub fn result_line(&self, root: &Path) -> String {
+        format!("{}:{}:{}: {}", relative_path(&self.path, root).display(), self.line + 1, self.column + 1, self.text)
+    }
+}
+
+/// `path` as seen from `root`, which the search started in. If `root` is the file itself, that's its whole path.
+pub fn relative_path<'a>(path: &'a Path, root: &Path) -> &'a Path {
+    path.strip_prefix(root).ok().filter(|p| !p.as_os_str().is_empty()).unwrap_or(path)
+}
+
+/// Every match of `search` in the file at `path`, in order. Lines are searched one at a time, like grep does.
+/// A file that can't be read as UTF-8 text, which binary files can't, has no matches.
+pub fn grep_file(path: &Path, search: &Search, word_chars: &WordChars) -> Vec<GrepMatch> {
+    let contents = match read_to_string(path) {
+        Ok(contents) => contents,
+        Err(_) => return vec![]
+    };
+    contents.lines().enumerate().flat_map(|(line, text)| {
+        search.find_all(text, word_chars).into_iter().map(move |m| GrepMatch {
+            path: path.to_path_buf(),
+            line,
+            column: m.start,
+            text: text.to_string()
+        })
+    }).collect()
+}
+
+/// Calls `f` with every file under `root`, in file name order, until it returns false. Whatever .gitignore and
+/// .ignore files leave out is skipped, and so are hidden files and directories, just like git and ripgrep do.
+/// The ignore files count even if `root` isn't in a git repository.
+pub fn walk_files<F: FnMut(&Path) -> bool>(root: &Path, mut f: F) {
+    let walker = WalkBuilder::new(root)
+        .require_git(false)
+        .sort_by_file_name(|a, b| a.cmp(b))
+        .build();
+    for entry in walker.filter_map(Result::ok) {
+        if entry.file_type().is_some_and(|t| t.is_file()) && !f(entry.path()) {
+            return;
+        }
+    }
+}
+
+/// Calls `f` with every match in the files under `root`, in the order `walk_files` goes through them, until it returns false
+fn walk<F: FnMut(GrepMatch) -> bool>(root: &Path, search: &Search, word_chars: &WordChars, mut f: F) {
+    walk_files(root, |path| grep_file(path, search, word_chars).into_iter().all(&mut f));
+}
+
+/// Every match of `search` in the files under `root`. See `spawn` for searching without waiting for it to finish.
+pub fn grep(root: &Path, search: &Search, word_chars: &WordChars) -> Vec<GrepMatch> {
+    let mut matches = vec![];
+    walk(root, search, word_chars, |m| {
+        matches.push(m);
+        true
+    });
+    matches
+}
+
+/// Searches the files under `root` like `grep` does, but on a thread of its own, sending each match as soon as it's
+/// found. The receiver is disconnected once the search is done, and dropping it stops the search.
+pub fn spawn(root: PathBuf, search: Search, word_chars: WordChars) -> Receiver<GrepMatch> {
+    let (sender, receiver) = channel();
+    thread::spawn(move || {
+        walk(&root, &search, &word_chars, |m| sender.send(m).is_ok());
+    });
+    receiver
+}
+
+/// A line that a replace across files changes, which can be left out of the replace
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hunk {
+    /// 0-indexed line
+    pub line: usize,
+    pub before: String,
+    pub after: String,
+    /// the replacements that make `after` out of `before`, in character positions of the whole file
+    pub replacements: Vec<(Range<usize>, String)>,
+    pub selected: bool
+}
+
+/// What a replace across files changes in one of them, line by line
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileChanges {
+    pub path: PathBuf,
+    pub hunks: Vec<Hunk>,
+    /// the text the changes were found in, which is the only one they can be applied to
+    pub text: String
+}
+
+impl FileChanges {
+    /// The changes that replacing the matches of `search` in `text` with `replacement` makes, with every hunk selected.
+    /// Lines are searched one at a time, like grep does. None if nothing matches.
+    pub fn new(path: PathBuf, text: &str, search: &Search, word_chars: &WordChars, replacement: &str) -> Option<FileChanges> {
+        let mut hunks = vec![];
+        let mut line_start = 0;
+        for (line, raw) in text.split('\n').enumerate() {
+            let before = raw.strip_suffix('\r').unwrap_or(raw);
+            let replacements = search.replacements(before, word_chars, replacement);
+            if !replacements.is_empty() {
+                hunks.push(Hunk {
+                    line,
+                    before: before.to_string(),
+                    after: apply_replacements(before, &replacements),
+                    replacements: replacements.into_iter().map(|(r, with)| (r.start + line_start..r.end + line_start, with)).collect(),
+                    selected: true
+                });
+            }
+            line_start += raw.chars().count() + 1;
+        }
+        if hunks.is_empty() {
+            None
+        } else {
+            Some(FileChanges { path, hunks, text: text.to_string() })
+        }
+    }
+
+    /// The replacements of the selected hunks, in order
+    pub fn selected_replacements(&self) -> Vec<(Range<usize>, String)> {
+        self.hunks.iter().filter(|h| h.selected).flat_map(|h| h.replacements.iter().cloned()).collect()
+    }
+
+    /// The text the changes were found in, with the selected hunks applied
+    pub fn apply(&self) -> String {
+        apply_replacements(&self.text, &self.selected_replacements())
+    }
+}
+
+/// `text` with every range, in characters, replaced by its text. The ranges are in order and don't overlap.
+pub fn apply_replacements(text: &str, replacements: &[(Range<usize>, String)]) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut replacements = replacements.iter().peekable();
+    for (pos, ch) in text.chars().enumerate() {
+        if let Some((range, with)) = replacements.peek() {
+            if pos == range.start {
+                result.push_str(with);
+            }
+            if range.contains(&pos) {
+                if pos + 1 == range.end {
+                    replacements.next();
+                }
+                continue;
+            }
+        }
+        result.push(ch);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{create_dir_all, remove_dir_all, write};
+    use crate::data::search::{Search, SearchOptions};
+    use crate::data::word::WordChars;
+    use crate::grep::{grep, spawn, FileChanges};
+
+    #[test]
+    fn test_grep() {
+        let root = std::env::temp_dir().join(format!("cxedit-grep-{}", std::process::id()));
+        create_dir_all(root.join("src")).unwrap();
+        create_dir_all(root.join("target")).unwrap();
+        create_dir_all(root.join(".hidden")).unwrap();
+        write(root.join(".gitignore"), "target/\n*.log\n").unwrap();
+        write(root.join("src/main.rs"), "fn main() {\n    let needle = 1; // needle\n}\n").unwrap();
+        write(root.join("notes.txt"), "Needle in\na haystack").unwrap();
+        write(root.join("target/out.rs"), "needle").unwrap();
+        write(root.join("build.log"), "needle").unwrap();
+        write(root.join(".hidden/secret"), "needle").unwrap();
+        write(root.join("binary"), [0xffu8, 0xfe, b'n', b'e', b'e', b'd', b'l', b'e']).unwrap();
+
+        let search = Search::new("needle", &SearchOptions::default()).unwrap();
+        let matches = grep(&root, &search, &WordChars::default());
+        let lines: Vec<String> = matches.iter().map(|m| m.result_line(&root)).collect();
+        assert_eq!(vec![
+            "notes.txt:1:1: Needle in".to_string(),
+            "src/main.rs:2:9:     let needle = 1; // needle".to_string(),
+            "src/main.rs:2:24:     let needle = 1; // needle".to_string(),
+        ], lines);
+        assert_eq!((root.join("src/main.rs"), 1, 8), (matches[1].path.clone(), matches[1].line, matches[1].column));
+        // a single file can be searched too, and then it's listed by the path it was given as
+        let file = root.join("src/main.rs");
+        let in_file = grep(&file, &Search::new("main", &SearchOptions::default()).unwrap(), &WordChars::default());
+        assert_eq!(format!("{}:1:4: fn main() {{", file.display()), in_file[0].result_line(&file));
+
+        let streamed: Vec<_> = spawn(root.clone(), search, WordChars::default()).iter().collect();
+        assert_eq!(matches, streamed);
+        remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_file_changes() {
+        let text = "let a = foo(1);\r\nno match\nfoo(2) + foo(3)\n";
+        let opts = SearchOptions { regex: true, ..SearchOptions::default() };
+        let search = Search::new("foo\\((\\d)\\)", &opts).unwrap();
+        let mut changes = FileChanges::new("a.rs".into(), text, &search, &WordChars::default(), "bar($1)").unwrap();
+        let lines: Vec<_> = changes.hunks.iter().map(|h| (h.line, h.before.as_str(), h.after.as_str())).collect();
+        assert_eq!(vec![(0, "let a = foo(1);", "let a = bar(1);"), (2, "foo(2) + foo(3)", "bar(2) + bar(3)")], lines);
+        assert_eq!(vec![(26..32, "bar(2)".to_string()), (35..41, "bar(3)".to_string())], changes.hunks[1].replacements);
+        assert_eq!("let a = bar(1);\r\nno match\nbar(2) + bar(3)\n", changes.apply());
+        changes.hunks[0].selected = false;
+        assert_eq!(2, changes.selected_replacements().len());
+        assert_eq!("let a = foo(1);\r\nno match\nbar(2) + bar(3)\n", changes.apply());
+        assert_eq!(None, FileChanges::new("a.rs".into(), text, &Search::new("baz", &opts).unwrap(), &WordChars::default(), ""));
+    }
+}
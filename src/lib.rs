@@ -8,6 +8,8 @@ pub mod data;
 pub mod editor;
 pub mod comms;
 pub mod cfg;
+pub mod clipboard;
 pub mod ipc;
+pub mod grep;
 
 use serde::{Serialize, Deserialize};
\ No newline at end of file
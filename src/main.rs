@@ -36,8 +36,9 @@ fn main() {
         None
     };
     let mut editor = Editor::new();
-    if let Ok(original) = editor.setup_rawmode(0) {
-        editor.init(Some(original));
+    editor.set_config_path(default_config);
+    if editor.setup_rawmode().is_ok() {
+        editor.init();
         f.and_then(|file_path| {
             editor.open(file_path);
             Some(file_path)
@@ -0,0 +1,453 @@
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use cxedit::editor::editor::Editor;
+use cxedit::editor::terminal::VirtualTerminal;
+use cxedit::editor::view::ViewCursor;
+use cxedit::editor::color::Color;
+
+const WIDTH: usize = 40;
+const HEIGHT: usize = 10;
+
+fn start() -> (Editor, Arc<Mutex<VirtualTerminal>>) {
+    start_with_config(None)
+}
+
+/// Starts an editor that reads its config from `config`, and saves recorded macros to it
+fn start_with_config(config: Option<&Path>) -> (Editor, Arc<Mutex<VirtualTerminal>>) {
+    let vt = Arc::new(Mutex::new(VirtualTerminal::new(WIDTH, HEIGHT)));
+    let mut editor = Editor::with_terminal(vt.clone());
+    if let Some(path) = config {
+        editor.set_config_path(path);
+    }
+    editor.setup_rawmode().unwrap();
+    editor.init();
+    (editor, vt)
+}
+
+fn type_keys(editor: &mut Editor, vt: &Arc<Mutex<VirtualTerminal>>, input: &[u8]) {
+    vt.lock().unwrap().feed(input);
+    editor.process_input();
+}
+
+#[test]
+fn typed_text_is_shown() {
+    let (mut editor, vt) = start();
+    type_keys(&mut editor, &vt, b"hello\rworld");
+    let vt = vt.lock().unwrap();
+    assert_eq!(vt.row_text(1), "hello");
+    assert_eq!(vt.row_text(2), "world");
+    assert_eq!(vt.cursor(), ViewCursor { row: 2, col: 6 });
+    assert!(vt.row_text(HEIGHT).starts_with("[status]:"));
+}
+
+#[test]
+fn arrow_keys_move_the_cursor() {
+    let (mut editor, vt) = start();
+    type_keys(&mut editor, &vt, b"abc\x1b[D\x1b[DX");
+    let vt = vt.lock().unwrap();
+    assert_eq!(vt.row_text(1), "aXbc");
+    assert_eq!(vt.cursor(), ViewCursor { row: 1, col: 3 });
+}
+
+#[test]
+fn wide_characters_move_the_cursor_by_their_width() {
+    let (mut editor, vt) = start();
+    type_keys(&mut editor, &vt, "中文e\u{301}".as_bytes());
+    type_keys(&mut editor, &vt, b"\x1b[D\x1b[D");
+    let vt = vt.lock().unwrap();
+    assert_eq!(vt.row_text(1), "中文e\u{301}");
+    assert_eq!(vt.cursor(), ViewCursor { row: 1, col: 3 });
+}
+
+#[test]
+fn tabs_reach_up_to_the_next_tab_stop() {
+    let (mut editor, vt) = start();
+    type_keys(&mut editor, &vt, b"a\tb\r\x1b[200~ab\tc\x1b[201~");
+    let vt = vt.lock().unwrap();
+    assert_eq!(vt.row_text(1), "a   b");
+    assert_eq!(vt.row_text(2), "ab  c");
+    assert_eq!(vt.cursor(), ViewCursor { row: 2, col: 6 });
+}
+
+#[test]
+fn enter_indents_and_closing_brackets_dedent() {
+    let (mut editor, vt) = start();
+    type_keys(&mut editor, &vt, b"fn f() {\rx;\r}");
+    {
+        let vt = vt.lock().unwrap();
+        assert_eq!(vt.row_text(2), "    x;");
+        assert_eq!(vt.row_text(3), "}");
+    }
+    // undoing the closing bracket brings the indentation back
+    type_keys(&mut editor, &vt, &[26]);
+    assert_eq!(vt.lock().unwrap().cursor(), ViewCursor { row: 3, col: 5 });
+}
+
+#[test]
+fn ctrl_and_alt_arrows_move_by_words() {
+    let (mut editor, vt) = start();
+    type_keys(&mut editor, &vt, b"foo bar.baz qux");
+    // Ctrl+Left twice, then Alt+Left
+    type_keys(&mut editor, &vt, b"\x1b[1;5D\x1b[1;5D\x1b[1;3DX");
+    assert_eq!(vt.lock().unwrap().row_text(1), "foo Xbar.baz qux");
+    // Ctrl+Right, then Ctrl+Alt+Right to the end of "baz"
+    type_keys(&mut editor, &vt, b"\x1b[1;5C\x1b[1;7C");
+    assert_eq!(vt.lock().unwrap().cursor(), ViewCursor { row: 1, col: 12 });
+    // Shift+Alt+Left back to the end of "foo", then Shift+Alt+Right over "Xbar.baz" to the end of "qux"
+    type_keys(&mut editor, &vt, b"\x1b[1;4D");
+    assert_eq!(vt.lock().unwrap().cursor(), ViewCursor { row: 1, col: 3 });
+    type_keys(&mut editor, &vt, b"\x1b[1;4C\x1b[1;4C");
+    assert_eq!(vt.lock().unwrap().cursor(), ViewCursor { row: 1, col: 16 });
+}
+
+#[test]
+fn prompt_input_is_edited_by_characters() {
+    let (mut editor, vt) = start();
+    type_keys(&mut editor, &vt, "1ü2 1ßü2".as_bytes());
+    // "1é2" into the find prompt, then the "é" is replaced by "ü", and "ß" is typed in front of it
+    type_keys(&mut editor, &vt, "\x061é2\x1b[D\x7fü\x1b[Dß\r".as_bytes());
+    assert_eq!(vt.lock().unwrap().cursor(), ViewCursor { row: 1, col: 5 });
+}
+
+#[test]
+fn home_end_and_page_keys_move_the_cursor() {
+    let (mut editor, vt) = start();
+    let lines: Vec<String> = (1..=20).map(|n| format!("line {}", n)).collect();
+    type_keys(&mut editor, &vt, lines.join("\r").as_bytes());
+    let line_at_cursor = |vt: &Arc<Mutex<VirtualTerminal>>| {
+        let vt = vt.lock().unwrap();
+        (vt.row_text(vt.cursor().row), vt.cursor().col)
+    };
+    // Home, then End
+    type_keys(&mut editor, &vt, b"\x1b[H");
+    assert_eq!(("line 20".to_string(), 1), line_at_cursor(&vt));
+    type_keys(&mut editor, &vt, b"\x1b[F");
+    assert_eq!(("line 20".to_string(), 8), line_at_cursor(&vt));
+    // PageUp goes up as many lines as the view shows, and Ctrl+Home to the start of the buffer
+    type_keys(&mut editor, &vt, b"\x1b[5~");
+    assert_eq!(("line 11".to_string(), 8), line_at_cursor(&vt));
+    type_keys(&mut editor, &vt, b"\x1b[1;5H");
+    assert_eq!(ViewCursor { row: 1, col: 1 }, vt.lock().unwrap().cursor());
+    assert_eq!("line 1", vt.lock().unwrap().row_text(1));
+    type_keys(&mut editor, &vt, b"\x1b[6~");
+    assert_eq!(("line 10".to_string(), 1), line_at_cursor(&vt));
+    type_keys(&mut editor, &vt, b"\x1b[1;5F");
+    assert_eq!(("line 20".to_string(), 8), line_at_cursor(&vt));
+}
+
+#[test]
+fn matching_bracket_is_highlighted_and_jumped_to() {
+    let (mut editor, vt) = start();
+    type_keys(&mut editor, &vt, b"f(x[1]) + 2\x1b[D\x1b[D\x1b[D\x1b[D");
+    {
+        let vt = vt.lock().unwrap();
+        assert_eq!(vt.cursor(), ViewCursor { row: 1, col: 8 });
+        assert_eq!(vt.cell(1, 2).unwrap().style.bg, Some(Color::Magenta));
+        assert_ne!(vt.cell(1, 4).unwrap().style.bg, Some(Color::Magenta));
+    }
+    // Alt+]
+    type_keys(&mut editor, &vt, b"\x1b]");
+    let vt = vt.lock().unwrap();
+    assert_eq!(vt.cursor(), ViewCursor { row: 1, col: 2 });
+    assert_eq!(vt.cell(1, 7).unwrap().style.bg, Some(Color::Magenta));
+    assert_ne!(vt.cell(1, 2).unwrap().style.bg, Some(Color::Magenta));
+}
+
+#[test]
+fn search_moves_to_matches_and_wraps_around() {
+    let (mut editor, vt) = start();
+    type_keys(&mut editor, &vt, b"a foo\rb Foo\rfoo c");
+    // Ctrl+F from the end of the buffer wraps around to the first match
+    type_keys(&mut editor, &vt, b"\x06foo\r");
+    {
+        let vt = vt.lock().unwrap();
+        assert_eq!(vt.cursor(), ViewCursor { row: 1, col: 3 });
+        assert_eq!(vt.row_text(HEIGHT), "[find]: match 1 of 3, wrapped around");
+    }
+    // F3 goes on to the next one, Shift+F3 back
+    type_keys(&mut editor, &vt, b"\x1bOR\x1bOR");
+    {
+        let vt = vt.lock().unwrap();
+        assert_eq!(vt.cursor(), ViewCursor { row: 3, col: 1 });
+        assert_eq!(vt.row_text(HEIGHT), "[find]: match 3 of 3");
+    }
+    type_keys(&mut editor, &vt, b"\x1b[1;2R");
+    assert_eq!(vt.lock().unwrap().cursor(), ViewCursor { row: 2, col: 3 });
+    type_keys(&mut editor, &vt, b"\x06bar\r");
+    assert_eq!(vt.lock().unwrap().row_text(HEIGHT), "Pattern not found: bar");
+}
+
+#[test]
+fn search_direction_and_options_are_picked_in_the_prompt() {
+    let (mut editor, vt) = start();
+    type_keys(&mut editor, &vt, b"foo food\rFoo");
+    // Alt+b searches backwards, Alt+c makes the case matter
+    type_keys(&mut editor, &vt, b"\x06\x1bb\x1bcfoo\r");
+    {
+        let vt = vt.lock().unwrap();
+        assert_eq!(vt.cursor(), ViewCursor { row: 1, col: 5 });
+        assert_eq!(vt.row_text(HEIGHT), "[find]: match 2 of 2");
+    }
+    // Alt+w matches whole words only, Alt+r takes the pattern as a regex, and F3 searches the same way again
+    type_keys(&mut editor, &vt, b"\x06\x1bw\x1brfo+\r");
+    {
+        let vt = vt.lock().unwrap();
+        assert_eq!(vt.cursor(), ViewCursor { row: 2, col: 1 });
+        assert_eq!(vt.row_text(HEIGHT), "[find]: match 2 of 2");
+    }
+    type_keys(&mut editor, &vt, b"\x1bOR");
+    {
+        let vt = vt.lock().unwrap();
+        assert_eq!(vt.cursor(), ViewCursor { row: 1, col: 1 });
+        assert_eq!(vt.row_text(HEIGHT), "[find]: match 1 of 2, wrapped around");
+    }
+}
+
+#[test]
+fn incremental_search_cycles_matches_and_escape_goes_back() {
+    let (mut editor, vt) = start();
+    let text = (1..=15).map(|n| format!("line {}", n)).collect::<Vec<_>>().join("\r");
+    type_keys(&mut editor, &vt, text.as_bytes());
+    // Escape goes back to where the cursor was, with the view scrolled as it was
+    type_keys(&mut editor, &vt, b"\x06line 1\x1b[B\x1b");
+    {
+        let vt = vt.lock().unwrap();
+        assert_eq!(vt.cursor(), ViewCursor { row: 9, col: 8 });
+        assert_eq!(vt.row_text(9), "line 15");
+        assert_eq!(vt.cell(9, 1).unwrap().style.bg, Some(Color::Blue));
+    }
+    // "line 1" matches line 1 and lines 10-15, the search wraps around to line 1 and Down picks the ones after it
+    type_keys(&mut editor, &vt, b"\x06line 1\x1b[B\x1b[B\x1b[A\x1b[B\r");
+    let vt = vt.lock().unwrap();
+    assert_eq!(vt.row_text(HEIGHT), "[find]: match 3 of 7");
+    assert_eq!(vt.row_text(vt.cursor().row), "line 11");
+    assert_eq!(vt.cell(1, 1).unwrap().style.bg, Some(Color::Blue));
+}
+
+#[test]
+fn replace_asks_for_each_match_and_undoes_in_one_step() {
+    let (mut editor, vt) = start();
+    type_keys(&mut editor, &vt, b"foo foo\rfoo\rfoo");
+    // y, n, y and then q leaves the last one be
+    type_keys(&mut editor, &vt, b"\x0c%/foo/bar/c\rynyq");
+    {
+        let vt = vt.lock().unwrap();
+        assert_eq!((vt.row_text(1), vt.row_text(2), vt.row_text(3)), ("bar foo".into(), "bar".into(), "foo".into()));
+        assert_eq!(vt.row_text(HEIGHT), "[replaced]: 2 of 4 matches");
+        assert_eq!(vt.cell(1, 1).unwrap().style.bg, Some(Color::Blue));
+    }
+    // a line range, without asking
+    type_keys(&mut editor, &vt, b"\x0c1,2/o/0/\r");
+    {
+        let vt = vt.lock().unwrap();
+        assert_eq!((vt.row_text(1), vt.row_text(2), vt.row_text(3)), ("bar f00".into(), "bar".into(), "foo".into()));
+        assert_eq!(vt.row_text(HEIGHT), "[replaced]: 2 of 2 matches");
+    }
+    type_keys(&mut editor, &vt, &[26, 26]);
+    assert_eq!(vt.lock().unwrap().row_text(1), "foo foo");
+    assert_eq!(vt.lock().unwrap().row_text(2), "foo");
+}
+
+#[test]
+fn grep_lists_matches_and_opens_them() {
+    let root = std::env::temp_dir().join(format!("cxedit-grep-editor-{}", std::process::id()));
+    std::fs::create_dir_all(root.join("ignored")).unwrap();
+    std::fs::write(root.join(".gitignore"), "ignored/\n").unwrap();
+    std::fs::write(root.join("a.txt"), "one\ntwo foo\n").unwrap();
+    std::fs::write(root.join("b.txt"), "  foo\n").unwrap();
+    std::fs::write(root.join("ignored/c.txt"), "foo\n").unwrap();
+    let (mut editor, vt) = start();
+    type_keys(&mut editor, &vt, b"hello");
+    let grep = format!("\x0efoo {}\r", root.display());
+    // escape goes back to the buffer that was being edited
+    type_keys(&mut editor, &vt, grep.as_bytes());
+    type_keys(&mut editor, &vt, b"\x1b");
+    assert_eq!(vt.lock().unwrap().row_text(1), "hello");
+    type_keys(&mut editor, &vt, grep.as_bytes());
+    {
+        let vt = vt.lock().unwrap();
+        assert_eq!((vt.row_text(1), vt.row_text(2), vt.row_text(3)), ("a.txt:2:5: two foo".into(), "b.txt:1:3:   foo".into(), "".into()));
+        assert_eq!(vt.row_text(HEIGHT), "[grep]: 2 matches for foo");
+    }
+    // the results can't be edited, and a file isn't opened over unsaved changes
+    type_keys(&mut editor, &vt, b"x\x1b[B\r");
+    {
+        let vt = vt.lock().unwrap();
+        assert_eq!(vt.row_text(1), "a.txt:2:5: two foo");
+        assert_eq!(vt.row_text(HEIGHT), "Unsaved changes, save them first");
+    }
+    type_keys(&mut editor, &vt, b"\x1b");
+    type_keys(&mut editor, &vt, format!("\x13{}\r", root.join("ignored/hello.txt").display()).as_bytes());
+    // enter opens the match under the cursor
+    type_keys(&mut editor, &vt, grep.as_bytes());
+    type_keys(&mut editor, &vt, b"\x1b[B\r");
+    {
+        let vt = vt.lock().unwrap();
+        assert_eq!(vt.row_text(1), "  foo");
+        assert_eq!(vt.cursor(), ViewCursor { row: 1, col: 3 });
+    }
+    // a file that is open already is switched to as it is, not read again
+    type_keys(&mut editor, &vt, b"bar ");
+    type_keys(&mut editor, &vt, grep.as_bytes());
+    type_keys(&mut editor, &vt, b"\x1b[B\r");
+    {
+        let vt = vt.lock().unwrap();
+        assert_eq!(vt.row_text(1), "  bar foo");
+        assert_eq!(vt.cursor(), ViewCursor { row: 1, col: 3 });
+    }
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn replace_in_files_previews_and_applies_the_picked_changes() {
+    let root = std::env::temp_dir().join(format!("cxrf{}", std::process::id()));
+    std::fs::create_dir_all(&root).unwrap();
+    std::fs::write(root.join("a.txt"), "foo one\nfoo two\n").unwrap();
+    std::fs::write(root.join("b.txt"), "x foo\n").unwrap();
+    std::fs::write(root.join("c.txt"), "foo\n").unwrap();
+    let (mut editor, vt) = start();
+    // a.txt is open with unsaved changes, so it's replaced in where it's open
+    type_keys(&mut editor, &vt, format!("\x0f{}\r!", root.join("a.txt").display()).as_bytes());
+    type_keys(&mut editor, &vt, format!("\x04/foo/bar/ {}\r", root.display()).as_bytes());
+    {
+        let vt = vt.lock().unwrap();
+        let rows: Vec<String> = (1..9).map(|row| vt.row_text(row)).collect();
+        assert_eq!(vec!["a.txt", "[x] 1: -!foo one", "[x] 1: +!bar one", "[x] 2: -foo two", "[x] 2: +bar two",
+                        "b.txt", "[x] 1: -x foo", "[x] 1: +x bar"], rows);
+        assert!(vt.row_text(HEIGHT).starts_with("[replace in files]: 4 of 4 changes in 3"));
+    }
+    // space leaves out the hunk under the cursor, or the whole file on its name
+    type_keys(&mut editor, &vt, b"\x1b[B\x1b[B\x1b[B \x1b[B\x1b[B ");
+    {
+        let vt = vt.lock().unwrap();
+        assert_eq!((vt.row_text(4), vt.row_text(5), vt.row_text(7)), ("[ ] 2: -foo two".into(), "[ ] 2: +bar two".into(), "[ ] 1: -x foo".into()));
+    }
+    type_keys(&mut editor, &vt, b"\r");
+    {
+        let vt = vt.lock().unwrap();
+        assert_eq!((vt.row_text(1), vt.row_text(2)), ("a.txt: 1 replaced, updated in its buffer".into(), "c.txt: 1 replaced, saved".into()));
+        assert_eq!(vt.row_text(HEIGHT), "[replaced]: 2 matches in 2 files");
+    }
+    assert_eq!(std::fs::read_to_string(root.join("a.txt")).unwrap(), "foo one\nfoo two\n");
+    assert_eq!(std::fs::read_to_string(root.join("b.txt")).unwrap(), "x foo\n");
+    assert_eq!(std::fs::read_to_string(root.join("c.txt")).unwrap(), "bar\n");
+    type_keys(&mut editor, &vt, b"\x1b");
+    {
+        let vt = vt.lock().unwrap();
+        assert_eq!((vt.row_text(1), vt.row_text(2)), ("!bar one".into(), "foo two".into()));
+    }
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn replace_in_files_leaves_files_changed_since_the_preview() {
+    let root = std::env::temp_dir().join(format!("cxrfc{}", std::process::id()));
+    std::fs::create_dir_all(&root).unwrap();
+    std::fs::write(root.join("a.txt"), "foo\n").unwrap();
+    std::fs::write(root.join("b.txt"), "foo\n").unwrap();
+    let (mut editor, vt) = start();
+    type_keys(&mut editor, &vt, format!("\x04/foo/bar/ {}\r", root.display()).as_bytes());
+    std::fs::write(root.join("b.txt"), "foo\nfoo\n").unwrap();
+    type_keys(&mut editor, &vt, b"\r");
+    {
+        let vt = vt.lock().unwrap();
+        assert_eq!((vt.row_text(1), vt.row_text(2)), ("a.txt: 1 replaced, saved".into(), "b.txt: not changed, it has changed since".into()));
+        assert_eq!(vt.row_text(HEIGHT), "[replaced]: 1 matches in 1 files");
+    }
+    assert_eq!(std::fs::read_to_string(root.join("a.txt")).unwrap(), "bar\n");
+    assert_eq!(std::fs::read_to_string(root.join("b.txt")).unwrap(), "foo\nfoo\n");
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn recorded_macros_are_saved_to_the_config_path() {
+    let path = std::env::temp_dir().join(format!("cxedit-macros-{}.rc", std::process::id()));
+    let (mut editor, vt) = start_with_config(Some(&path));
+    // Ctrl+r "m" records, and Ctrl+r again stops it
+    type_keys(&mut editor, &vt, b"\x12m\rab\x12");
+    assert!(std::fs::read_to_string(&path).unwrap().contains("\"m\""));
+    drop(editor);
+
+    // an editor reading the same config can play it back, and one without a config doesn't know of it
+    let (mut editor, vt) = start_with_config(Some(&path));
+    type_keys(&mut editor, &vt, b"\x05m\r");
+    assert_eq!(vt.lock().unwrap().row_text(1), "ab");
+    let (mut editor, vt) = start();
+    type_keys(&mut editor, &vt, b"\x05m\r");
+    assert_eq!(vt.lock().unwrap().row_text(1), "");
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn clicks_place_the_cursor_but_not_on_the_status_line() {
+    let (mut editor, vt) = start();
+    type_keys(&mut editor, &vt, b"hello\rworld");
+    type_keys(&mut editor, &vt, b"\x1b[<0;3;1M\x1b[<0;3;1m");
+    assert_eq!(vt.lock().unwrap().cursor(), ViewCursor { row: 1, col: 3 });
+    let status = vt.lock().unwrap().row_text(HEIGHT);
+    type_keys(&mut editor, &vt, format!("\x1b[<0;5;{}M\x1b[<0;5;{}m", HEIGHT, HEIGHT).as_bytes());
+    assert_eq!(vt.lock().unwrap().cursor(), ViewCursor { row: 1, col: 3 });
+    assert_eq!(vt.lock().unwrap().row_text(HEIGHT), status);
+}
+
+#[test]
+fn undo_clears_the_screen_and_reports_on_the_status_line() {
+    let (mut editor, vt) = start();
+    type_keys(&mut editor, &vt, b"hello\rworld");
+    type_keys(&mut editor, &vt, &[26]);
+    let vt = vt.lock().unwrap();
+    assert_eq!(vt.row_text(1), "");
+    assert_eq!(vt.row_text(2), "");
+    assert_eq!(vt.cursor(), ViewCursor { row: 1, col: 1 });
+    assert!(vt.row_text(HEIGHT).starts_with("[undo]:"));
+}
+
+#[test]
+fn redo_works_with_a_config_that_predates_it() {
+    let path = std::env::temp_dir().join(format!("cxedit-old-config-{}.rc", std::process::id()));
+    std::fs::write(&path, r#"{
+        "file_name": "config.rc",
+        "key_bindings": { "CtrlZ": { "Action": "Undo" } },
+        "command_combo_key_bindings": {},
+        "history_size": "Infinite",
+        "bg_color": { "Background": "Blue" },
+        "fg_color": { "Foreground": "White" },
+        "stat_line_color": [{ "Background": "BrightCyan" }, { "Background": "Black" }]
+    }"#).unwrap();
+    let (mut editor, vt) = start_with_config(Some(&path));
+    type_keys(&mut editor, &vt, b"hello");
+    type_keys(&mut editor, &vt, &[26]);
+    assert_eq!(vt.lock().unwrap().row_text(1), "");
+    // Ctrl+y
+    type_keys(&mut editor, &vt, &[25]);
+    assert_eq!(vt.lock().unwrap().row_text(1), "hello");
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn bracketed_paste_is_inserted() {
+    let (mut editor, vt) = start();
+    type_keys(&mut editor, &vt, b"\x1b[200~one\rtwo\x1b[201~");
+    let vt = vt.lock().unwrap();
+    assert_eq!(vt.row_text(1), "one");
+    assert_eq!(vt.row_text(2), "two");
+}
+
+#[test]
+fn resize_moves_the_status_line() {
+    let (mut editor, vt) = start();
+    type_keys(&mut editor, &vt, b"hello");
+    vt.lock().unwrap().resize(30, 6);
+    editor.process_input();
+    let vt = vt.lock().unwrap();
+    assert_eq!(vt.row_text(1), "hello");
+    assert!(vt.row_text(6).starts_with("[status]:"));
+}
+
+#[test]
+fn raw_mode_is_restored_when_the_editor_is_dropped() {
+    let (editor, vt) = start();
+    assert!(vt.lock().unwrap().is_raw_mode());
+    drop(editor);
+    assert!(!vt.lock().unwrap().is_raw_mode());
+}